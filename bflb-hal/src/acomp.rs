@@ -0,0 +1,408 @@
+//! Analog comparators peripheral.
+//!
+//! The chip has two analog comparators (ACOMP0 and ACOMP1) located in the always-on
+//! domain. Each one compares a positive and a negative input and triggers an
+//! interrupt on the edges of its output, both comparators share the `HBN_OUT1`
+//! interrupt. Because they are always on, the comparators can also be used as
+//! wake-up sources from low power modes (PDS and HBN0).
+
+use core::cell::RefCell;
+use core::ptr::addr_of;
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{AON, HBN, PDS};
+use crate::gpio::{Pin, Alternate, PinFunction, PinDrive, PinConfig};
+use crate::adc::{DacA, DacB, Ground};
+use crate::interrupt;
+use crate::sealed::Sealed;
+
+
+/// Definition of an exclusive access to an analog comparator. This comparator need
+/// to be configured in order to obtain a [`Acomp`] structure that is actually usable.
+///
+/// Available comparators: 0, 1.
+pub struct AcompAccess<const NUM: u8>(pub(crate) ());
+
+impl<const NUM: u8> AcompAccess<NUM> {
+
+    /// Initialize this comparator with the given configuration and inputs. The
+    /// comparator is enabled but no edge is triggering interrupt or wake-up, see
+    /// [`Acomp::set_trigger`].
+    pub fn init<P, N>(self, config: &AcompConfig, mut pos: P, mut neg: N) -> Acomp<NUM, P, N>
+    where
+        P: AcompInput,
+        N: AcompInput,
+    {
+
+        let pos_sel = pos.configure();
+        let neg_sel = neg.configure();
+
+        set_trigger_bits::<NUM>(0);
+
+        // Reset analog part of the comparator.
+        AON.acomp_ctrl().modify(|reg| match NUM {
+            0 => reg.acomp0_rstn_ana().clear(),
+            1 => reg.acomp1_rstn_ana().clear(),
+            _ => unreachable!()
+        });
+        AON.acomp_ctrl().modify(|reg| {
+            match NUM {
+                0 => reg.acomp0_rstn_ana().fill(),
+                1 => reg.acomp1_rstn_ana().fill(),
+                _ => unreachable!()
+            }
+            reg.acomp_vref_sel().set(config.reference.min(MAX_REFERENCE) as _);
+        });
+
+        let hyst_p = config.hysteresis_pos as u32;
+        let hyst_n = config.hysteresis_neg as u32;
+        let bias = config.speed as u32;
+        let level = config.scaling as u32;
+
+        match NUM {
+            0 => AON.acomp0_ctrl().modify(|reg| {
+                reg.acomp0_muxen().fill();
+                reg.acomp0_pos_sel().set(pos_sel as _);
+                reg.acomp0_neg_sel().set(neg_sel as _);
+                reg.acomp0_level_sel().set(level);
+                reg.acomp0_bias_prog().set(bias);
+                reg.acomp0_hyst_selp().set(hyst_p);
+                reg.acomp0_hyst_seln().set(hyst_n);
+                reg.acomp0_en().fill();
+            }),
+            1 => AON.acomp1_ctrl().modify(|reg| {
+                reg.acomp1_muxen().fill();
+                reg.acomp1_pos_sel().set(pos_sel as _);
+                reg.acomp1_neg_sel().set(neg_sel as _);
+                reg.acomp1_level_sel().set(level);
+                reg.acomp1_bias_prog().set(bias);
+                reg.acomp1_hyst_selp().set(hyst_p);
+                reg.acomp1_hyst_seln().set(hyst_n);
+                reg.acomp1_en().fill();
+            }),
+            _ => unreachable!()
+        }
+
+        clear_status::<NUM>();
+
+        Acomp {
+            pos,
+            neg,
+        }
+
+    }
+
+}
+
+
+/// An initialized analog comparator.
+pub struct Acomp<const NUM: u8, P, N> {
+    pos: P,
+    neg: N,
+}
+
+impl<const NUM: u8, P, N> Acomp<NUM, P, N> {
+
+    /// Disable this comparator and get back the inputs.
+    pub fn downgrade(self) -> (AcompAccess<NUM>, P, N) {
+        // Drop will be called at the end, effectively disabling the comparator.
+        unsafe { (
+            AcompAccess(()),
+            addr_of!(self.pos).read(),
+            addr_of!(self.neg).read(),
+        ) }
+    }
+
+    /// Return true if the positive input is currently higher than the negative one.
+    pub fn output(&self) -> bool {
+        let mut ctrl = AON.acomp_ctrl().get();
+        match NUM {
+            0 => ctrl.acomp0_out_raw().get() != 0,
+            1 => ctrl.acomp1_out_raw().get() != 0,
+            _ => unreachable!()
+        }
+    }
+
+    /// Set the edges of the output that trigger the interrupt, calling the callback
+    /// set with [`set_callback`](Self::set_callback).
+    ///
+    /// The same edges also wake up the chip from PDS and HBN0 low power modes,
+    /// [`AcompEdge::None`] disables both the interrupt and the wake-up.
+    pub fn set_trigger(&mut self, edge: AcompEdge) {
+        clear_status::<NUM>();
+        set_trigger_bits::<NUM>(edge as u32);
+    }
+
+    /// Get the edges of the output that trigger the interrupt and wake-up.
+    pub fn trigger(&self) -> AcompEdge {
+        let mut irq_mode = HBN.irq_mode().get();
+        let bits = match NUM {
+            0 => irq_mode.irq_acomp0_en().get(),
+            1 => irq_mode.irq_acomp1_en().get(),
+            _ => unreachable!()
+        };
+        match bits {
+            0 => AcompEdge::None,
+            1 => AcompEdge::Rising,
+            2 => AcompEdge::Falling,
+            _ => AcompEdge::Both,
+        }
+    }
+
+    /// Return true if a triggering edge happened since the last time it was cleared.
+    #[inline]
+    pub fn triggered(&self) -> bool {
+        HBN.irq_stat().get().irq_stat().get() & status_mask::<NUM>() != 0
+    }
+
+    /// Clear the triggered status of this comparator.
+    #[inline]
+    pub fn clear_triggered(&mut self) {
+        clear_status::<NUM>();
+    }
+
+    /// Set a callback to be called when a triggering edge happens, edges must be
+    /// selected with [`set_trigger`](Self::set_trigger).
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        critical_section::with(|cs| {
+            CALLBACKS[NUM as usize].borrow_ref_mut(cs).replace(Box::new(callback));
+        });
+        unsafe { interrupt::HBN_OUT1.set_enabled(true) }
+    }
+
+    /// Remove the callback, if any.
+    pub fn clear_callback(&mut self) {
+        critical_section::with(|cs| {
+            CALLBACKS[NUM as usize].borrow_ref_mut(cs).take();
+            // The interrupt is shared by both comparators.
+            if CALLBACKS.iter().all(|callback| callback.borrow_ref(cs).is_none()) {
+                unsafe { interrupt::HBN_OUT1.set_enabled(false) }
+            }
+        });
+    }
+
+}
+
+impl<const NUM: u8, P, N> Drop for Acomp<NUM, P, N> {
+    fn drop(&mut self) {
+        self.clear_callback();
+        set_trigger_bits::<NUM>(0);
+        clear_status::<NUM>();
+        match NUM {
+            0 => AON.acomp0_ctrl().modify(|reg| reg.acomp0_en().clear()),
+            1 => AON.acomp1_ctrl().modify(|reg| reg.acomp1_en().clear()),
+            _ => unreachable!()
+        }
+    }
+}
+
+
+/// A trait implemented on possible comparator inputs, such as GPIO pins.
+pub trait AcompInput: Sealed {
+
+    /// Configure the input and return its channel number.
+    fn configure(&mut self) -> u8;
+
+}
+
+/// Internal macro to implement [`AcompInput`] on [`Pin`].
+macro_rules! impl_acomp_input_pin {
+    ($channel:literal: $pin:literal) => {
+        impl AcompInput for Pin<$pin, Alternate> {
+            #[inline]
+            fn configure(&mut self) -> u8 {
+                let mut cfg = PinConfig::default();
+                cfg.set_function(PinFunction::Analog);
+                cfg.set_drive(PinDrive::Drive0);
+                cfg.set_input_enable(true);
+                cfg.set_smt(true);
+                self.set_config(cfg);
+                $channel
+            }
+        }
+    };
+}
+
+// Comparator channels 0 to 7 are shared with the ADC channels.
+impl_acomp_input_pin!(0: 17);
+impl_acomp_input_pin!(2: 4);
+impl_acomp_input_pin!(3: 11);
+
+/// Internal macro to implement [`AcompInput`] on ZST structures.
+macro_rules! impl_acomp_input_zst {
+    ($channel:literal: $zst:ident) => {
+        impl Sealed for $zst {}
+        impl AcompInput for $zst {
+            #[inline]
+            fn configure(&mut self) -> u8 {
+                $channel
+            }
+        }
+    };
+}
+
+/// Internal 1.2V bandgap reference.
+pub struct Vref1p2;
+/// Reference selected with [`AcompConfig::reference`], scaled by
+/// [`AcompConfig::scaling`]. This is typically used as the threshold.
+pub struct VrefScaled;
+
+impl_acomp_input_zst!(8: DacA);
+impl_acomp_input_zst!(9: DacB);
+impl_acomp_input_zst!(10: Vref1p2);
+impl_acomp_input_zst!(11: VrefScaled);
+impl_acomp_input_zst!(12: Ground);
+
+
+/// Callbacks of each comparator.
+static CALLBACKS: [Mutex<RefCell<Option<AcompCallback>>>; 2] = [
+    Mutex::new(RefCell::new(None)),
+    Mutex::new(RefCell::new(None)),
+];
+
+/// Type alias for a boxed closure used as a comparator callback.
+type AcompCallback = Box<dyn FnMut() + Send>;
+
+/// Interrupt handler for both comparators, on `HBN_OUT1`.
+pub(crate) fn acomp_handler(_code: usize, cs: CriticalSection) {
+    let status = HBN.irq_stat().get().irq_stat().get();
+    acomp_handler_num::<0>(status, cs);
+    acomp_handler_num::<1>(status, cs);
+}
+
+/// Internal handler for a single comparator.
+fn acomp_handler_num<const NUM: u8>(status: u32, cs: CriticalSection) {
+    if status & status_mask::<NUM>() != 0 {
+        clear_status::<NUM>();
+        if let Some(callback) = CALLBACKS[NUM as usize].borrow_ref_mut(cs).as_mut() {
+            callback();
+        }
+    }
+}
+
+/// Internal function to set the raw trigger bits of a comparator, this also enables
+/// the HBN_OUT1 wake-up source of PDS mode while any comparator is triggering.
+fn set_trigger_bits<const NUM: u8>(bits: u32) {
+
+    HBN.irq_mode().modify(|reg| match NUM {
+        0 => reg.irq_acomp0_en().set(bits),
+        1 => reg.irq_acomp1_en().set(bits),
+        _ => unreachable!()
+    });
+
+    let mut irq_mode = HBN.irq_mode().get();
+    let any = irq_mode.irq_acomp0_en().get() != 0 || irq_mode.irq_acomp1_en().get() != 0;
+    PDS.int().modify(|reg| {
+        let src = reg.cr_pds_wakeup_src_en().get();
+        if any {
+            reg.cr_pds_wakeup_src_en().set(src | PDS_WAKEUP_HBN_OUT1);
+        } else {
+            reg.cr_pds_wakeup_src_en().set(src & !PDS_WAKEUP_HBN_OUT1);
+        }
+    });
+
+}
+
+/// Internal function to clear the interrupt status of a comparator.
+fn clear_status<const NUM: u8>() {
+    let mask = status_mask::<NUM>();
+    HBN.irq_clr().modify(|reg| reg.irq_clr().set(mask));
+    HBN.irq_clr().modify(|reg| reg.irq_clr().set(0));
+}
+
+/// Return the bit of a comparator in HBN interrupt status and clear registers.
+#[inline]
+const fn status_mask<const NUM: u8>() -> u32 {
+    match NUM {
+        0 => 1 << 20,
+        1 => 1 << 22,
+        _ => unreachable!()
+    }
+}
+
+/// Bit of `hbn_irq_out[1]` in the PDS wake-up sources.
+const PDS_WAKEUP_HBN_OUT1: u32 = 1 << 2;
+
+
+/// Maximum value of the reference selection.
+pub const MAX_REFERENCE: u8 = 63;
+
+/// Configuration structure for comparator initialization.
+#[derive(Debug, Clone)]
+pub struct AcompConfig {
+    /// Hysteresis applied on rising output.
+    pub hysteresis_pos: AcompHysteresis,
+    /// Hysteresis applied on falling output.
+    pub hysteresis_neg: AcompHysteresis,
+    /// Speed of the comparator, faster comparator consumes more current.
+    pub speed: AcompSpeed,
+    /// Scaling factor of the [`VrefScaled`] input.
+    pub scaling: AcompScaling,
+    /// Reference level of the [`VrefScaled`] input, from 0 to 63. This is shared
+    /// by both comparators.
+    pub reference: u8,
+}
+
+impl AcompConfig {
+
+    /// Create a new basic config with no hysteresis and the slowest speed.
+    pub const fn new(scaling: AcompScaling, reference: u8) -> Self {
+        Self {
+            hysteresis_pos: AcompHysteresis::None,
+            hysteresis_neg: AcompHysteresis::None,
+            speed: AcompSpeed::Slow,
+            scaling,
+            reference,
+        }
+    }
+
+}
+
+/// Edges of a comparator output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AcompEdge {
+    None = 0,
+    Rising = 1,
+    Falling = 2,
+    Both = 3,
+}
+
+/// Hysteresis voltage of a comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AcompHysteresis {
+    None = 0,
+    Mv10 = 1,
+    Mv20 = 2,
+    Mv30 = 3,
+    Mv40 = 4,
+    Mv50 = 5,
+    Mv60 = 6,
+    Mv70 = 7,
+}
+
+/// Speed (bias current) of a comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AcompSpeed {
+    Slow = 0,
+    Medium = 1,
+    Fast = 2,
+}
+
+/// Scaling factor of the reference input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AcompScaling {
+    X0p25 = 0x00,
+    X0p50 = 0x10,
+    X0p75 = 0x20,
+    X1p00 = 0x30,
+}
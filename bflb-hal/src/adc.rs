@@ -1,6 +1,19 @@
 //! Analog to Digital converter peripheral.
 
+use core::cell::RefCell;
+
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+use alloc::boxed::Box;
+
+use critical_section::Mutex;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+use critical_section::CriticalSection;
+
 use crate::arch::bl808::{AON, GPIP};
+use crate::time::{self, Duration};
+
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub mod stream;
 
 
 /// Exclusive access to ADC peripheral.
@@ -8,14 +21,25 @@ pub struct AdcAccess(pub(crate) ());
 
 impl AdcAccess {
 
-    // /// Create a new single channel converter. This can be used later for polling
-    // /// the channel.
-    // pub fn into_single<C>(self, config: &AdcConfig, mut channel: C) -> AdcSingle
-    // where
-    //     C: AdcChannelUntyped
-    // {
-    //     todo!()
-    // }
+    /// Create a new single channel converter. This can be used later for converting
+    /// the channel on demand. Scan and continuous modes of the config are ignored.
+    pub fn into_single<C>(self, config: &AdcConfig, mut channel: C) -> AdcSingle<C>
+    where
+        C: AdcChannelUntyped
+    {
+
+        Self::init(config, false, false);
+
+        let mut builder = ChannelArrayBuilder::default();
+        channel.apply(&mut builder);
+        builder.init_single();
+
+        AdcSingle {
+            channel,
+            resolution: config.resolution,
+        }
+
+    }
 
     /// Create a new scan handle for an array of channels. This can be used later
     /// for polling these channels analogic values.
@@ -24,33 +48,38 @@ impl AdcAccess {
         A: AdcChannelArray,
     {
 
+        Self::init(config, config.scan_conv_mode, config.continuous_conv_mode);
+
+        let mut array_builder = ChannelArrayBuilder::default();
+        array.apply(&mut array_builder);
+        let array_len = array_builder.index as u8;
+        array_builder.init();
+
+        Self::start_conversion();
+
+        AdcScan {
+            array,
+            array_len,
+            resolution: config.resolution,
+        }
+
+    }
+
+    /// Internal function to reset and configure the ADC.
+    fn init(config: &AdcConfig, scan_conv_mode: bool, continuous_conv_mode: bool) {
+
         AON.gpadc_reg_cmd().modify(|reg| reg.gpadc_global_en().clear());
         AON.gpadc_reg_cmd().modify(|reg| reg.gpadc_global_en().fill());
 
         // Reset ADC
         AON.gpadc_reg_cmd().modify(|reg| reg.gpadc_soft_rst().fill());
-        // TODO: Wait 8 NOP
+        time::wait(Duration::from_micros(1));
         AON.gpadc_reg_cmd().modify(|reg| reg.gpadc_soft_rst().clear());
 
-        // Disable interrupts and clear status.
-        GPIP.gpadc_config().modify(|reg| {
-
-            reg.gpadc_fifo_underrun_mask().fill();
-            reg.gpadc_fifo_overrun_mask().fill();
-            reg.gpadc_rdy_mask().fill();
-
-            reg.gpadc_fifo_underrun_clr().fill();
-            reg.gpadc_fifo_overrun_clr().fill();
-            reg.gpadc_rdy_clr().fill();
-
-            reg.gpadc_fifo_clr().fill();
-            reg.gpadc_fifo_thl().clear();
-            reg.gpadc_dma_en().clear();
-
-        });
+        disable_interrupts();
 
         Self::start_conversion();
-        // TODO: Wait 1 ms
+        time::wait(Duration::from_micros(1000));
         Self::stop_conversion();
 
         AON.gpadc_reg_config1().set_with(|reg| {
@@ -61,18 +90,9 @@ impl AdcAccess {
             reg.gpadc_clk_div_ratio().set(config.clock_div as _);
             reg.gpadc_res_sel().set(config.resolution as _);
 
-            if config.scan_conv_mode {
-                reg.gpadc_scan_en().fill();
-                reg.gpadc_clk_ana_inv().fill();
-            }
-
-            if config.continuous_conv_mode {
-                reg.gpadc_cont_conv_en().fill();
-            }
-
         });
 
-        // TODO: Wait 8 NOP
+        time::wait(Duration::from_micros(1));
 
         AON.gpadc_reg_config2().set_with(|reg| {
 
@@ -93,36 +113,53 @@ impl AdcAccess {
             reg.gpadc_neg_gnd().set(config.differential_mode as _);
         });
 
-        // Calibration offset.
+        // The offset is corrected in software, see `measure_offset`.
         AON.gpadc_reg_define().modify(|reg| {
             reg.gpadc_os_cal_data().clear();
         });
 
-        // TODO: Disable interrupts and clear status AGAIN.
+        let offset = measure_offset();
+
+        AON.gpadc_reg_config1().modify(|reg| {
+
+            if scan_conv_mode {
+                reg.gpadc_scan_en().fill();
+                reg.gpadc_clk_ana_inv().fill();
+            }
+
+            if continuous_conv_mode {
+                reg.gpadc_cont_conv_en().fill();
+            }
+
+        });
+
+        disable_interrupts();
 
         AON.gpadc_reg_isr().modify(|reg| {
             reg.gpadc_neg_satur().fill();
             reg.gpadc_pos_satur().fill();
         });
 
-        let mut array_builder = ChannelArrayBuilder::default();
-        array.apply(&mut array_builder);
-        let array_len = array_builder.index as u8;
-        array_builder.init();
-
-        Self::start_conversion();
-
-        AdcScan {
-            array,
-            array_len,
-        }
+        let calibration = AdcCalibration {
+            gain: 2048 - crate::efuse::get_adc_gain_trim().unwrap_or(0) as i32,
+            offset,
+            vref: config.vref,
+            resolution: config.resolution,
+            differential: config.differential_mode,
+        };
+
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            state.resolution = config.resolution;
+            state.calibration = calibration;
+        });
 
     }
 
     /// Start Analog to Digital conversion.
     pub fn start_conversion() {
         Self::stop_conversion();
-        // TODO: Wait 100 us
+        time::wait(Duration::from_micros(100));
         AON.gpadc_reg_cmd().modify(|reg| reg.gpadc_conv_start().fill());
     }
 
@@ -232,10 +269,29 @@ macro_rules! impl_adc_channel_ref_zst {
 
 impl_adc_channel_ref_zst!(12: DacA);
 impl_adc_channel_ref_zst!(13: DacB);
-impl_adc_channel_ref_zst!(14: TsenP);
+/// Internal temperature sensor, measured with [`AdcSingle::celsius`].
+pub struct TsenP;
+impl AdcChannelRef for TsenP {
+    #[inline]
+    fn configure(&mut self) -> u8 {
+        AON.gpadc_reg_config2().modify(|reg| {
+            reg.gpadc_tsext_sel().clear(); // Internal diode
+            reg.gpadc_ts_en().fill();
+        });
+        14
+    }
+}
 impl_adc_channel_ref_zst!(15: TsenN);
 impl_adc_channel_ref_zst!(16: Vref);
-impl_adc_channel_ref_zst!(18: VbatHalf);
+/// Internal half of the battery voltage, see [`AdcCalibration::millivolts`].
+pub struct VbatHalf;
+impl AdcChannelRef for VbatHalf {
+    #[inline]
+    fn configure(&mut self) -> u8 {
+        AON.gpadc_reg_config2().modify(|reg| reg.gpadc_vbat_en().fill());
+        VBAT_HALF_CHANNEL
+    }
+}
 impl_adc_channel_ref_zst!(23: Ground);
 
 
@@ -334,6 +390,10 @@ impl ChannelArrayBuilder {
 
     fn init(self) {
 
+        AON.gpadc_reg_config1().modify(|reg| {
+            reg.gpadc_scan_length().set(self.index.saturating_sub(1) as _)
+        });
+
         AON.gpadc_reg_scn_pos1().set_with(|reg| {
             reg.0 = self.scan_pos as u32
         });
@@ -352,6 +412,119 @@ impl ChannelArrayBuilder {
 
     }
 
+    fn init_single(self) {
+        AON.gpadc_reg_cmd().modify(|reg| {
+            reg.gpadc_pos_sel().set((self.scan_pos & 0x1F) as _);
+            reg.gpadc_neg_sel().set((self.scan_neg & 0x1F) as _);
+        });
+    }
+
+}
+
+
+/// A single channel converter, conversions are started on demand.
+pub struct AdcSingle<C: AdcChannelUntyped> {
+    /// The converted channel.
+    channel: C,
+    /// The resolution, used to decode results.
+    resolution: AdcResolution,
+}
+
+impl<C: AdcChannelUntyped> AdcSingle<C> {
+
+    /// Start a conversion and wait for its result, the raw value of the channel is
+    /// also updated.
+    pub fn convert(&mut self) -> AdcResult {
+
+        GPIP.gpadc_config().modify(|reg| reg.gpadc_fifo_clr().fill());
+        AdcAccess::start_conversion();
+
+        while GPIP.gpadc_config().get().gpadc_fifo_data_count().get() == 0 {
+            core::hint::spin_loop();
+        }
+
+        let result = AdcResult::decode(GPIP.gpadc_dma_rdata().get().gpadc_dma_rdata().get(), self.resolution);
+        AdcAccess::stop_conversion();
+
+        self.channel.set_raw_value(result.value as u32);
+        result
+
+    }
+
+    /// Start a conversion without waiting for its result, the result is given to
+    /// the callback registered with [`set_callback`](Self::set_callback).
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub fn start(&mut self) {
+        GPIP.gpadc_config().modify(|reg| reg.gpadc_fifo_clr().fill());
+        AdcAccess::start_conversion();
+    }
+
+    /// Set a callback to be called with each conversion result from interrupt.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(AdcResult) + Send + 'static,
+    {
+        set_callback(Box::new(callback));
+    }
+
+    /// Remove the callback, if any.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub fn clear_callback(&mut self) {
+        clear_callback();
+    }
+
+    /// Start a conversion and wait for its result in millivolts.
+    pub fn convert_millivolts(&mut self) -> i32 {
+        let result = self.convert();
+        self.calibration().millivolts(result)
+    }
+
+    /// Get the calibration used to convert results to voltages.
+    #[inline]
+    pub fn calibration(&self) -> AdcCalibration {
+        get_calibration()
+    }
+
+    /// Get the converted channel.
+    #[inline]
+    pub fn channel(&self) -> &C {
+        &self.channel
+    }
+
+    /// Stop conversions and return the channel.
+    pub fn finish(self) -> C {
+        AdcAccess::stop_conversion();
+        #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+        clear_callback();
+        self.channel
+    }
+
+}
+
+
+impl AdcSingle<AdcChannel<TsenP, Ground>> {
+
+    /// Measure the internal temperature sensor, in degrees Celsius. The sensor
+    /// is measured with two bias currents, the difference being proportional to the
+    /// absolute temperature.
+    pub fn celsius(&mut self) -> f32 {
+
+        let mut measure = |low: bool| {
+            AON.gpadc_reg_config2().modify(|reg| reg.gpadc_tsvbe_low().set(low as _));
+            let result = self.convert();
+            (result.value as i32) << (16 - self.resolution.bits())
+        };
+
+        let high = measure(false);
+        let low = measure(true);
+        let delta = (high - low).abs();
+
+        let offset = crate::efuse::get_tsen_trim().unwrap_or(TSEN_DEFAULT_OFFSET) as i32;
+        (delta - offset) as f32 / TSEN_SLOPE
+
+    }
+
 }
 
 
@@ -362,6 +535,8 @@ pub struct AdcScan<A: AdcChannelArray> {
     array: A,
     /// The number of channels in the array.
     array_len: u8,
+    /// The resolution, used to decode results.
+    resolution: AdcResolution,
 }
 
 impl<A: AdcChannelArray> AdcScan<A> {
@@ -370,14 +545,15 @@ impl<A: AdcChannelArray> AdcScan<A> {
     pub fn poll(&mut self) -> &A {
 
         while GPIP.gpadc_config().get().gpadc_fifo_data_count().get() < self.array_len as u32 {
-            // TODO: Wait
+            core::hint::spin_loop();
         }
 
         let mut values_buf = [0; 16];
         let values = &mut values_buf[..self.array_len as usize];
 
         for value in &mut values[..] {
-            *value = GPIP.gpadc_dma_rdata().get().gpadc_dma_rdata().get();
+            let word = GPIP.gpadc_dma_rdata().get().gpadc_dma_rdata().get();
+            *value = AdcResult::decode(word, self.resolution).value as u32;
         }
 
         self.array.set_raw_values(values);
@@ -386,14 +562,270 @@ impl<A: AdcChannelArray> AdcScan<A> {
 
     }
 
+    /// Set a callback to be called from interrupt with each conversion result of
+    /// the scan, results should then not be polled.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(AdcResult) + Send + 'static,
+    {
+        set_callback(Box::new(callback));
+    }
+
+    /// Remove the callback, if any.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub fn clear_callback(&mut self) {
+        clear_callback();
+    }
+
+    /// Get the calibration used to convert results to voltages.
+    #[inline]
+    pub fn calibration(&self) -> AdcCalibration {
+        get_calibration()
+    }
+
     pub fn finish(self) -> A {
         AdcAccess::stop_conversion();
+        #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+        clear_callback();
         self.array
     }
 
 }
 
 
+/// A conversion result, as decoded from the ADC FIFO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdcResult {
+    /// Positive channel identifier, see [`AdcChannelRef`].
+    pub pos_channel: u8,
+    /// Negative channel identifier, see [`AdcChannelRef`].
+    pub neg_channel: u8,
+    /// Conversion code, its number of significant bits depends on the resolution.
+    pub value: u16,
+}
+
+impl AdcResult {
+
+    /// Decode a FIFO word: the positive channel is in bits 21..26, the negative
+    /// channel in bits 16..21 and the conversion code in the low half-word.
+    fn decode(word: u32, resolution: AdcResolution) -> Self {
+        Self {
+            pos_channel: ((word >> 21) & 0x1F) as u8,
+            neg_channel: ((word >> 16) & 0x1F) as u8,
+            value: (word as u16) >> (16 - resolution.bits()),
+        }
+    }
+
+}
+
+
+/// Calibration of the ADC, used to convert conversion codes to voltages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdcCalibration {
+    /// Gain correction in 1/2048 units, from eFuse trim.
+    gain: i32,
+    /// Offset in 16-bit codes, measured with both inputs grounded.
+    offset: i32,
+    vref: AdcVref,
+    resolution: AdcResolution,
+    differential: bool,
+}
+
+impl AdcCalibration {
+
+    /// Convert a conversion result to millivolts, gain and offset corrections are
+    /// applied. The internal VBAT/2 channel is scaled back to the battery voltage.
+    pub fn millivolts(&self, result: AdcResult) -> i32 {
+
+        let code = (result.value as i32) << (16 - self.resolution.bits());
+        let vref = match self.vref {
+            AdcVref::V3p2 => 3200,
+            AdcVref::V2p0 => 2000,
+        };
+
+        let millivolts = if self.differential {
+            // Codes are two's complement in differential mode, full scale is ±vref.
+            let code = (code as i16) as i32 - self.offset;
+            code * self.gain / 2048 * vref / 32768
+        } else {
+            let code = (code - self.offset).max(0);
+            code * self.gain / 2048 * vref / 65536
+        };
+
+        if result.pos_channel == VBAT_HALF_CHANNEL {
+            millivolts * 2
+        } else {
+            millivolts
+        }
+
+    }
+
+    /// Gain correction applied, in 1/2048 units.
+    #[inline]
+    pub fn gain(&self) -> i32 {
+        self.gain
+    }
+
+    /// Offset correction applied, in 16-bit codes.
+    #[inline]
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+}
+
+/// Channel identifier of the internal VBAT/2 channel.
+const VBAT_HALF_CHANNEL: u8 = 18;
+/// Channel identifier of the ground.
+const GROUND_CHANNEL: u8 = 23;
+/// Default temperature sensor offset, if not trimmed.
+const TSEN_DEFAULT_OFFSET: u16 = 2042;
+/// Temperature sensor slope, in 16-bit codes per degree.
+const TSEN_SLOPE: f32 = 7.753;
+
+
+/// Type alias for a boxed closure used as a conversion callback.
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+type AdcCallback = Box<dyn FnMut(AdcResult) + Send>;
+
+/// Internal state of the ADC, used by the interrupt handler.
+struct AdcState {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    callback: Option<AdcCallback>,
+    resolution: AdcResolution,
+    calibration: AdcCalibration,
+}
+
+static STATE: Mutex<RefCell<AdcState>> = Mutex::new(RefCell::new(AdcState {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    callback: None,
+    resolution: AdcResolution::Resolution16,
+    calibration: AdcCalibration {
+        gain: 2048,
+        offset: 0,
+        vref: AdcVref::V3p2,
+        resolution: AdcResolution::Resolution16,
+        differential: false,
+    },
+}));
+
+/// Internal function to get the current calibration.
+fn get_calibration() -> AdcCalibration {
+    critical_section::with(|cs| STATE.borrow_ref(cs).calibration)
+}
+
+/// Internal function to measure the offset of the ADC with both inputs grounded,
+/// this is done in single conversion mode and the average is returned in 16-bit
+/// codes.
+fn measure_offset() -> i32 {
+
+    const SAMPLES: i32 = 4;
+
+    AON.gpadc_reg_cmd().modify(|reg| {
+        reg.gpadc_pos_sel().set(GROUND_CHANNEL as _);
+        reg.gpadc_neg_sel().set(GROUND_CHANNEL as _);
+    });
+
+    let mut sum = 0;
+    for _ in 0..SAMPLES {
+
+        GPIP.gpadc_config().modify(|reg| reg.gpadc_fifo_clr().fill());
+        AdcAccess::start_conversion();
+
+        while GPIP.gpadc_config().get().gpadc_fifo_data_count().get() == 0 {
+            core::hint::spin_loop();
+        }
+
+        let word = GPIP.gpadc_dma_rdata().get().gpadc_dma_rdata().get();
+        sum += AdcResult::decode(word, AdcResolution::Resolution16).value as i32;
+
+    }
+
+    AdcAccess::stop_conversion();
+    sum / SAMPLES
+
+}
+
+/// Internal function to mask all interrupts and clear the FIFO and status.
+fn disable_interrupts() {
+
+    GPIP.gpadc_config().modify(|reg| {
+
+        reg.gpadc_fifo_underrun_mask().fill();
+        reg.gpadc_fifo_overrun_mask().fill();
+        reg.gpadc_rdy_mask().fill();
+
+        reg.gpadc_fifo_underrun_clr().fill();
+        reg.gpadc_fifo_overrun_clr().fill();
+        reg.gpadc_rdy_clr().fill();
+
+        reg.gpadc_fifo_clr().fill();
+        reg.gpadc_fifo_thl().clear();
+        reg.gpadc_dma_en().clear();
+
+    });
+
+    GPIP.gpadc_config().modify(|reg| {
+        reg.gpadc_fifo_underrun_clr().clear();
+        reg.gpadc_fifo_overrun_clr().clear();
+        reg.gpadc_rdy_clr().clear();
+    });
+
+}
+
+/// Internal function to set the callback and unmask the ready interrupt.
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+fn set_callback(callback: AdcCallback) {
+    critical_section::with(|cs| {
+        STATE.borrow_ref_mut(cs).callback = Some(callback);
+    });
+    GPIP.gpadc_config().modify(|reg| reg.gpadc_rdy_mask().clear());
+    unsafe { crate::interrupt::GPADC_DMA.set_enabled(true) }
+}
+
+/// Internal function to remove the callback and mask the ready interrupt.
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+fn clear_callback() {
+    unsafe { crate::interrupt::GPADC_DMA.set_enabled(false) }
+    GPIP.gpadc_config().modify(|reg| reg.gpadc_rdy_mask().fill());
+    critical_section::with(|cs| {
+        STATE.borrow_ref_mut(cs).callback.take();
+    });
+}
+
+/// Interrupt handler for ADC ready interrupt, the FIFO is drained to the callback.
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub(crate) fn gpadc_handler(_code: usize, cs: CriticalSection) {
+
+    // The callback is taken out of the state while called, so the state is not
+    // borrowed meanwhile.
+    let (resolution, mut callback) = {
+        let mut state = STATE.borrow_ref_mut(cs);
+        (state.resolution, state.callback.take())
+    };
+
+    while GPIP.gpadc_config().get().gpadc_fifo_data_count().get() != 0 {
+        let word = GPIP.gpadc_dma_rdata().get().gpadc_dma_rdata().get();
+        if let Some(callback) = callback.as_mut() {
+            callback(AdcResult::decode(word, resolution));
+        }
+    }
+
+    // Put back the callback, unless replaced meanwhile.
+    if let Some(callback) = callback {
+        let mut state = STATE.borrow_ref_mut(cs);
+        if state.callback.is_none() {
+            state.callback = Some(callback);
+        }
+    }
+
+    GPIP.gpadc_config().modify(|reg| reg.gpadc_rdy_clr().fill());
+    GPIP.gpadc_config().modify(|reg| reg.gpadc_rdy_clr().clear());
+
+}
+
+
 #[derive(Debug, Clone)]
 pub struct AdcConfig {
     pub clock_div: AdcClockDiv,
@@ -439,6 +871,19 @@ pub enum AdcResolution {
     Resolution16 = 4,
 }
 
+impl AdcResolution {
+
+    /// Number of significant bits of conversion codes.
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::Resolution12 => 12,
+            Self::Resolution14 => 14,
+            Self::Resolution16 => 16,
+        }
+    }
+
+}
+
 /// ADC voltage reference definition.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -0,0 +1,400 @@
+//! Continuous ADC sampling into a DMA ring buffer.
+//!
+//! The scan is converted continuously and results are moved by a DMA channel into
+//! the two halves of a ring buffer, alternatively. Each time a half is filled, the
+//! next half is immediately armed and the callback is called with the filled half.
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{AON, GPIP};
+use crate::cache::{CacheAligned, clean_invalidate_data_range, invalidate_data_range};
+use crate::dma::{self, DmaAccess, DmaInterruptSupport, DmaSrcEndpoint, DmaDstEndpoint,
+    DmaEndpointConfig, DmaPeripheral, DmaDataWidth, DmaBurstSize, DmaIncrement};
+use crate::clock;
+
+use super::{AdcAccess, AdcScan, AdcChannelArray, AdcResult, AdcResolution};
+
+
+/// Maximum number of samples in each half of the ring buffer.
+const MAX_HALF_LEN: usize = 4064;
+
+
+impl<A: AdcChannelArray> AdcScan<A> {
+
+    /// Start continuous conversion of this scan, results are moved into the given
+    /// ring buffer by the given DMA channel. The callback is called from interrupt
+    /// each time a half of the ring buffer is filled, it must process the block
+    /// before the other half is filled.
+    ///
+    /// The ring buffer must have an even length, each half being at most 4064
+    /// samples. The analog clock divider is changed to approach the configured
+    /// sample rate, see [`AdcStream::sample_rate`] for the actual rate.
+    pub fn into_stream<const PORT: u8, const CHANNEL: u8, F>(self,
+        config: &AdcStreamConfig,
+        dma: DmaAccess<PORT, CHANNEL>,
+        buffer: Box<CacheAligned<[u32]>>,
+        callback: F,
+    ) -> AdcStream<A, PORT, CHANNEL>
+    where
+        DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+        F: FnMut(AdcBlock<'_>) + Send + 'static,
+    {
+
+        assert!(!buffer.is_empty() && buffer.len().is_multiple_of(2), "ring buffer length must be even");
+        assert!(buffer.len() / 2 <= MAX_HALF_LEN, "ring buffer half is too long");
+
+        AdcAccess::stop_conversion();
+
+        // Choose the analog clock divider for the requested rate.
+        let mut config1 = AON.gpadc_reg_config1().get();
+        let clock_div = clock_div_ratio(config1.gpadc_clk_div_ratio().get());
+        let cycles = clock_div * conversion_cycles(self.resolution) * self.array_len as u32;
+        let source_freq = clock::analog::get_adc_freq() * clock::analog::get_adc_div();
+        let div = (source_freq / (cycles * config.sample_rate.max(1))).clamp(1, 64);
+        unsafe { clock::analog::setup_adc(clock::analog::get_adc_sel(), div, true); }
+
+        AON.gpadc_reg_config1().modify(|reg| {
+            reg.gpadc_scan_en().fill();
+            reg.gpadc_clk_ana_inv().fill();
+            reg.gpadc_cont_conv_en().fill();
+        });
+
+        GPIP.gpadc_config().modify(|reg| {
+            reg.gpadc_fifo_clr().fill();
+            reg.gpadc_fifo_overrun_clr().fill();
+        });
+        GPIP.gpadc_config().modify(|reg| {
+            reg.gpadc_fifo_overrun_clr().clear();
+            reg.gpadc_dma_en().fill();
+        });
+
+        critical_section::with(|cs| {
+
+            let mut state = STREAM.borrow_ref_mut(cs);
+            state.buffer = Some(buffer);
+            state.callback = Some(Box::new(callback));
+            state.resolution = self.resolution;
+            state.running = true;
+            state.overruns = 0;
+            drop(state);
+
+            arm(dma, AdcHalf::First, cs);
+
+        });
+
+        AdcAccess::start_conversion();
+
+        AdcStream {
+            scan: Some(self),
+            _dma: PhantomData,
+        }
+
+    }
+
+}
+
+
+/// A scan being continuously converted into a ring buffer, see
+/// [`AdcScan::into_stream`]. Conversion is stopped when dropped.
+pub struct AdcStream<A: AdcChannelArray, const PORT: u8, const CHANNEL: u8>
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+    /// The scan, only taken when stopping.
+    scan: Option<AdcScan<A>>,
+    /// The DMA channel is owned by the running transfer.
+    _dma: PhantomData<DmaAccess<PORT, CHANNEL>>,
+}
+
+impl<A: AdcChannelArray, const PORT: u8, const CHANNEL: u8> AdcStream<A, PORT, CHANNEL>
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+
+    /// Return the actual sample rate of the scan, each sample being a conversion of
+    /// the whole scan.
+    pub fn sample_rate(&self) -> u32 {
+        let scan = self.scan.as_ref().unwrap();
+        let mut config1 = AON.gpadc_reg_config1().get();
+        let clock_div = clock_div_ratio(config1.gpadc_clk_div_ratio().get());
+        let cycles = clock_div * conversion_cycles(scan.resolution) * scan.array_len as u32;
+        clock::analog::get_adc_freq() / cycles
+    }
+
+    /// Return the number of FIFO overruns since the stream started, samples have been
+    /// lost for each of them.
+    pub fn overruns(&self) -> u32 {
+        critical_section::with(|cs| STREAM.borrow_ref(cs).overruns)
+    }
+
+    /// Stop continuous conversion and get back the scan, the DMA channel and the
+    /// ring buffer.
+    pub fn stop(mut self) -> (AdcScan<A>, DmaAccess<PORT, CHANNEL>, Box<CacheAligned<[u32]>>) {
+        let buffer = self.disable().unwrap();
+        (self.scan.take().unwrap(), DmaAccess(()), buffer)
+    }
+
+    /// Internal function to stop conversion and DMA, returning the ring buffer if
+    /// not already stopped.
+    fn disable(&mut self) -> Option<Box<CacheAligned<[u32]>>> {
+
+        AdcAccess::stop_conversion();
+        dma::abort_callback(PORT, CHANNEL);
+
+        GPIP.gpadc_config().modify(|reg| {
+            reg.gpadc_dma_en().clear();
+            reg.gpadc_fifo_clr().fill();
+        });
+
+        AON.gpadc_reg_config1().modify(|reg| reg.gpadc_cont_conv_en().clear());
+
+        critical_section::with(|cs| {
+            let mut state = STREAM.borrow_ref_mut(cs);
+            state.running = false;
+            state.callback.take();
+            let buffer = state.buffer.take()?;
+            // Drop lines that could have been speculatively loaded.
+            unsafe { invalidate_data_range(buffer.as_ptr() as usize, buffer.len() * 4) }
+            Some(buffer)
+        })
+
+    }
+
+}
+
+impl<A: AdcChannelArray, const PORT: u8, const CHANNEL: u8> Drop for AdcStream<A, PORT, CHANNEL>
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+    fn drop(&mut self) {
+        if self.scan.is_some() {
+            self.disable();
+        }
+    }
+}
+
+
+/// A block of samples delivered to the stream callback.
+pub struct AdcBlock<'a> {
+    words: &'a [u32],
+    resolution: AdcResolution,
+    half: AdcHalf,
+    overrun: bool,
+}
+
+impl<'a> AdcBlock<'a> {
+
+    /// The half of the ring buffer containing this block.
+    #[inline]
+    pub fn half(&self) -> AdcHalf {
+        self.half
+    }
+
+    /// Return true if a FIFO overrun happened while filling this block, some samples
+    /// have been lost.
+    #[inline]
+    pub fn overrun(&self) -> bool {
+        self.overrun
+    }
+
+    /// Raw FIFO words of this block.
+    #[inline]
+    pub fn raw(&self) -> &'a [u32] {
+        self.words
+    }
+
+    /// Iterate over decoded results of this block.
+    pub fn iter(&self) -> impl Iterator<Item = AdcResult> + 'a {
+        let resolution = self.resolution;
+        self.words.iter().map(move |&word| AdcResult::decode(word, resolution))
+    }
+
+}
+
+/// Half of the ring buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdcHalf {
+    First = 0,
+    Second = 1,
+}
+
+impl AdcHalf {
+    #[inline]
+    fn next(self) -> Self {
+        match self {
+            Self::First => Self::Second,
+            Self::Second => Self::First,
+        }
+    }
+}
+
+/// Configuration of a continuous stream.
+#[derive(Debug, Clone)]
+pub struct AdcStreamConfig {
+    /// Approximate sample rate of the whole scan, in hertz.
+    pub sample_rate: u32,
+}
+
+impl AdcStreamConfig {
+
+    /// Create a new config with the given sample rate.
+    pub const fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+
+}
+
+
+/// Type alias for a boxed closure used as a stream callback.
+type StreamCallback = Box<dyn FnMut(AdcBlock<'_>) + Send>;
+
+/// Internal state of the stream, shared with the DMA callback.
+struct StreamState {
+    buffer: Option<Box<CacheAligned<[u32]>>>,
+    callback: Option<StreamCallback>,
+    resolution: AdcResolution,
+    running: bool,
+    overruns: u32,
+}
+
+static STREAM: Mutex<RefCell<StreamState>> = Mutex::new(RefCell::new(StreamState {
+    buffer: None,
+    callback: None,
+    resolution: AdcResolution::Resolution16,
+    running: false,
+    overruns: 0,
+}));
+
+/// Internal function to start the transfer of the given half.
+fn arm<const PORT: u8, const CHANNEL: u8>(access: DmaAccess<PORT, CHANNEL>, half: AdcHalf, cs: CriticalSection)
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+
+    let state = STREAM.borrow_ref(cs);
+    let buffer = state.buffer.as_ref().unwrap();
+    let len = buffer.len() / 2;
+    let segment = StreamSegment {
+        addr: buffer.as_ptr() as usize + half as usize * len * 4,
+        len,
+    };
+    drop(state);
+
+    access.into_transfer(StreamFifo, segment).wait_callback(move |_, _, access| {
+        critical_section::with(|cs| complete(access, half, cs));
+    });
+
+}
+
+/// Internal function called from DMA interrupt when a half is filled.
+fn complete<const PORT: u8, const CHANNEL: u8>(access: DmaAccess<PORT, CHANNEL>, half: AdcHalf, cs: CriticalSection)
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+
+    if !STREAM.borrow_ref(cs).running {
+        return;
+    }
+
+    // Arm the next half first to keep up with the FIFO.
+    arm(access, half.next(), cs);
+
+    let overrun = GPIP.gpadc_config().get().gpadc_fifo_overrun().get() != 0;
+    if overrun {
+        GPIP.gpadc_config().modify(|reg| reg.gpadc_fifo_overrun_clr().fill());
+        GPIP.gpadc_config().modify(|reg| reg.gpadc_fifo_overrun_clr().clear());
+    }
+
+    let mut state = STREAM.borrow_ref_mut(cs);
+    let state = &mut *state;
+    state.overruns += overrun as u32;
+
+    let buffer = state.buffer.as_ref().unwrap();
+    let len = buffer.len() / 2;
+    let words = &buffer[half as usize * len..][..len];
+
+    if let Some(callback) = state.callback.as_mut() {
+        callback(AdcBlock {
+            words,
+            resolution: state.resolution,
+            half,
+            overrun,
+        });
+    }
+
+}
+
+
+/// Internal DMA source endpoint for the ADC FIFO.
+struct StreamFifo;
+
+impl DmaSrcEndpoint for StreamFifo {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        DmaEndpointConfig {
+            peripheral: Some(DmaPeripheral::AdcRx),
+            data_width: DmaDataWidth::Word,
+            burst_size: DmaBurstSize::Incr1,
+            increment: DmaIncrement::Const,
+            addr: GPIP.gpadc_dma_rdata().0 as _,
+        }
+    }
+
+}
+
+/// Internal DMA destination endpoint for a half of the ring buffer, the ring buffer
+/// is cache aligned and the half is not accessed by the CPU while transferring.
+struct StreamSegment {
+    addr: usize,
+    len: usize,
+}
+
+impl DmaDstEndpoint for StreamSegment {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { clean_invalidate_data_range(self.addr, self.len * 4) }
+        DmaEndpointConfig {
+            peripheral: None,
+            data_width: DmaDataWidth::Word,
+            burst_size: DmaBurstSize::Incr1,
+            increment: DmaIncrement::Incr(self.len),
+            addr: self.addr,
+        }
+    }
+
+    fn close(&mut self) {
+        // Drop lines that could have been speculatively loaded during the transfer.
+        unsafe { invalidate_data_range(self.addr, self.len * 4) }
+    }
+
+}
+
+
+/// Internal function to get the division ratio from the config register value.
+fn clock_div_ratio(value: u32) -> u32 {
+    match value {
+        1 => 4,
+        2 => 8,
+        3 => 12,
+        4 => 16,
+        5 => 20,
+        6 => 24,
+        _ => 32,
+    }
+}
+
+/// Internal function to get the approximate number of ADC clock cycles for a
+/// conversion of one channel. Higher resolutions are obtained by oversampling, each
+/// two more bits requiring four times more conversions.
+fn conversion_cycles(resolution: AdcResolution) -> u32 {
+    match resolution {
+        AdcResolution::Resolution12 => 16,
+        AdcResolution::Resolution14 => 64,
+        AdcResolution::Resolution16 => 256,
+    }
+}
@@ -276,9 +276,13 @@ embedded_util::reg! {
         [00..01] acomp0_en,
         [04..07] acomp0_hyst_seln,
         [07..10] acomp0_hyst_selp,
+        /// Bias current, higher is faster.
         [10..12] acomp0_bias_prog,
+        /// Scaling factor of reference channel 11 (0x00: 0.25 to 0x30: 1).
         [12..18] acomp0_level_sel,
+        /// 0-7: ADC channels, 8: DAC A, 9: DAC B, 10: 1.2V, 11: scaled reference, 12: ground.
         [18..22] acomp0_neg_sel,
+        /// Same channels as `acomp0_neg_sel`.
         [22..26] acomp0_pos_sel,
         [26..27] acomp0_muxen,
     }
@@ -289,9 +293,13 @@ embedded_util::reg! {
         [00..01] acomp1_en,
         [04..07] acomp1_hyst_seln,
         [07..10] acomp1_hyst_selp,
+        /// Bias current, higher is faster.
         [10..12] acomp1_bias_prog,
+        /// Scaling factor of reference channel 11 (0x00: 0.25 to 0x30: 1).
         [12..18] acomp1_level_sel,
+        /// 0-7: ADC channels, 8: DAC A, 9: DAC B, 10: 1.2V, 11: scaled reference, 12: ground.
         [18..22] acomp1_neg_sel,
+        /// Same channels as `acomp1_neg_sel`.
         [22..26] acomp1_pos_sel,
         [26..27] acomp1_muxen,
     }
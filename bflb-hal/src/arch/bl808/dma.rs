@@ -216,6 +216,7 @@ impl DmaBitField {
 
 /// Represent a DMA Linked-List-Item. This is equivalent to the first 4 words
 /// of the [`DmaChannel`] MMIO structure.
+#[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct DmaChannelLli {
     /// Source address of the transfer.
@@ -0,0 +1,112 @@
+//! DMA2D peripheral, only accessible from D0.
+//!
+//! The port registers are the same as regular DMA ports, channels add a source and
+//! a destination 2D addressing: after `x_cnt` transfers the address is incremented by
+//! the Y increment instead of the X increment, this is repeated `y_cnt` times for the
+//! source. Increments are signed and expressed in bytes.
+
+use super::dma::{DmaBitField, DmaConfig, DmaChannelControl, DmaChannelConfig};
+
+
+embedded_util::mmio! {
+
+    pub struct Dma2d {
+        /// Status of the DMA interrupt after masking.
+        [0x000] ro int_status: DmaBitField,
+        /// Interrupt terminal count request status, after masking.
+        [0x004] ro int_tc_status: DmaBitField,
+        /// Terminal count request clear.
+        [0x008] wo int_tc_clear: DmaBitField,
+        /// Interrupt error status, after masking.
+        [0x00C] ro int_error_status: DmaBitField,
+        /// Interrupt error clear.
+        [0x010] wo int_error_clear: DmaBitField,
+        /// Status of the terminal count interrupt prior to masking.
+        [0x014] ro raw_int_tc_status: DmaBitField,
+        /// Status of the error interrupt prior to masking.
+        [0x018] ro raw_int_error_status: DmaBitField,
+        /// Channel enable status.
+        [0x01C] ro channel_enable_status: DmaBitField,
+        /// DMA configuration register.
+        [0x030] rw config: DmaConfig,
+        /// DMA synchronization logic for DMA request signals:
+        /// - 0 - Enable
+        /// - 1 - Disable
+        [0x034] rw sync: u32,
+    }
+
+    pub struct Dma2dChannel {
+        /// DMA source address.
+        [0x00] rw src_addr: u32,
+        /// DMA Destination address.
+        [0x04] rw dst_addr: u32,
+        /// LLI, First linked list item.
+        [0x08] rw lli: u32,
+        /// Control register for DMA channel, the transfer size is the total number
+        /// of transfers of the 2D transfer.
+        [0x0C] rw control: DmaChannelControl,
+        /// Source 2D counts.
+        [0x10] rw src_count: Dma2dCount,
+        /// Source X increment, in bytes (signed).
+        [0x14] rw src_x_incr: u32,
+        /// Source Y increment, in bytes (signed).
+        [0x18] rw src_y_incr: u32,
+        /// Destination 2D counts, the Y count is ignored.
+        [0x1C] rw dst_count: Dma2dCount,
+        /// Destination X increment, in bytes (signed).
+        [0x20] rw dst_x_incr: u32,
+        /// Destination Y increment, in bytes (signed).
+        [0x24] rw dst_y_incr: u32,
+        /// Color key value, compared to source pixels.
+        [0x28] rw key: u32,
+        /// Color key configuration.
+        [0x2C] rw key_config: Dma2dKeyConfig,
+        /// Configuration register for DMA channel.
+        [0x30] rw config: DmaChannelConfig,
+        /// 2D mode configuration.
+        [0x34] rw mode: Dma2dMode,
+    }
+
+}
+
+impl Dma2d {
+
+    /// Custom function to get the registers for configuring a specific
+    /// DMA2D channel.
+    #[must_use]
+    #[inline(always)]
+    pub const fn channel(self, n: usize) -> Dma2dChannel {
+        unsafe { Dma2dChannel::new(self.0.add(0x100 + n * 0x100)) }
+    }
+
+}
+
+
+embedded_util::reg! {
+
+    pub struct Dma2dCount: u32 {
+        /// Number of transfers in a row.
+        [00..16] x_count,
+        /// Number of rows.
+        [16..32] y_count,
+    }
+
+    pub struct Dma2dKeyConfig: u32 {
+        /// Color key enable, source pixels equal to the key are not written to
+        /// the destination.
+        [00..01] enable,
+        /// Size of a pixel compared to the key:
+        /// - 0 - 8 bits
+        /// - 1 - 16 bits
+        /// - 2 - 24 bits
+        /// - 3 - 32 bits
+        [01..03] mode,
+    }
+
+    pub struct Dma2dMode: u32 {
+        /// 2D addressing enable, when cleared the channel behaves like a regular
+        /// DMA channel.
+        [00..01] enable_2d,
+    }
+
+}
@@ -0,0 +1,29 @@
+//! eFuse data registers, the eFuse content is loaded in these registers at boot.
+//!
+//! Only the trim values used by the HAL are described here.
+
+embedded_util::mmio! {
+
+    pub struct EfData {
+        /// Trim values of the GPADC gain and temperature sensor.
+        [0x0F0] ro adc_trim: EfDataAdcTrim,
+    }
+
+}
+
+embedded_util::reg! {
+
+    pub struct EfDataAdcTrim: u32 {
+        /// Temperature sensor offset.
+        [00..12] tsen_trim,
+        /// Parity of the temperature sensor offset (even).
+        [12..13] tsen_trim_parity,
+        [13..14] tsen_trim_en,
+        /// GPADC gain correction, signed 12-bit value in 1/2048 units.
+        [14..26] gpadc_gain_trim,
+        /// Parity of the GPADC gain correction (even).
+        [26..27] gpadc_gain_trim_parity,
+        [27..28] gpadc_gain_trim_en,
+    }
+
+}
@@ -344,9 +344,14 @@ embedded_util::reg! {
 
 embedded_util::reg! {
     pub struct GlbDacCfg0: u32 {
+        /// Analog reset of DAC channel A, active low.
         [00..01] gpdaca_rstn_ana,
+        /// Analog reset of DAC channel B, active low.
         [01..02] gpdacb_rstn_ana,
         [07..08] gpdac_test_en,
+        /// DAC reference:
+        /// - 0 - Internal
+        /// - 1 - External
         [08..09] gpdac_ref_sel,
         [09..12] gpdac_test_sel,
         [24..32] gpdac_reserved,
@@ -355,7 +360,9 @@ embedded_util::reg! {
 
 embedded_util::reg! {
     pub struct GlbDacCfg1: u32 {
+        /// Channel A enable.
         [00..01] gpdac_a_en,
+        /// Channel A output to its pad.
         [01..02] gpdac_ioa_en,
         [18..20] gpdac_a_rng,
         [20..23] gpdac_a_outmux,
@@ -767,6 +774,14 @@ embedded_util::reg! {
 
 embedded_util::reg! {
     pub struct GlbResetSts0: u32 {
+        /// Sources of the last reset, a bit is set for each source involved:
+        /// - 0 - Analog power (power-on)
+        /// - 1 - External reset pin
+        /// - 2 - PDS reset
+        /// - 3 - Watchdog
+        /// - 4 - CPU power-on reset
+        /// - 5 - System software reset
+        /// - 6 - CPU system reset request
         [00..07] top_reset_recorder,
         [07..08] clr_top_reset_recorder,
     }
@@ -50,8 +50,17 @@ embedded_util::reg! {
 embedded_util::reg! {
     pub struct GpipGpdacConfig: u32 {
         [00..01] gpdac_en,
+        /// Output sample rate:
+        /// - 0 - 32 kHz
+        /// - 1 - 16 kHz
+        /// - 3 - 8 kHz
+        /// - 4 - 512 kHz
         [08..11] gpdac_mode,
+        /// Source of channel A:
+        /// - 0 - Register value
+        /// - 1 - DMA
         [16..20] gpdac_ch_a_sel,
+        /// Source of channel B, same values as `gpdac_ch_a_sel`.
         [20..24] gpdac_ch_b_sel,
     }
 }
@@ -59,7 +68,12 @@ embedded_util::reg! {
 embedded_util::reg! {
     pub struct GpipGpdacDmaConfig: u32 {
         [00..01] gpdac_dma_tx_en,
+        /// Invert the most significant bit of DMA samples, for signed samples.
         [01..02] gpdac_dma_inv_msb,
+        /// Format of DMA words:
+        /// - 0 - One sample of channel A
+        /// - 1 - One sample of channel A (low) and B (high)
+        /// - 2 - Two samples of channel A, first in low half-word
         [04..08] gpdac_dma_format,
     }
 }
@@ -82,7 +82,9 @@ embedded_util::reg! {
         [04..13] pin_wakeup_mask,
         [16..17] en_hw_pu_pd,
         [18..19] irq_bor_en,
+        /// Bit 0: rising edge, bit 1: falling edge.
         [20..22] irq_acomp0_en,
+        /// Bit 0: rising edge, bit 1: falling edge.
         [22..24] irq_acomp1_en,
         [24..27] pin_wakeup_sel,
         [27..28] pin_wakeup_en,
@@ -141,6 +143,9 @@ embedded_util::reg! {
         [00..02] root_clk_sel,
         [02..03] uart_clk_sel,
         [03..05] f32k_sel,
+        /// Reset events latched in the always-on domain:
+        /// - 0 - Power-on reset
+        /// - 1 - Brown-out reset
         [07..13] reset_event,
         [13..14] clr_reset_event,
         [15..16] uart_clk_sel2,
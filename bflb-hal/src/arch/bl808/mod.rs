@@ -120,6 +120,8 @@ pub mod uart;
 pub use uart::Uart;
 pub mod dma;
 pub use dma::Dma;
+pub mod dma2d;
+pub use dma2d::Dma2d;
 pub mod cam;
 pub use cam::{Cam, CamFront};
 pub mod mjpeg;
@@ -130,6 +132,14 @@ pub mod ipc;
 pub use ipc::Ipc;
 pub mod i2c;
 pub use i2c::I2c;
+pub mod spi;
+pub use spi::Spi;
+pub mod pwm;
+pub use pwm::Pwm;
+pub mod timer;
+pub use timer::Timer;
+pub mod ef_data;
+pub use ef_data::EfData;
 
 use super::riscv::clic::Clic;
 
@@ -156,6 +166,8 @@ pub const CCI: Cci              = Cci(addr::CCI_BASE as _);
 pub const SF_CTRL: SfCtrl       = SfCtrl(addr::SF_CTRL_BASE as _);
 /// General Purpose ??
 pub const GPIP: Gpip            = Gpip(addr::GPIP_BASE as _);
+/// The eFuse data registers.
+pub const EF_DATA: EfData       = EfData(addr::EF_DATA_BASE as _);
 
 // IPC
 pub const IPC_M0: Ipc             = Ipc(addr::IPC0_BASE as _);
@@ -167,6 +179,9 @@ pub const DMA0: Dma             = Dma(addr::DMA0_BASE as _);
 pub const DMA1: Dma             = Dma(addr::DMA1_BASE as _);
 pub const DMA2: Dma             = Dma(addr::DMA2_BASE as _);
 
+// DMA2D
+pub const DMA2D: Dma2d          = Dma2d(addr::DMA2D_BASE as _);
+
 // UART
 pub const UART0: Uart           = Uart(addr::UART0_BASE as _);
 pub const UART1: Uart           = Uart(addr::UART1_BASE as _);
@@ -178,6 +193,17 @@ pub const I2C1: I2c             = I2c(addr::I2C1_BASE as _);
 pub const I2C2: I2c             = I2c(addr::I2C2_BASE as _);
 pub const I2C3: I2c             = I2c(addr::I2C3_BASE as _);
 
+// SPI
+pub const SPI0: Spi             = Spi(addr::SPI0_BASE as _);
+pub const SPI1: Spi             = Spi(addr::SPI1_BASE as _);
+
+// PWM
+pub const PWM: Pwm              = Pwm(addr::PWM_BASE as _);
+
+// TIMER
+pub const TIMER0: Timer         = Timer(addr::TIMER0_BASE as _);
+pub const TIMER1: Timer         = Timer(addr::TIMER1_BASE as _);
+
 // CAMERA
 pub const CAM_FRONT: CamFront   = CamFront(addr::ISP_MISC_BASE as _);
 pub const CAM0: Cam             = Cam(addr::DVP0_BASE as _);
@@ -0,0 +1,233 @@
+//! PWM (Pulse-Width Modulation) peripheral.
+//!
+//! The controller contains two groups of 4 channels, each channel has a positive and
+//! a negative (complementary) output.
+
+embedded_util::mmio! {
+
+    pub struct Pwm {
+        [0x00] rw int_config: PwmIntConfig,
+    }
+
+    pub struct PwmGroup {
+        [0x00] rw config0: PwmConfig0,
+        /// Per-channel output configuration.
+        [0x04] rw config1: PwmConfig1,
+        [0x08] rw period: PwmPeriod,
+        /// Per-channel dead time between positive and negative outputs.
+        [0x0C] rw dead_time: PwmDeadTime,
+        /// Interrupt status.
+        [0x20] ro int_sts: PwmIntBits,
+        /// Interrupt mask, a set bit masks the interrupt.
+        [0x24] rw int_mask: PwmIntBits,
+        /// Interrupt clear.
+        [0x28] wo int_clear: PwmIntBits,
+        /// Interrupt enable.
+        [0x2C] rw int_en: PwmIntBits,
+    }
+
+}
+
+impl Pwm {
+
+    /// Custom function to get the registers of the given PWM group.
+    #[must_use]
+    #[inline(always)]
+    pub const fn group(self, n: usize) -> PwmGroup {
+        unsafe { PwmGroup::new(self.0.add(0x40 + n * 0x40)) }
+    }
+
+}
+
+impl PwmGroup {
+
+    /// Custom function to get the threshold register of the given channel, these
+    /// registers are located at 0x10 and following.
+    #[must_use]
+    #[inline(always)]
+    pub fn thre(self, channel: usize) -> embedded_util::PtrRw<PwmThre> {
+        unsafe { embedded_util::PtrRw(self.0.add(0x10 + channel * 4) as _) }
+    }
+
+}
+
+embedded_util::reg! {
+
+    pub struct PwmIntConfig: u32 {
+        /// Interrupt status of each group.
+        [00..02] interrupt_sts,
+        /// Interrupt clear of each group.
+        [08..10] int_clear,
+    }
+
+    pub struct PwmConfig0: u32 {
+        /// Clock divider, 0 is the same as 1.
+        [00..16] clk_div,
+        /// Stop the group when the repeat interrupt is triggered.
+        [19..20] stop_on_rept,
+        /// ADC trigger source.
+        [20..24] adc_trg_src,
+        /// Software break, all outputs go to their break state.
+        [24..25] sw_break_en,
+        /// External break.
+        [25..26] ext_break_en,
+        /// External break polarity.
+        [26..27] ext_break_pl,
+        /// Stop the group counter.
+        [27..28] stop_en,
+        /// Stop mode:
+        /// - 0 - Stop immediately
+        /// - 1 - Stop at the end of the current period
+        [28..29] stop_mode,
+        /// Group is stopped.
+        [29..30] sts_stop,
+        /// Clock source:
+        /// - 0 - XCLK
+        /// - 1 - BCLK
+        /// - 2 - F32K
+        [30..32] reg_clk_sel,
+    }
+
+    pub struct PwmPeriod: u32 {
+        /// Period of the group counter, in divided clock cycles.
+        [00..16] period,
+        /// Number of periods before the repeat interrupt.
+        [16..32] int_period_cnt,
+    }
+
+    pub struct PwmThre: u32 {
+        /// Counter value where the positive output goes active.
+        [00..16] threl,
+        /// Counter value where the positive output goes inactive.
+        [16..32] threh,
+    }
+
+}
+
+/// Per-channel output configuration, each channel has 4 configuration bits in the
+/// low half-word and 4 polarity/break bits in the high half-word.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[repr(transparent)]
+pub struct PwmConfig1(pub u32);
+
+impl PwmConfig1 {
+
+    #[inline]
+    fn get_bit(&self, bit: u8) -> bool {
+        self.0 & (1 << bit) != 0
+    }
+
+    #[inline]
+    fn set_bit(&mut self, bit: u8, value: bool) {
+        if value {
+            self.0 |= 1 << bit;
+        } else {
+            self.0 &= !(1 << bit);
+        }
+    }
+
+    /// Positive output enable.
+    #[inline]
+    pub fn positive_enable(&self, channel: u8) -> bool { self.get_bit(channel * 4) }
+    #[inline]
+    pub fn set_positive_enable(&mut self, channel: u8, value: bool) { self.set_bit(channel * 4, value) }
+
+    /// Positive output idle state.
+    #[inline]
+    pub fn positive_idle(&self, channel: u8) -> bool { self.get_bit(channel * 4 + 1) }
+    #[inline]
+    pub fn set_positive_idle(&mut self, channel: u8, value: bool) { self.set_bit(channel * 4 + 1, value) }
+
+    /// Negative output enable.
+    #[inline]
+    pub fn negative_enable(&self, channel: u8) -> bool { self.get_bit(channel * 4 + 2) }
+    #[inline]
+    pub fn set_negative_enable(&mut self, channel: u8, value: bool) { self.set_bit(channel * 4 + 2, value) }
+
+    /// Negative output idle state.
+    #[inline]
+    pub fn negative_idle(&self, channel: u8) -> bool { self.get_bit(channel * 4 + 3) }
+    #[inline]
+    pub fn set_negative_idle(&mut self, channel: u8, value: bool) { self.set_bit(channel * 4 + 3, value) }
+
+    /// Positive output polarity, set for active high.
+    #[inline]
+    pub fn positive_polarity(&self, channel: u8) -> bool { self.get_bit(16 + channel * 2) }
+    #[inline]
+    pub fn set_positive_polarity(&mut self, channel: u8, value: bool) { self.set_bit(16 + channel * 2, value) }
+
+    /// Negative output polarity, set for active high.
+    #[inline]
+    pub fn negative_polarity(&self, channel: u8) -> bool { self.get_bit(16 + channel * 2 + 1) }
+    #[inline]
+    pub fn set_negative_polarity(&mut self, channel: u8, value: bool) { self.set_bit(16 + channel * 2 + 1, value) }
+
+    /// Positive output break state.
+    #[inline]
+    pub fn positive_break(&self, channel: u8) -> bool { self.get_bit(24 + channel * 2) }
+    #[inline]
+    pub fn set_positive_break(&mut self, channel: u8, value: bool) { self.set_bit(24 + channel * 2, value) }
+
+    /// Negative output break state.
+    #[inline]
+    pub fn negative_break(&self, channel: u8) -> bool { self.get_bit(24 + channel * 2 + 1) }
+    #[inline]
+    pub fn set_negative_break(&mut self, channel: u8, value: bool) { self.set_bit(24 + channel * 2 + 1, value) }
+
+}
+
+/// Dead time of each channel, 8 bits per channel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[repr(transparent)]
+pub struct PwmDeadTime(pub u32);
+
+impl PwmDeadTime {
+
+    #[inline]
+    pub fn get(&self, channel: u8) -> u8 {
+        (self.0 >> (channel * 8)) as u8
+    }
+
+    #[inline]
+    pub fn set(&mut self, channel: u8, cycles: u8) {
+        self.0 &= !(0xFF << (channel * 8));
+        self.0 |= (cycles as u32) << (channel * 8);
+    }
+
+}
+
+/// Interrupt bits of a PWM group.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[repr(transparent)]
+pub struct PwmIntBits(pub u32);
+
+impl PwmIntBits {
+
+    /// Channel interrupt when the counter reaches the low threshold.
+    pub const fn channel_low(channel: u8) -> u32 { 1 << (channel * 2) }
+    /// Channel interrupt when the counter reaches the high threshold.
+    pub const fn channel_high(channel: u8) -> u32 { 1 << (channel * 2 + 1) }
+    /// Period end interrupt.
+    pub const PERIOD: u32 = 1 << 8;
+    /// Break interrupt.
+    pub const BREAK: u32 = 1 << 9;
+    /// Repeat interrupt, after `int_period_cnt` periods.
+    pub const REPEAT: u32 = 1 << 10;
+    /// All interrupt bits.
+    pub const ALL: u32 = 0x7FF;
+
+    #[inline]
+    pub fn get(&self, bits: u32) -> bool {
+        self.0 & bits != 0
+    }
+
+    #[inline]
+    pub fn set(&mut self, bits: u32, value: bool) {
+        if value {
+            self.0 |= bits;
+        } else {
+            self.0 &= !bits;
+        }
+    }
+
+}
@@ -0,0 +1,179 @@
+//! SPI controller peripheral.
+
+embedded_util::mmio! {
+    pub struct Spi {
+        [0x000] rw config: SpiConfig,
+        [0x004] rw int_sts: SpiIntSts,
+        [0x008] ro bus_busy: SpiBusBusy,
+        /// Length of START, STOP and DATA phases (master mode).
+        [0x010] rw prd_0: SpiPrd0,
+        /// Length of INTERVAL between frames (master mode).
+        [0x014] rw prd_1: SpiPrd1,
+        [0x018] rw rxd_ignr: SpiRxdIgnr,
+        /// Time-out value for slave mode.
+        [0x01C] rw sto_value: SpiStoValue,
+        [0x080] rw fifo_config_0: SpiFifoConfig0,
+        [0x084] rw fifo_config_1: SpiFifoConfig1,
+        /// The FIFO write data buffer, only the lower bits corresponding to the frame
+        /// size are used.
+        [0x088] wo fifo_wdata: u32,
+        /// The FIFO read data buffer, only the lower bits corresponding to the frame
+        /// size are valid.
+        [0x08C] ro fifo_rdata: u32,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiConfig: u32 {
+        /// Enable signal of SPI master function. Asserting this bit will trigger the
+        /// transaction, and should be de-asserted after finish.
+        [00..01] m_en,
+        /// Enable signal of SPI slave function. Asserting this bit will trigger the
+        /// transaction, and should be de-asserted after finish.
+        [01..02] s_en,
+        /// SPI frame size (also the valid width for each FIFO entry).
+        /// - 0 - 8 bits
+        /// - 1 - 16 bits
+        /// - 2 - 24 bits
+        /// - 3 - 32 bits
+        [02..04] frame_size,
+        /// SCLK clock polarity.
+        /// - 0 - SCLK output low at IDLE state
+        /// - 1 - SCLK output high at IDLE state
+        [04..05] sclk_pol,
+        /// SCLK clock phase.
+        /// - 0 - MOSI changes at SCLK falling edge (with pol = 0)
+        /// - 1 - MOSI changes at SCLK rising edge (with pol = 0)
+        [05..06] sclk_ph,
+        /// Bit-inverse for each data byte.
+        /// - 0 - Each byte is sent MSB-first
+        /// - 1 - Each byte is sent LSB-first
+        [06..07] bit_inv,
+        /// Byte-inverse for each FIFO entry.
+        /// - 0 - Byte 0 is sent first
+        /// - 1 - Byte 3 is sent first
+        [07..08] byte_inv,
+        /// Enable signal of RX data ignore function.
+        [08..09] rxd_ignr_en,
+        /// Enable signal of master continuous transfer mode. When enabled, SS will
+        /// stay asserted if next data is valid.
+        [09..10] m_cont_en,
+        /// Slave 3-pin mode (no SS pin).
+        [10..11] s_3pin_mode,
+        /// Enable signal of all input de-glitch function.
+        [11..12] deg_en,
+        /// De-glitch function cycle count.
+        [12..16] deg_cnt,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiIntSts: u32 {
+        /// Transfer end interrupt, shared by both master and slave mode.
+        [00..01] end_int,
+        /// TX FIFO ready interrupt (tx_fifo_cnt > tx_fifo_th).
+        [01..02] txf_int,
+        /// RX FIFO ready interrupt (rx_fifo_cnt > rx_fifo_th).
+        [02..03] rxf_int,
+        /// Slave mode transfer time-out interrupt.
+        [03..04] sto_int,
+        /// Slave mode TX underrun interrupt.
+        [04..05] txu_int,
+        /// TX/RX FIFO error interrupt (overflow/underflow).
+        [05..06] fer_int,
+        [08..09] end_mask,
+        [09..10] txf_mask,
+        [10..11] rxf_mask,
+        [11..12] sto_mask,
+        [12..13] txu_mask,
+        [13..14] fer_mask,
+        [16..17] end_clr,
+        [19..20] sto_clr,
+        [20..21] txu_clr,
+        [24..25] end_en,
+        [25..26] txf_en,
+        [26..27] rxf_en,
+        [27..28] sto_en,
+        [28..29] txu_en,
+        [29..30] fer_en,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiBusBusy: u32 {
+        /// Indicator of SPI bus busy.
+        [00..01] bus_busy,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiPrd0: u32 {
+        /// Length of START condition.
+        [00..08] prd_s,
+        /// Length of STOP condition.
+        [08..16] prd_p,
+        /// Length of DATA phase 0.
+        [16..24] prd_d_ph_0,
+        /// Length of DATA phase 1.
+        [24..32] prd_d_ph_1,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiPrd1: u32 {
+        /// Length of INTERVAL between frame.
+        [00..08] prd_i,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiRxdIgnr: u32 {
+        /// Ending point of RX data ignore function.
+        [00..05] ignr_p,
+        /// Starting point of RX data ignore function.
+        [16..21] ignr_s,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiStoValue: u32 {
+        /// Time-out value for `sto_int` interrupt.
+        [00..12] sto_value,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiFifoConfig0: u32 {
+        /// Enable signal of dma_tx_req/ack interface.
+        [00..01] dma_tx_en,
+        /// Enable signal of dma_rx_req/ack interface.
+        [01..02] dma_rx_en,
+        /// Clear signal of TX FIFO.
+        [02..03] tx_fifo_clr,
+        /// Clear signal of RX FIFO.
+        [03..04] rx_fifo_clr,
+        /// Overflow flag of TX FIFO, can be cleared by tx_fifo_clr.
+        [04..05] tx_fifo_overflow,
+        /// Underflow flag of TX FIFO, can be cleared by tx_fifo_clr.
+        [05..06] tx_fifo_underflow,
+        /// Overflow flag of RX FIFO, can be cleared by rx_fifo_clr.
+        [06..07] rx_fifo_overflow,
+        /// Underflow flag of RX FIFO, can be cleared by rx_fifo_clr.
+        [07..08] rx_fifo_underflow,
+    }
+}
+
+embedded_util::reg! {
+    pub struct SpiFifoConfig1: u32 {
+        /// TX FIFO available count (in entries).
+        [00..06] tx_fifo_cnt,
+        /// RX FIFO available count (in entries).
+        [08..14] rx_fifo_cnt,
+        /// TX FIFO threshold, dma_tx_req will not be asserted if tx_fifo_cnt is less
+        /// than this value.
+        [16..21] tx_fifo_th,
+        /// RX FIFO threshold, dma_rx_req will not be asserted if rx_fifo_cnt is less
+        /// than this value.
+        [24..29] rx_fifo_th,
+    }
+}
@@ -0,0 +1,197 @@
+//! General purpose timer peripheral.
+//!
+//! TIMER0 on MCU subsystem and TIMER1 on MM subsystem share the same layout, each
+//! one contains two timer channels with 3 match registers each and a watchdog.
+
+embedded_util::mmio! {
+
+    pub struct Timer {
+        /// Clock source of channels.
+        [0x00] rw tccr: TimerTccr,
+        /// Counter value of channel 0 and 1.
+        [0x2C] ro tcr0: u32,
+        [0x30] ro tcr1: u32,
+        /// Preload value of channel 0 and 1.
+        [0x50] rw tplvr0: u32,
+        [0x54] rw tplvr1: u32,
+        /// Preload control of channel 0 and 1.
+        [0x5C] rw tplcr0: TimerTplcr,
+        [0x60] rw tplcr1: TimerTplcr,
+        /// Watchdog mode.
+        [0x64] rw wmer: TimerWmer,
+        /// Watchdog match value.
+        [0x68] rw wmr: TimerWmr,
+        /// Watchdog counter value.
+        [0x6C] ro wvr: TimerWvr,
+        /// Watchdog reset status.
+        [0x70] rw wsr: TimerWsr,
+        /// Watchdog interrupt clear.
+        [0x80] wo wicr: TimerWicr,
+        /// Channels enable.
+        [0x84] rw tcer: TimerTcer,
+        /// Channels mode.
+        [0x88] rw tcmr: TimerTcmr,
+        /// Watchdog counter reset.
+        [0x98] wo wcr: TimerWcr,
+        /// Watchdog access keys, the first key (0xBABA) then the second key (0xEB10)
+        /// must be written before each write to a watchdog register.
+        [0x9C] wo wfar: u32,
+        [0xA0] wo wsar: u32,
+        /// Clock dividers of channels.
+        [0xBC] rw tcdr: TimerTcdr,
+    }
+
+}
+
+impl Timer {
+
+    /// Custom function to get the match value register `n` (0..3) of the given
+    /// channel. Match registers are located at 0x10 for channel 0 and 0x1C for
+    /// channel 1.
+    #[must_use]
+    #[inline(always)]
+    pub fn tmr(self, channel: usize, n: usize) -> embedded_util::PtrRw<u32> {
+        unsafe { embedded_util::PtrRw(self.0.add(0x10 + channel * 0xC + n * 4) as _) }
+    }
+
+    /// Custom function to get the match status register of the given channel, a bit
+    /// is set for each match register that matched the counter.
+    #[must_use]
+    #[inline(always)]
+    pub fn tmsr(self, channel: usize) -> embedded_util::PtrRo<TimerMatchBits> {
+        unsafe { embedded_util::PtrRo(self.0.add(0x34 + channel * 4) as _) }
+    }
+
+    /// Custom function to get the match interrupt enable register of the given
+    /// channel.
+    #[must_use]
+    #[inline(always)]
+    pub fn tier(self, channel: usize) -> embedded_util::PtrRw<TimerMatchBits> {
+        unsafe { embedded_util::PtrRw(self.0.add(0x40 + channel * 4) as _) }
+    }
+
+    /// Custom function to get the match interrupt clear register of the given
+    /// channel.
+    #[must_use]
+    #[inline(always)]
+    pub fn ticr(self, channel: usize) -> embedded_util::PtrWo<TimerMatchBits> {
+        unsafe { embedded_util::PtrWo(self.0.add(0x78 + channel * 4) as _) }
+    }
+
+    /// Custom function to get the match interrupt mode register of the given
+    /// channel, a set bit for edge-triggered and a cleared one for level-triggered.
+    #[must_use]
+    #[inline(always)]
+    pub fn tilr(self, channel: usize) -> embedded_util::PtrRw<TimerMatchBits> {
+        unsafe { embedded_util::PtrRw(self.0.add(0x90 + channel * 4) as _) }
+    }
+
+}
+
+embedded_util::reg! {
+
+    pub struct TimerTccr: u32 {
+        /// Clock source of channel 0:
+        /// - 0 - BCLK
+        /// - 1 - F32K
+        /// - 2 - 1 kHz
+        /// - 3 - XTAL
+        /// - 4 - GPIO
+        /// - 5 - No clock
+        [00..04] cs_0,
+        /// Clock source of channel 1, same values as `cs_0`.
+        [04..08] cs_1,
+        /// Clock source of the watchdog, same values as `cs_0`.
+        [08..12] cs_wdt,
+    }
+
+    pub struct TimerTplcr: u32 {
+        /// Preload trigger:
+        /// - 0 - No preload
+        /// - 1 - Preload when match 0
+        /// - 2 - Preload when match 1
+        /// - 3 - Preload when match 2
+        [00..02] tplcr,
+    }
+
+    pub struct TimerTcer: u32 {
+        [01..02] timer0_en,
+        [02..03] timer1_en,
+        /// Counter clear of channel 0, must be set and then cleared.
+        [05..06] timer0_cnt_clr,
+        [06..07] timer1_cnt_clr,
+    }
+
+    pub struct TimerTcmr: u32 {
+        /// Mode of channel 0:
+        /// - 0 - Preload mode, the counter is reloaded on the preload trigger
+        /// - 1 - Free run mode, the counter is never reloaded
+        [01..02] timer0_mode,
+        [02..03] timer1_mode,
+    }
+
+    pub struct TimerTcdr: u32 {
+        /// Clock divider of channel 0, minus one.
+        [08..16] tcdr0,
+        /// Clock divider of channel 1, minus one.
+        [16..24] tcdr1,
+        /// Clock divider of the watchdog, minus one.
+        [24..32] wcdr,
+    }
+
+    pub struct TimerWmer: u32 {
+        /// Watchdog enable.
+        [00..01] we,
+        /// Watchdog expiration action:
+        /// - 0 - Interrupt
+        /// - 1 - Reset
+        [01..02] wrie,
+    }
+
+    pub struct TimerWmr: u32 {
+        [00..16] wmr,
+    }
+
+    pub struct TimerWvr: u32 {
+        [00..16] wvr,
+    }
+
+    pub struct TimerWsr: u32 {
+        /// Set if the last reset was caused by this watchdog, write 0 to clear.
+        [00..01] wts,
+    }
+
+    pub struct TimerWicr: u32 {
+        [00..01] wiclr,
+    }
+
+    pub struct TimerWcr: u32 {
+        /// Write 1 to reset the watchdog counter.
+        [00..01] wcr,
+    }
+
+}
+
+/// A bit field where each bit is associated to a match register of a channel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[repr(transparent)]
+pub struct TimerMatchBits(pub u32);
+
+impl TimerMatchBits {
+
+    /// Set the bit for the given match register.
+    #[inline]
+    pub fn set(&mut self, n: u8, bit: bool) {
+        if bit {
+            self.0 |= 1 << n;
+        } else {
+            self.0 &= !(1 << n);
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, n: u8) -> bool {
+        (self.0 & (1 << n)) != 0
+    }
+
+}
@@ -182,3 +182,15 @@ impl<T: ?Sized> DerefMut for CacheAligned<T> {
         &mut self.0
     }
 }
+
+impl<T: ?Sized + AsRef<U>, U: ?Sized> AsRef<U> for CacheAligned<T> {
+    fn as_ref(&self) -> &U {
+        self.0.as_ref()
+    }
+}
+
+impl<T: ?Sized + AsMut<U>, U: ?Sized> AsMut<U> for CacheAligned<T> {
+    fn as_mut(&mut self) -> &mut U {
+        self.0.as_mut()
+    }
+}
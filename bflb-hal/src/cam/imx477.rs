@@ -5,7 +5,7 @@
 use crate::i2c::{I2cDev, I2cAddr};
 use crate::gpio::{Pin, Output};
 use crate::clock::Clocks;
-use crate::time::wait;
+use crate::time::{wait, Duration};
 
 /// I²C address of the sensor.
 const I2C_ADDR: I2cAddr = I2cAddr::new(0x1A);
@@ -35,7 +35,7 @@ const DEFAULT_LINK_FREQ: u32 = 450_000_000;
 const PIXEL_RATE: u32 = 840_000_000;
 
 /// Minimum delay before power up is considered ready (8 ms).
-const POWER_ON_DELAY: u64 = 8_000_000;
+const POWER_ON_DELAY: Duration = Duration::from_millis(8);
 
 
 /// Driver structure for Sony IMX477 sensor.
@@ -2,6 +2,9 @@
 
 use crate::arch::bl808::GLB;
 
+use super::pll::{PllAudioDiv, get_audio_pll_freq};
+use super::get_xclk_freq;
+
 
 /// Enable or disable common clock gate for ADC/DAC.
 pub unsafe fn set_adc_dac_enable(enable: bool) {
@@ -32,6 +35,15 @@ pub unsafe fn set_adc_div(div: u32) {
     GLB.adc_cfg0().modify(|reg| reg.gpadc_32m_clk_div().set(div - 1));
 }
 
+/// Get the frequency of the ADC clock, after the divider.
+pub fn get_adc_freq() -> u32 {
+    let source_freq = match get_adc_sel() {
+        AdcClockSel::AudioPll => get_audio_pll_freq(PllAudioDiv::Div1),
+        AdcClockSel::Xclk => get_xclk_freq(),
+    };
+    source_freq / get_adc_div()
+}
+
 #[inline(never)]
 pub unsafe fn setup_adc(sel: AdcClockSel, div: u32, enable: bool) {
     set_adc_div_enable(false);
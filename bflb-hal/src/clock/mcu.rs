@@ -32,6 +32,33 @@ pub fn get_mcu_pll_freq() -> u32 {
 }
 
 
+/// Get the selector for the MCU PLL 160 MHz clock, used by peripherals.
+pub fn get_mcu_pll160_sel() -> McuPll160Sel {
+    match GLB.dig_clk_cfg1().get().top_muxpll_160m_sel().get() {
+        0 => McuPll160Sel::WifiPll160,
+        1 => McuPll160Sel::CpuPll160,
+        2 => McuPll160Sel::AudioPllDiv2,
+        3 => McuPll160Sel::AudioPllDiv2p5,
+        _ => unreachable!()
+    }
+}
+
+/// Set the selector for the MCU PLL 160 MHz clock.
+pub unsafe fn set_mcu_pll160_sel(sel: McuPll160Sel) {
+    GLB.dig_clk_cfg1().modify(|reg| reg.top_muxpll_160m_sel().set(sel as _));
+}
+
+/// Get the frequency for the MCU PLL 160 MHz clock.
+pub fn get_mcu_pll160_freq() -> u32 {
+    match get_mcu_pll160_sel() {
+        McuPll160Sel::WifiPll160 => get_wifi_pll_freq(160_000_000),
+        McuPll160Sel::CpuPll160 => get_cpu_pll_freq(160_000_000),
+        McuPll160Sel::AudioPllDiv2 => get_audio_pll_freq(PllAudioDiv::Div2),
+        McuPll160Sel::AudioPllDiv2p5 => get_audio_pll_freq(PllAudioDiv::Div2p5),
+    }
+}
+
+
 /// Get the selector for the main MCU freq.
 pub fn get_mcu_root_sel() -> McuRootSel {
     match HBN.glb().get().mcu_root_sel().get() {
@@ -142,6 +169,14 @@ pub enum McuPllSel {
     WifiPll320 = 3,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McuPll160Sel {
+    WifiPll160 = 0,
+    CpuPll160 = 1,
+    AudioPllDiv2 = 2,
+    AudioPllDiv2p5 = 3,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum McuRootSel {
     Xclk = 0,
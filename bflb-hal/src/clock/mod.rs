@@ -42,7 +42,9 @@ pub mod mcu;
 pub mod mm;
 pub mod uart;
 pub mod i2c;
+pub mod spi;
 pub mod pwm;
+pub mod timer;
 pub mod dma;
 pub mod pll;
 
@@ -295,6 +297,11 @@ pub(crate) fn init() {
         uart::setup_mcu_uart(uart::UartSel::Xclk, 1, true);
         uart::set_mcu_uart0_enable(true);
 
+        spi::setup_mcu_spi(spi::McuSpiSel::Xclk, 1, true);
+
+        pwm::set_pwm0_enable(true);
+        timer::set_timer0_enable(true);
+
         analog::set_adc_dac_enable(true);
 
     }
@@ -333,6 +340,12 @@ pub fn debug_clock_diagram(write: &mut dyn fmt::Write) -> fmt::Result {
         mcu::McuPllSel::WifiPll240 => "wifi pll 240m",
         mcu::McuPllSel::WifiPll320 => "wifi pll 320m",
     })?;
+    writeln!(write, " mcu pll 160m: {:>9} Hz <- {}", mcu::get_mcu_pll160_freq(), match mcu::get_mcu_pll160_sel() {
+        mcu::McuPll160Sel::WifiPll160 => "wifi pll 160m",
+        mcu::McuPll160Sel::CpuPll160 => "cpu pll 160m",
+        mcu::McuPll160Sel::AudioPllDiv2 => "audio pll /2",
+        mcu::McuPll160Sel::AudioPllDiv2p5 => "audio pll /2.5",
+    })?;
     writeln!(write, "     mcu root: {:>9} Hz <- {}", mcu::get_mcu_root_freq(), match mcu::get_mcu_root_sel() {
         mcu::McuRootSel::Xclk => "xclk",
         mcu::McuRootSel::McuPll => "cg <- mcu pll",
@@ -378,6 +391,16 @@ pub fn debug_clock_diagram(write: &mut dyn fmt::Write) -> fmt::Result {
         uart::UartSel::Xclk => "xclk",
     })?;
     
+    writeln!(write, "============== SPI clocks")?;
+    writeln!(write, "      mcu spi: {:>9} Hz <- cg <- /{} <- {}", spi::get_mcu_spi_freq(), spi::get_mcu_spi_div(), match spi::get_mcu_spi_sel() {
+        spi::McuSpiSel::Pll160 => "mcu pll 160m",
+        spi::McuSpiSel::Xclk => "xclk",
+    })?;
+    writeln!(write, "       mm spi: {:>9} Hz <- cg <- /{} <- {}", spi::get_mm_spi_freq(), spi::get_mm_spi_div(), match spi::get_mm_spi_sel() {
+        spi::MmSpiSel::MmPll160 => "mm pll 160m",
+        spi::MmSpiSel::MmXclk => "mm xclk",
+    })?;
+    
     writeln!(write, "============== I2C clocks")?;
     writeln!(write, "      mcu i2c: {:>9} Hz <- cg <- /{} <- {}", i2c::get_mcu_i2c_freq(), i2c::get_mcu_i2c_div(), match i2c::get_mcu_i2c_sel() {
         i2c::McuI2cSel::McuPbclk => "mcu pbclk",
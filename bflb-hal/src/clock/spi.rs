@@ -0,0 +1,127 @@
+//! Clock functions for SPI peripheral.
+//! 
+//! This module contains both MCU (SPI0) and MM (SPI1) subsystems SPI controls.
+
+use crate::arch::bl808::{GLB, MM_GLB};
+
+use super::mm::{get_mm_pll160_freq, get_mm_xclk_freq};
+use super::mcu::get_mcu_pll160_freq;
+use super::get_xclk_freq;
+
+
+/// Enable clock gate for MCU SPI controller (0).
+pub unsafe fn set_mcu_spi_enable(enable: bool) {
+    GLB.cgen_cfg1().modify(|reg| reg.cgen_s1a_spi().set(enable as _));
+    GLB.spi_cfg0().modify(|reg| reg.spi_clk_en().set(enable as _));
+}
+
+/// Get clock selector for MCU SPI controller (0).
+pub fn get_mcu_spi_sel() -> McuSpiSel {
+    match GLB.spi_cfg0().get().spi_clk_sel().get() {
+        0 => McuSpiSel::Pll160,
+        1 => McuSpiSel::Xclk,
+        _ => unreachable!()
+    }
+}
+
+/// Set clock selector for MCU SPI controller (0).
+pub unsafe fn set_mcu_spi_sel(sel: McuSpiSel) {
+    GLB.spi_cfg0().modify(|reg| reg.spi_clk_sel().set(sel as _));
+}
+
+/// Get clock divider for MCU SPI controller (0).
+pub fn get_mcu_spi_div() -> u32 {
+    GLB.spi_cfg0().get().spi_clk_div().get() + 1
+}
+
+/// Set clock divider for MCU SPI controller (0).
+pub unsafe fn set_mcu_spi_div(div: u32) {
+    GLB.spi_cfg0().modify(|reg| reg.spi_clk_div().set(div - 1));
+}
+
+/// Get clock frequency for MCU SPI controller (0).
+pub fn get_mcu_spi_freq() -> u32 {
+    let freq = match get_mcu_spi_sel() {
+        McuSpiSel::Pll160 => get_mcu_pll160_freq(),
+        McuSpiSel::Xclk => get_xclk_freq(),
+    };
+    freq / get_mcu_spi_div()
+}
+
+/// Setup the clock for MCU SPI controller (0).
+#[inline(never)]
+pub unsafe fn setup_mcu_spi(clock_sel: McuSpiSel, div: u32, enable: bool) {
+    set_mcu_spi_enable(false);
+    set_mcu_spi_sel(clock_sel);
+    set_mcu_spi_div(div);
+    set_mcu_spi_enable(enable);
+}
+
+
+/// Get clock selector for MM SPI controller (1).
+#[doc(alias = "Clock_Get_DSP_SPI_Clk")]
+pub fn get_mm_spi_sel() -> MmSpiSel {
+    match MM_GLB.mm_clk_ctrl_cpu().get().spi_clk_sel().get() {
+        0 => MmSpiSel::MmPll160,
+        1 => MmSpiSel::MmXclk,
+        _ => unreachable!()
+    }
+}
+
+/// Set clock selector for MM SPI controller (1).
+pub unsafe fn set_mm_spi_sel(sel: MmSpiSel) {
+    MM_GLB.mm_clk_ctrl_cpu().modify(|reg| reg.spi_clk_sel().set(sel as _));
+}
+
+/// Get clock divider for MM SPI controller (1).
+pub fn get_mm_spi_div() -> u32 {
+    MM_GLB.mm_clk_ctrl_peri().get().spi_clk_div().get() + 1
+}
+
+/// Set clock divider for MM SPI controller (1).
+pub unsafe fn set_mm_spi_div(div: u32) {
+    MM_GLB.mm_clk_ctrl_peri().modify(|reg| reg.spi_clk_div().set(div - 1));
+}
+
+/// Return true if the clock divider for MM SPI controller (1) is enabled.
+pub fn get_mm_spi_enable() -> bool {
+    MM_GLB.mm_clk_ctrl_peri().get().spi_clk_div_en().get() != 0
+}
+
+/// Enable clock divider for MM SPI controller (1).
+pub unsafe fn set_mm_spi_enable(enable: bool) {
+    MM_GLB.mm_clk_ctrl_peri().modify(|reg| reg.spi_clk_div_en().set(enable as _));
+}
+
+/// Get clock frequency for MM SPI controller (1).
+pub fn get_mm_spi_freq() -> u32 {
+    let freq = match get_mm_spi_sel() {
+        MmSpiSel::MmPll160 => get_mm_pll160_freq(),
+        MmSpiSel::MmXclk => get_mm_xclk_freq(),
+    };
+    freq / get_mm_spi_div()
+}
+
+/// Setup the clock for MM SPI controller (1).
+#[inline(never)]
+pub unsafe fn setup_mm_spi(clock_sel: MmSpiSel, div: u32, enable: bool) {
+    set_mm_spi_enable(false);
+    set_mm_spi_sel(clock_sel);
+    set_mm_spi_div(div);
+    set_mm_spi_enable(enable);
+}
+
+
+/// Selector for MCU SPI clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McuSpiSel {
+    Pll160 = 0,
+    Xclk = 1,
+}
+
+/// Selector for MM SPI clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmSpiSel {
+    MmPll160 = 0,
+    MmXclk = 1,
+}
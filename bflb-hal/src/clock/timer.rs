@@ -0,0 +1,8 @@
+//! Timer related clocks.
+
+use crate::arch::bl808::GLB;
+
+
+pub unsafe fn set_timer0_enable(enable: bool) {
+    GLB.cgen_cfg1().modify(|reg| reg.cgen_s1a_timer().set(enable as _));
+}
@@ -2,7 +2,7 @@
 
 use crate::arch::bl808::{GLB, HBN};
 
-use super::mcu::get_mcu_pbclk_freq;
+use super::mcu::{get_mcu_pbclk_freq, get_mcu_pll160_freq};
 use super::get_xclk_freq;
 
 
@@ -61,7 +61,7 @@ pub unsafe fn set_mcu_uart_div(div: u32) {
 pub fn get_mcu_uart_freq() -> u32 {
     let freq = match get_mcu_uart_sel() {
         UartSel::McuPbclk => get_mcu_pbclk_freq(),
-        UartSel::Pll160 => todo!("pll160 is not implemented"),
+        UartSel::Pll160 => get_mcu_pll160_freq(),
         UartSel::Xclk => get_xclk_freq(),
     };
     freq / get_mcu_uart_div()
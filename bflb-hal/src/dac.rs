@@ -0,0 +1,299 @@
+//! Digital to Analog converter peripheral.
+//!
+//! The DAC has two 10-bit channels (A and B), each one can be output to its pad and
+//! is internally connected to the ADC (see [`DacA`](crate::adc::DacA) and
+//! [`DacB`](crate::adc::DacB) channels). Channels are either driven by a register
+//! value or fed by DMA at the configured sample rate.
+
+use core::ptr::addr_of;
+
+use crate::arch::bl808::{GLB, GPIP};
+use crate::gpio::{Pin, Alternate, PinFunction, PinDrive, PinConfig};
+use crate::dma::{DmaDstEndpoint, DmaEndpointConfig, DmaPeripheral, DmaDataWidth,
+    DmaBurstSize, DmaIncrement};
+use crate::sealed::Sealed;
+use crate::time::{self, Duration};
+
+
+/// Exclusive access to DAC peripheral.
+pub struct DacAccess(pub(crate) ());
+
+impl DacAccess {
+
+    /// Initialize the DAC with the given outputs for channels A and B, an output
+    /// can be `()` to only use the channel internally. Both channels are enabled
+    /// with an initial value of zero.
+    pub fn init<A, B>(self, config: &DacConfig, mut out_a: A, mut out_b: B) -> Dac<A, B>
+    where
+        A: DacOutput<0>,
+        B: DacOutput<1>,
+    {
+
+        // Reset analog part of both channels.
+        GLB.dac_cfg0().modify(|reg| {
+            reg.gpdaca_rstn_ana().clear();
+            reg.gpdacb_rstn_ana().clear();
+        });
+        time::wait(Duration::from_micros(1));
+        GLB.dac_cfg0().modify(|reg| {
+            reg.gpdaca_rstn_ana().fill();
+            reg.gpdacb_rstn_ana().fill();
+            reg.gpdac_ref_sel().set(config.vref as _);
+        });
+
+        GPIP.gpdac_config().modify(|reg| {
+            reg.gpdac_mode().set(config.sample_rate as _);
+            reg.gpdac_ch_a_sel().clear();
+            reg.gpdac_ch_b_sel().clear();
+        });
+
+        GPIP.gpdac_dma_config().modify(|reg| {
+            reg.gpdac_dma_tx_en().clear();
+            reg.gpdac_dma_format().clear();
+        });
+
+        GLB.dac_cfg3().set_with(|reg| {
+            reg.gpdac_a_data().clear();
+            reg.gpdac_b_data().clear();
+        });
+
+        let io_a = out_a.configure();
+        GLB.dac_cfg1().modify(|reg| {
+            reg.gpdac_a_en().fill();
+            reg.gpdac_ioa_en().set(io_a as _);
+        });
+
+        let io_b = out_b.configure();
+        GLB.dac_cfg2().modify(|reg| {
+            reg.gpdac_b_en().fill();
+            reg.gpdac_iob_en().set(io_b as _);
+        });
+
+        GPIP.gpdac_config().modify(|reg| reg.gpdac_en().fill());
+
+        Dac {
+            out_a,
+            out_b,
+            dma_format: DacDmaFormat::A,
+        }
+
+    }
+
+}
+
+
+/// An initialized DAC.
+pub struct Dac<A, B> {
+    out_a: A,
+    out_b: B,
+    dma_format: DacDmaFormat,
+}
+
+impl<A, B> Dac<A, B> {
+
+    /// Disable the DAC and get back the outputs.
+    pub fn downgrade(self) -> (DacAccess, A, B) {
+        // Drop will be called at the end, effectively disabling the DAC.
+        unsafe { (
+            DacAccess(()),
+            addr_of!(self.out_a).read(),
+            addr_of!(self.out_b).read(),
+        ) }
+    }
+
+    /// Set the value of the given channel, from 0 to 1023. This is ignored while the
+    /// channel is fed by DMA.
+    pub fn set_value(&mut self, channel: DacChannel, value: u16) {
+        let value = value.min(MAX_VALUE);
+        GLB.dac_cfg3().modify(|reg| match channel {
+            DacChannel::A => reg.gpdac_a_data().set(value as _),
+            DacChannel::B => reg.gpdac_b_data().set(value as _),
+        });
+    }
+
+    /// Get the register value of the given channel.
+    pub fn value(&self, channel: DacChannel) -> u16 {
+        let mut data = GLB.dac_cfg3().get();
+        match channel {
+            DacChannel::A => data.gpdac_a_data().get() as u16,
+            DacChannel::B => data.gpdac_b_data().get() as u16,
+        }
+    }
+
+    /// Set the output sample rate, used when fed by DMA.
+    pub fn set_sample_rate(&mut self, sample_rate: DacSampleRate) {
+        GPIP.gpdac_config().modify(|reg| reg.gpdac_mode().set(sample_rate as _));
+    }
+
+    /// Set the format of words written by DMA, this is used the next time this DAC
+    /// is used as a DMA destination.
+    pub fn set_dma_format(&mut self, format: DacDmaFormat) {
+        self.dma_format = format;
+    }
+
+}
+
+impl<A, B> Drop for Dac<A, B> {
+    fn drop(&mut self) {
+        disable();
+    }
+}
+
+/// The DAC can be used as a DMA destination, words written depend on the format
+/// set with [`Dac::set_dma_format`]. Samples are paced by the configured sample
+/// rate.
+impl<A, B> DmaDstEndpoint for Dac<A, B> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+
+        let (sel_a, sel_b) = match self.dma_format {
+            DacDmaFormat::A | DacDmaFormat::AA => (1, 0),
+            DacDmaFormat::AB => (1, 1),
+        };
+
+        GPIP.gpdac_config().modify(|reg| {
+            reg.gpdac_ch_a_sel().set(sel_a);
+            reg.gpdac_ch_b_sel().set(sel_b);
+        });
+
+        GPIP.gpdac_dma_config().modify(|reg| {
+            reg.gpdac_dma_format().set(self.dma_format as _);
+            reg.gpdac_dma_tx_en().fill();
+        });
+
+        DmaEndpointConfig {
+            // GPIP TX request is used by the DAC.
+            peripheral: Some(DmaPeripheral::AdcTx),
+            data_width: DmaDataWidth::Word,
+            burst_size: DmaBurstSize::Incr1,
+            increment: DmaIncrement::Const,
+            addr: GPIP.gpdac_dma_wdata().0 as _,
+        }
+
+    }
+
+    fn close(&mut self) {
+        GPIP.gpdac_dma_config().modify(|reg| reg.gpdac_dma_tx_en().clear());
+        GPIP.gpdac_config().modify(|reg| {
+            reg.gpdac_ch_a_sel().clear();
+            reg.gpdac_ch_b_sel().clear();
+        });
+    }
+
+}
+
+
+/// A trait implemented on possible outputs of a DAC channel, `()` can be used to
+/// keep the channel internal.
+pub trait DacOutput<const CHANNEL: u8>: Sealed {
+
+    /// Configure the output, return true if the channel must be output to its pad.
+    fn configure(&mut self) -> bool;
+
+}
+
+impl<const CHANNEL: u8> DacOutput<CHANNEL> for () {
+    #[inline]
+    fn configure(&mut self) -> bool {
+        false
+    }
+}
+
+/// Internal macro to implement [`DacOutput`] on [`Pin`].
+macro_rules! impl_dac_output_pin {
+    ($channel:literal: $pin:literal) => {
+        impl DacOutput<$channel> for Pin<$pin, Alternate> {
+            #[inline]
+            fn configure(&mut self) -> bool {
+                let mut cfg = PinConfig::default();
+                cfg.set_function(PinFunction::Analog);
+                cfg.set_drive(PinDrive::Drive0);
+                cfg.set_smt(true);
+                self.set_config(cfg);
+                true
+            }
+        }
+    };
+}
+
+impl_dac_output_pin!(0: 11);
+impl_dac_output_pin!(1: 4);
+
+
+/// Internal function to disable the DAC.
+fn disable() {
+    GPIP.gpdac_dma_config().modify(|reg| reg.gpdac_dma_tx_en().clear());
+    GPIP.gpdac_config().modify(|reg| reg.gpdac_en().clear());
+    GLB.dac_cfg1().modify(|reg| {
+        reg.gpdac_a_en().clear();
+        reg.gpdac_ioa_en().clear();
+    });
+    GLB.dac_cfg2().modify(|reg| {
+        reg.gpdac_b_en().clear();
+        reg.gpdac_iob_en().clear();
+    });
+}
+
+
+/// Maximum value of a channel.
+pub const MAX_VALUE: u16 = 1023;
+
+/// Configuration structure for DAC initialization.
+#[derive(Debug, Clone)]
+pub struct DacConfig {
+    /// Reference voltage.
+    pub vref: DacVref,
+    /// Output sample rate, used when fed by DMA.
+    pub sample_rate: DacSampleRate,
+}
+
+impl DacConfig {
+
+    /// Create a new basic config with internal reference.
+    pub const fn new(sample_rate: DacSampleRate) -> Self {
+        Self {
+            vref: DacVref::Internal,
+            sample_rate,
+        }
+    }
+
+}
+
+/// Channels of the DAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DacChannel {
+    A,
+    B,
+}
+
+/// DAC voltage reference definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DacVref {
+    Internal = 0,
+    External = 1,
+}
+
+/// DAC output sample rate definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DacSampleRate {
+    Khz32 = 0,
+    Khz16 = 1,
+    Khz8 = 3,
+    Khz512 = 4,
+}
+
+/// Format of 32-bit words written to the DAC by DMA, each sample is in the low 10
+/// bits of a half-word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DacDmaFormat {
+    /// One sample of channel A in the low half-word.
+    A = 0,
+    /// One sample of channel A in the low half-word and one of channel B in the
+    /// high half-word.
+    AB = 1,
+    /// Two consecutive samples of channel A, the first one in the low half-word.
+    AA = 2,
+}
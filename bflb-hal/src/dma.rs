@@ -6,12 +6,27 @@
 //! uses.
 
 use core::cell::RefCell;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 use critical_section::{Mutex, CriticalSection};
 
-use crate::cache::{CacheAligned, clean_data_range, clean_invalidate_data_range};
+use crate::cache::{CacheAligned, LINE_SIZE, clean_data_range, clean_invalidate_data_range,
+    invalidate_data_range};
 use crate::arch::bl808::{DMA0, DMA1, DMA2, dma};
+use crate::sealed::Sealed;
+
+pub mod circular;
+pub mod allocator;
+pub mod memory;
+pub mod scoped;
+
+pub use memory::{memcpy, memset};
 
 /// This peripheral structure wraps all DMA ports available.
 pub struct Dma {
@@ -128,155 +143,512 @@ impl<const PORT: u8, const CHANNEL: u8> DmaAccess<PORT, CHANNEL> {
     /// implementors of [`DmaEndpoint`] for more information, note 
     /// that supported peripherals depends on the `PORT` used.
     /// 
+    /// Transfers longer than what a single channel configuration supports, or made
+    /// of multiple segments (see [`DmaChain`]), are split into a chain of linked 
+    /// list items (LLI) that are owned by the returned transfer.
+    /// 
     /// The returned [`DmaTransfer`] handle can be used to wait for 
     /// result and get back the source and destination endpoint in 
     /// order to reuse them.
     #[inline(never)]
     pub fn into_transfer<Src, Dst>(self, 
-        mut src: Src,
-        mut dst: Dst) -> DmaTransfer<PORT, CHANNEL, Src, Dst>
+        src: Src,
+        dst: Dst) -> DmaTransfer<PORT, CHANNEL, Src, Dst>
     where
         Src: DmaSrcEndpoint,
         Dst: DmaDstEndpoint,
     {
+        start(self, src, dst, false)
+    }
 
-        let src_config = unsafe { src.configure() };
-        let dst_config = unsafe { dst.configure() };
+}
 
-        let transfer_len;
-        let mut src_incr = false;
-        let mut dst_incr = false;
+impl<const PORT: u8, const CHANNEL: u8> Sealed for DmaAccess<PORT, CHANNEL> {}
 
-        match (src_config.increment, dst_config.increment) {
-            (DmaIncrement::Incr(src_len), DmaIncrement::Incr(dst_len)) => {
-                assert_eq!(src_len, dst_len, "source and destination length must be equal");
-                transfer_len = src_len;
-                src_incr = true;
-                dst_incr = true;
-            }
-            (DmaIncrement::Incr(src_len), DmaIncrement::Const) => {
-                transfer_len = src_len;
-                src_incr = true;
-            }
-            (DmaIncrement::Const, DmaIncrement::Incr(dst_len)) => {
-                transfer_len = dst_len;
-                dst_incr = true;
-            }
-            _ => {
-                panic!("both source and destination have undetermined length");
-            }
-        }
+impl<A: DmaChannelHandle> Sealed for &mut A {}
 
-        // TODO: Support for LLI transfers.
-        assert!(transfer_len <= 4064, "doing more than 4064 transfers is currently not supported");
+/// A mutable reference to a channel can be used to run a transfer that doesn't need
+/// to own the channel, see [`scoped::transfer`].
+impl<A: DmaChannelHandle> DmaChannelHandle for &mut A {
 
-        let port_regs = get_port_regs::<PORT>();
-        let channel_regs = get_channel_regs::<PORT, CHANNEL>();
+    #[inline(always)]
+    fn port(&self) -> u8 {
+        (**self).port()
+    }
 
-        // TODO: Guard this for concurrent calls.
-        port_regs.config().modify(|reg| {
-            reg.smdma_enable().fill();
-        });
+    #[inline(always)]
+    fn channel(&self) -> u8 {
+        (**self).channel()
+    }
 
-        // Temporarily disable the channel.
-        channel_regs.config().modify(|reg| {
-            reg.enable().clear();
-        });
+}
+
+impl<const PORT: u8, const CHANNEL: u8> DmaChannelHandle for DmaAccess<PORT, CHANNEL> {
+
+    #[inline(always)]
+    fn port(&self) -> u8 {
+        PORT
+    }
+
+    #[inline(always)]
+    fn channel(&self) -> u8 {
+        CHANNEL
+    }
+
+}
+
+
+/// Trait implemented on exclusive accesses to a DMA channel, either statically known
+/// ([`DmaAccess`]) or allocated at runtime ([`AnyDmaChannel`](allocator::AnyDmaChannel)).
+pub trait DmaChannelHandle: Sealed {
+
+    /// Get the DMA port of this channel.
+    fn port(&self) -> u8;
+
+    /// Get the number of this channel in its port.
+    fn channel(&self) -> u8;
+
+}
+
+
+/// Internal function to start a transfer on the given channel, see [`start_unchecked`].
+/// Borrowed endpoints are rejected at compile time because the returned transfer can
+/// be forgotten while still running.
+pub(crate) fn start<A, Src, Dst>(
+    access: A,
+    src: Src,
+    dst: Dst,
+    circular: bool,
+) -> DmaChannelTransfer<A, Src, Dst>
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+    const { assert!(!Src::BORROWED && !Dst::BORROWED, "borrowed endpoints can only be used in scoped transfers") };
+    start_unchecked(access, src, dst, circular)
+}
+
+/// Internal function to start a transfer on the given channel, in circular mode the
+/// linked list items loop forever and the terminal count is raised at the end of each
+/// destination segment instead of the end of the whole transfer.
+/// 
+/// The caller must ensure that borrowed endpoints outlive the transfer.
+pub(crate) fn start_unchecked<A, Src, Dst>(
+    access: A,
+    mut src: Src,
+    mut dst: Dst,
+    circular: bool,
+) -> DmaChannelTransfer<A, Src, Dst>
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
 
-        // Configure control parameters.
-        channel_regs.control().modify(|reg| {
+    let (port, channel) = (access.port(), access.channel());
 
-            reg.src_increment().set(src_incr as _);
-            reg.dst_increment().set(dst_incr as _);
-            reg.src_burst_size().set(src_config.burst_size as _);
-            reg.dst_burst_size().set(dst_config.burst_size as _);
-            reg.src_width().set(src_config.data_width as _);
-            reg.dst_width().set(dst_config.data_width as _);
+    let mut src_segments = Vec::new();
+    let mut dst_segments = Vec::new();
+    unsafe {
+        src.configure_segments(&mut src_segments);
+        dst.configure_segments(&mut dst_segments);
+    }
+
+    let src_peripheral = get_segments_peripheral(&src_segments);
+    let dst_peripheral = get_segments_peripheral(&dst_segments);
+
+    let port_regs = get_port_regs(port);
+    let channel_regs = get_channel_regs(port, channel);
+
+    // TODO: Guard this for concurrent calls.
+    port_regs.config().modify(|reg| {
+        reg.smdma_enable().fill();
+    });
+
+    // Temporarily disable the channel.
+    channel_regs.config().modify(|reg| {
+        reg.enable().clear();
+    });
 
-            reg.dst_add_mode().clear();
-            reg.dst_minus_mode().clear();
+    // The first item is directly loaded in the channel registers, the following
+    // ones are linked from it.
+    let items = build_lli(&src_segments, &dst_segments, channel_regs.control().get(), circular);
+    let (first, lli) = link_lli(items, circular);
 
-            reg.transfer_size().set(transfer_len as _);
+    channel_regs.control().set(first.control);
 
-            reg.tc_int_enable().fill();
+    channel_regs.config().modify(|reg| {
+
+        if let Some(src) = src_peripheral {
+            reg.src_peripheral().set(get_peripheral_id(port, src));
+        } else {
+            reg.src_peripheral().clear();
+        }
+
+        if let Some(dst) = dst_peripheral {
+            reg.dst_peripheral().set(get_peripheral_id(port, dst));
+        } else {
+            reg.dst_peripheral().clear();
+        }
 
+        reg.flow_control().set(match (src_peripheral, dst_peripheral) {
+            (None, None) => 0,
+            (None, Some(_)) => 1,
+            (Some(_), None) => 2,
+            (Some(_), Some(_)) => 3,
         });
+        
+        reg.lli_counter().clear();
+
+    });
+
+    // Interrupts are masked by default because we are in manual waiting mode.
+    // This will be modified if callback-based waiting is later used.
+    channel_regs.config().modify(|reg| {
+        reg.int_error_mask().fill();
+        reg.int_tc_mask().fill();
+    });
+
+    channel_regs.src_addr().set(first.src_addr);
+    channel_regs.dst_addr().set(first.dst_addr);
+    channel_regs.lli().set(first.next_lli_addr);
+
+    // Clear interrupt related to this channel.
+    port_regs.int_tc_clear().set_with(|reg| reg.set(channel, true));
+    port_regs.int_error_clear().set_with(|reg| reg.set(channel, true));
+
+    channel_regs.config().modify(|reg| {
+        reg.enable().fill();
+    });
+
+    DmaChannelTransfer {
+        src,
+        dst,
+        lli,
+        access,
+        waker: DmaWakerRegistration(None),
+    }
 
-        channel_regs.config().modify(|reg| {
+}
+
+
+/// Maximum number of transfers of a single linked list item, the hardware limit
+/// is 4095 but it's rounded down to keep the chunks aligned.
+const LLI_MAX_TRANSFERS: usize = 4064;
 
-            if let Some(src) = src_config.peripheral {
-                reg.src_peripheral().set(get_peripheral_id::<PORT>(src));
-            } else {
-                reg.src_peripheral().clear();
+/// Internal cursor over the segments of an endpoint, used to build linked list items.
+struct SegmentCursor<'a> {
+    segments: &'a [DmaEndpointConfig],
+    index: usize,
+    /// Number of transfers already done in the current segment.
+    offset: usize,
+}
+
+impl<'a> SegmentCursor<'a> {
+
+    fn new(segments: &'a [DmaEndpointConfig]) -> Self {
+        Self { segments, index: 0, offset: 0 }
+    }
+
+    /// Return the current segment, skipping exhausted ones, or `None` if all segments
+    /// are exhausted. A segment with constant address is never exhausted.
+    fn current(&mut self) -> Option<&'a DmaEndpointConfig> {
+        while let Some(segment) = self.segments.get(self.index) {
+            match segment.increment {
+                DmaIncrement::Incr(len) if self.offset >= len => {
+                    self.index += 1;
+                    self.offset = 0;
+                }
+                _ => return Some(segment),
             }
+        }
+        None
+    }
+
+    /// Return the address of the current segment at the current offset.
+    fn addr(&self, segment: &DmaEndpointConfig) -> u32 {
+        match segment.increment {
+            DmaIncrement::Const => segment.addr as _,
+            DmaIncrement::Incr(_) => (segment.addr + (self.offset << segment.data_width as usize)) as _,
+        }
+    }
+
+}
 
-            if let Some(dst) = dst_config.peripheral {
-                reg.dst_peripheral().set(get_peripheral_id::<PORT>(dst));
-            } else {
-                reg.dst_peripheral().clear();
+/// Internal function to split the given source and destination segments into linked
+/// list items of at most [`LLI_MAX_TRANSFERS`] transfers. A new item is also started
+/// each time a source or destination segment is exhausted. The returned items are
+/// not yet linked together and only the last one has terminal count interrupt, or
+/// the last one of each destination segment in circular mode.
+fn build_lli(
+    src_segments: &[DmaEndpointConfig],
+    dst_segments: &[DmaEndpointConfig],
+    base_control: dma::DmaChannelControl,
+    circular: bool,
+) -> Vec<dma::DmaChannelLli> {
+
+    let mut items = Vec::new();
+    let mut src_cursor = SegmentCursor::new(src_segments);
+    let mut dst_cursor = SegmentCursor::new(dst_segments);
+
+    loop {
+
+        let (src, dst) = match (src_cursor.current(), dst_cursor.current()) {
+            (Some(src), Some(dst)) => (src, dst),
+            // Reaching the end of one side is the end if the other side is constant.
+            (None, Some(DmaEndpointConfig { increment: DmaIncrement::Const, .. })) |
+            (Some(DmaEndpointConfig { increment: DmaIncrement::Const, .. }), None) |
+            (None, None) => break,
+            _ => panic!("source and destination length must be equal"),
+        };
+
+        let len = match (src.increment, dst.increment) {
+            (DmaIncrement::Incr(src_len), DmaIncrement::Incr(dst_len)) => {
+                (src_len - src_cursor.offset).min(dst_len - dst_cursor.offset)
+            }
+            (DmaIncrement::Incr(src_len), DmaIncrement::Const) => {
+                src_len - src_cursor.offset
             }
+            (DmaIncrement::Const, DmaIncrement::Incr(dst_len)) => {
+                dst_len - dst_cursor.offset
+            }
+            (DmaIncrement::Const, DmaIncrement::Const) => {
+                panic!("both source and destination have undetermined length");
+            }
+        };
 
-            reg.flow_control().set(match (src_config.peripheral, dst_config.peripheral) {
-                (None, None) => 0,
-                (None, Some(_)) => 1,
-                (Some(_), None) => 2,
-                (Some(_), Some(_)) => 3,
-            });
-            
-            reg.lli_counter().clear();
+        let len = len.min(LLI_MAX_TRANSFERS);
+        items.push(build_lli_item(src, dst, src_cursor.addr(src), dst_cursor.addr(dst), len, base_control));
 
-        });
+        src_cursor.offset += len;
+        dst_cursor.offset += len;
 
-        // Interrupts are masked by default because we are in manual waiting mode.
-        // This will be modified if callback-based waiting is later used.
-        channel_regs.config().modify(|reg| {
-            reg.int_error_mask().fill();
-            reg.int_tc_mask().fill();
-        });
+        if circular && dst.increment == DmaIncrement::Incr(dst_cursor.offset) {
+            if let Some(last) = items.last_mut() {
+                last.control.tc_int_enable().fill();
+            }
+        }
+
+    }
 
-        channel_regs.src_addr().set(src_config.addr as _);
-        channel_regs.dst_addr().set(dst_config.addr as _);
+    // All segments are empty, we still need one item to run an empty transfer.
+    if items.is_empty() {
+        let (src, dst) = (&src_segments[0], &dst_segments[0]);
+        items.push(build_lli_item(src, dst, src.addr as _, dst.addr as _, 0, base_control));
+    }
 
-        // Clear interrupt related to this channel.
-        port_regs.int_tc_clear().set_with(|reg| reg.set(CHANNEL, true));
-        port_regs.int_error_clear().set_with(|reg| reg.set(CHANNEL, true));
+    if let Some(last) = items.last_mut() {
+        last.control.tc_int_enable().fill();
+    }
 
-        channel_regs.config().modify(|reg| {
-            reg.enable().fill();
-        });
+    items
 
-        DmaTransfer {
-            src,
-            dst,
-        }
+}
 
+/// Internal function to build a single linked list item, not yet linked.
+fn build_lli_item(
+    src: &DmaEndpointConfig,
+    dst: &DmaEndpointConfig,
+    src_addr: u32,
+    dst_addr: u32,
+    len: usize,
+    base_control: dma::DmaChannelControl,
+) -> dma::DmaChannelLli {
+
+    let mut control = base_control;
+    control.src_increment().set(matches!(src.increment, DmaIncrement::Incr(_)) as _);
+    control.dst_increment().set(matches!(dst.increment, DmaIncrement::Incr(_)) as _);
+    control.src_burst_size().set(src.burst_size as _);
+    control.dst_burst_size().set(dst.burst_size as _);
+    control.src_width().set(src.data_width as _);
+    control.dst_width().set(dst.data_width as _);
+    control.dst_add_mode().clear();
+    control.dst_minus_mode().clear();
+    control.transfer_size().set(len as _);
+    control.tc_int_enable().clear();
+
+    dma::DmaChannelLli {
+        src_addr,
+        dst_addr,
+        next_lli_addr: 0,
+        control,
+    }
+
+}
+
+/// Internal function to link the given items, returning the first item to be loaded
+/// in channel registers and the following items to keep in memory. These items are
+/// cleaned from data cache because they are read from memory by the DMA controller.
+/// 
+/// In circular mode, the first item is also kept in memory so that the last item
+/// can loop back to it.
+fn link_lli(mut items: Vec<dma::DmaChannelLli>, circular: bool) -> (dma::DmaChannelLli, Box<[dma::DmaChannelLli]>) {
+
+    let mut first = if circular { items[0] } else { items.remove(0) };
+    let mut items = items.into_boxed_slice();
+
+    let base_addr = items.as_ptr() as usize;
+    let item_size = core::mem::size_of::<dma::DmaChannelLli>();
+    let count = items.len();
+
+    for (i, item) in items.iter_mut().enumerate() {
+        item.next_lli_addr = if i + 1 < count {
+            (base_addr + (i + 1) * item_size) as _
+        } else if circular {
+            base_addr as _
+        } else {
+            0
+        };
     }
 
+    first.next_lli_addr = if circular {
+        (base_addr + (1 % count) * item_size) as _
+    } else if count != 0 {
+        base_addr as _
+    } else {
+        0
+    };
+
+    // SAFETY: Items are owned by us and only cleaned, not invalidated.
+    unsafe { clean_data_range(base_addr, count * item_size) }
+
+    (first, items)
+
+}
+
+/// Internal function to get the common peripheral of all segments of an endpoint.
+fn get_segments_peripheral(segments: &[DmaEndpointConfig]) -> Option<DmaPeripheral> {
+    assert!(!segments.is_empty(), "endpoint has no segment");
+    let peripheral = segments[0].peripheral;
+    assert!(segments.iter().all(|segment| segment.peripheral == peripheral), 
+        "all segments of an endpoint must use the same peripheral");
+    peripheral
 }
 
 
 /// Represent a running DMA transfer that is currently running or
 /// already finished. Once this transfer is done, it can be used to
 /// retrieve the original source and destination endpoints to reuse
-/// them.
-pub struct DmaTransfer<const PORT: u8, const CHANNEL: u8, Src, Dst> {
+/// them, as well as the channel access.
+/// 
+/// If dropped while still running, the transfer is aborted before its endpoints are
+/// dropped, see [`abort`](Self::abort).
+pub struct DmaChannelTransfer<A, Src, Dst>
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint
+{
     /// Source endpoint of the transfer.
     src: Src,
     /// Destination endpoint of the transfer.
     dst: Dst,
+    /// Linked list items following the first one, empty if the whole transfer fits
+    /// in the channel registers.
+    lli: Box<[dma::DmaChannelLli]>,
+    /// Access to the channel running the transfer.
+    access: A,
+    /// Waker registered when polled as a future.
+    waker: DmaWakerRegistration,
 }
 
-impl<const PORT: u8, const CHANNEL: u8, Src, Dst> DmaTransfer<PORT, CHANNEL, Src, Dst>
+/// A DMA transfer on a statically known channel, see [`DmaAccess::into_transfer`].
+pub type DmaTransfer<const PORT: u8, const CHANNEL: u8, Src, Dst> = DmaChannelTransfer<DmaAccess<PORT, CHANNEL>, Src, Dst>;
+
+impl<A, Src, Dst> DmaChannelTransfer<A, Src, Dst>
 where
+    A: DmaChannelHandle,
     Src: DmaSrcEndpoint,
     Dst: DmaDstEndpoint
 {
 
-    /// Return true if the transfer is completed and can be destructured.
+    /// Return true if the transfer is completed and can be destructured, this is also
+    /// the case if the transfer has been stopped by an error, see [`error`](Self::error).
     #[inline]
     pub fn completed(&self) -> bool {
-        // We know that the channel is interrupt-masked, so we must use raw register.
-        get_port_regs::<PORT>().raw_int_tc_status().get().get(CHANNEL)
+        // The controller disables the channel at the end of the transfer or on error.
+        !get_port_regs(self.access.port()).channel_enable_status().get().get(self.access.channel())
+    }
+
+    /// Return the error that stopped this transfer, if any. This should be checked
+    /// before destructuring a completed transfer, because the error is cleared when
+    /// the channel is reused.
+    #[inline]
+    pub fn error(&self) -> Option<DmaError> {
+        // The error interrupt is only masked by the handler, so the raw status is kept.
+        get_port_regs(self.access.port()).raw_int_error_status().get().get(self.access.channel())
+            .then_some(DmaError::Bus)
+    }
+
+    /// Return the number of data transfers (of the configured data width) left to
+    /// complete this transfer.
+    pub fn remaining(&self) -> usize {
+
+        let channel_regs = get_channel_regs(self.access.port(), self.access.channel());
+        let current = channel_regs.control().get().transfer_size().get() as usize;
+
+        // The LLI register points to the next item to load, all items from this one
+        // are remaining.
+        let next_addr = channel_regs.lli().get() as usize;
+        let base_addr = self.lli.as_ptr() as usize;
+        if next_addr == 0 || self.lli.is_empty() {
+            return current;
+        }
+
+        let index = (next_addr - base_addr) / core::mem::size_of::<dma::DmaChannelLli>();
+        current + self.lli[index..].iter()
+            .map(|item| {
+                let mut control = item.control;
+                control.transfer_size().get() as usize
+            })
+            .sum::<usize>()
+
+    }
+
+    /// Abort this transfer and get back its original components, the channel is
+    /// halted and data already read from the source is flushed to the destination
+    /// before disabling it. The number of transfers that have not been done can be
+    /// read with [`remaining`](Self::remaining) before calling this.
+    /// 
+    /// If a callback has been registered by polling this transfer as a future, it
+    /// is dropped without being called.
+    pub fn abort(mut self) -> (Src, Dst, A) {
+
+        self.halt();
+
+        let channel_regs = get_channel_regs(self.access.port(), self.access.channel());
+        // SAFETY: The channel is halted with no more data in its FIFO, so endpoints
+        // are no longer accessed.
+        let ret = unsafe { self.destruct() };
+        channel_regs.config().modify(|reg| reg.halt().clear());
+        ret
+
+    }
+
+    /// Internal function to halt the channel and wait until data already read from
+    /// the source is flushed to the destination, its interrupts are then masked and
+    /// cleared, and its callback is dropped.
+    fn halt(&mut self) {
+
+        let (port, channel) = (self.access.port(), self.access.channel());
+        let channel_regs = get_channel_regs(port, channel);
+        channel_regs.config().modify(|reg| {
+            reg.int_tc_mask().fill();
+            reg.int_error_mask().fill();
+            reg.halt().fill();
+        });
+        while channel_regs.config().get().active().get() != 0 {}
+
+        let port_regs = get_port_regs(port);
+        port_regs.int_tc_clear().set_with(|reg| reg.set(channel, true));
+        port_regs.int_error_clear().set_with(|reg| reg.set(channel, true));
+
+        self.waker.0 = None;
+        if has_interrupt_support(port) {
+            clear_callback(port, channel);
+        }
+
     }
 
     /// Internal function to destruct this transfer to its original
@@ -290,30 +662,41 @@ where
     /// SAFETY: Caller must ensure that the source and destination endpoint will no longer
     /// be accessed in any way by the DMA controller.
     #[inline]
-    unsafe fn destruct(mut self) -> (Src, Dst, DmaAccess<PORT, CHANNEL>) {
+    unsafe fn destruct(self) -> (Src, Dst, A) {
+
+        // The transfer is not dropped, so the channel is not halted.
+        let mut this = ManuallyDrop::new(self);
 
         // Disabling the channel ofc...
-        get_channel_regs::<PORT, CHANNEL>()
+        get_channel_regs(this.access.port(), this.access.channel())
             .config().modify(|reg| {
                 reg.enable().clear();
             });
 
-        self.src.close();
-        self.dst.close();
+        this.src.close();
+        this.dst.close();
 
-        (self.src, self.dst, DmaAccess(()))
+        // SAFETY: Each field is either dropped or moved out once, and the transfer
+        // itself is never dropped.
+        unsafe {
+            core::ptr::drop_in_place(&mut this.lli);
+            core::ptr::drop_in_place(&mut this.waker);
+            (core::ptr::read(&this.src), core::ptr::read(&this.dst), core::ptr::read(&this.access))
+        }
 
     }
 
     /// Try destructuring this transfer into its original components.
     /// 
-    /// This will only succeed if the DMA transfer is completed ([`completed`]).
-    pub fn try_wait(self) -> Result<(Src, Dst, DmaAccess<PORT, CHANNEL>), Self> {
+    /// This will only succeed if the DMA transfer is completed ([`completed`](Self::completed)),
+    /// possibly with an error that should be checked before ([`error`](Self::error)).
+    pub fn try_wait(self) -> Result<(Src, Dst, A), Self> {
         if self.completed() {
             // We know that this channel is interrupt-masked, so it should not generate
-            // interrupts. So we have to manually clear the terminal count bit here.
-            get_port_regs::<PORT>().int_tc_clear()
-                .set_with(|port| port.set(CHANNEL, true));
+            // interrupts. So we have to manually clear the status bits here.
+            let port_regs = get_port_regs(self.access.port());
+            port_regs.int_tc_clear().set_with(|reg| reg.set(self.access.channel(), true));
+            port_regs.int_error_clear().set_with(|reg| reg.set(self.access.channel(), true));
             // SAFETY: This is safe because we know that the transfer
             // is completed, so we can destruct.
             Ok(unsafe { self.destruct() })
@@ -326,7 +709,7 @@ where
     /// Indefinitely wait for completion of this DMA transfer and then 
     /// destruct the transfer into its original components. 
     /// See [`try_destruct`].
-    pub fn wait(self) -> (Src, Dst, DmaAccess<PORT, CHANNEL>) {
+    pub fn wait(self) -> (Src, Dst, A) {
         let mut transfer = self;
         loop {
             transfer = match transfer.try_wait() {
@@ -337,20 +720,26 @@ where
     }
 
     /// Wait for completion of this DMA transfer, calling the given callback when 
-    /// completed.
+    /// completed. The callback is also called if the transfer is stopped by an
+    /// error, which is not reported.
     /// 
     /// Additional constraints are put on source and destination: they need to be [`Send`]
     /// with a static lifetime because they need to be saved appart in a static location.
     /// 
     /// *This method is only available on the CPU type that supports
     /// interrupts for the current DMA port.*
-    pub fn wait_callback<F>(self, callback: F)
+    pub fn wait_callback<F>(mut self, callback: F)
     where
-        DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
-        F: FnOnce(Src, Dst, DmaAccess<PORT, CHANNEL>) + Send + 'static,
+        A: DmaInterruptSupport,
+        F: FnOnce(Src, Dst, A) + Send + 'static,
         Self: Send + 'static,
     {
 
+        let (port, channel) = (self.access.port(), self.access.channel());
+
+        // The waker callback, if any, is replaced below.
+        self.waker.0 = None;
+
         // The closure here are a bit tricky, because both 'self' and 'callback' are 
         // destructured by this closure, so this closure is forced to be an FnOnce.
         // PS: This closure will be called upon interrupt.
@@ -365,28 +754,204 @@ where
             if let Some(once_wrapper) = once_wrapper.take() {
                 once_wrapper();
             }
+            false
         });
 
-        critical_section::with(|cs| {
+        set_callback(port, channel, wrapper);
+        
+    }
+
+}
 
-            <DmaAccess<PORT, CHANNEL> as DmaInterruptSupport>
-                ::with_callback(move |slot| {
-                    *slot = Some(wrapper);
-                }, cs);
-            
-            // Unmask interrupt for this channel so it will now generate interrupts.
-            get_channel_regs::<PORT, CHANNEL>().config()
-                .modify(|config| config.int_tc_mask().clear());
 
-        });
-        
+/// The transfer may still be running when dropped, for example when a future is
+/// cancelled on timeout, so it's aborted before the endpoints and linked list items
+/// are freed.
+impl<A, Src, Dst> Drop for DmaChannelTransfer<A, Src, Dst>
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint
+{
+    fn drop(&mut self) {
+
+        self.halt();
+
+        get_channel_regs(self.access.port(), self.access.channel())
+            .config().modify(|reg| {
+                reg.enable().clear();
+                reg.halt().clear();
+            });
+
+        self.src.close();
+        self.dst.close();
+
+    }
+}
+
+
+/// Polling the transfer registers a callback that wakes the task on completion or
+/// error, the transfer can then be destructured with [`wait`](DmaChannelTransfer::wait)
+/// without blocking:
+/// 
+/// ```ignore
+/// (&mut transfer).await?;
+/// let (src, dst, access) = transfer.wait();
+/// ```
+/// 
+/// *This implementation is only available on the CPU type that supports
+/// interrupts for the current DMA port.*
+impl<A, Src, Dst> Future for DmaChannelTransfer<A, Src, Dst>
+where
+    A: DmaInterruptSupport,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+
+    type Output = Result<(), DmaError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+
+        // SAFETY: Only the waker registration is modified, it's not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.completed() {
+            this.waker.clear();
+            return Poll::Ready(match this.error() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            });
+        }
+
+        // The interrupt is raised if the transfer completed in the meantime, because
+        // the raw status is kept until unmasked. The callback is only replaced if the
+        // task changed, it's kept until completion or until the transfer is dropped.
+        let (port, channel) = (this.access.port(), this.access.channel());
+        let registration = &mut this.waker.0;
+        if !registration.as_ref().is_some_and(|(_, _, waker)| waker.will_wake(cx.waker())) {
+            let waker = cx.waker().clone();
+            set_callback(port, channel, Box::new(move || {
+                waker.wake_by_ref();
+                true
+            }));
+            *registration = Some((port, channel, cx.waker().clone()));
+        }
+
+        Poll::Pending
+
+    }
+
+}
+
+
+/// Internal registration of the waker of a transfer polled as a future, its callback
+/// is removed on completion or when the transfer is destructured or dropped.
+struct DmaWakerRegistration(Option<(u8, u8, Waker)>);
+
+impl DmaWakerRegistration {
+
+    /// Remove the callback of the registered waker, if any.
+    fn clear(&mut self) {
+        if let Some((port, channel, _)) = self.0.take() {
+            clear_callback(port, channel);
+        }
+    }
+
+}
+
+impl Drop for DmaWakerRegistration {
+    fn drop(&mut self) {
+        self.clear();
     }
+}
+
 
+/// Error that stopped a DMA transfer before its completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// A bus error occurred while accessing the source or destination, the
+    /// address is likely not mapped or not accessible by the controller.
+    Bus,
 }
 
 
-/// Type alias for a boxed closure used as a DMA transfer callback.
-type DmaCallback = Box<dyn FnMut() + Send>;
+/// Internal function to set the callback of a channel and unmask its terminal count
+/// and error interrupts so that the callback is called from interrupt.
+pub(crate) fn set_callback(port: u8, channel: u8, callback: DmaCallback) {
+    critical_section::with(|cs| {
+
+        with_callback(port, channel, move |slot| {
+            *slot = Some(callback);
+        }, cs);
+        
+        // Unmask interrupt for this channel so it will now generate interrupts.
+        get_channel_regs(port, channel).config()
+            .modify(|config| {
+                config.int_tc_mask().clear();
+                config.int_error_mask().clear();
+            });
+
+    });
+}
+
+/// Internal function to remove the callback of a channel and mask its terminal count
+/// and error interrupts, the callback is dropped without being called.
+pub(crate) fn clear_callback(port: u8, channel: u8) {
+    let callback = critical_section::with(|cs| {
+        get_channel_regs(port, channel).config()
+            .modify(|config| {
+                config.int_tc_mask().fill();
+                config.int_error_mask().fill();
+            });
+        let mut callback = None;
+        with_callback(port, channel, |slot| callback = slot.take(), cs);
+        callback
+    });
+    // Dropped outside of the critical section.
+    drop(callback);
+}
+
+/// Internal function to abort a transfer that has been given to
+/// [`DmaTransfer::wait_callback`], the channel is halted and disabled and the pending
+/// callback is dropped without being called.
+pub(crate) fn abort_callback(port: u8, channel: u8) {
+
+    let channel_regs = get_channel_regs(port, channel);
+
+    let callback = critical_section::with(|cs| {
+
+        channel_regs.config().modify(|reg| {
+            reg.int_tc_mask().fill();
+            reg.int_error_mask().fill();
+            reg.halt().fill();
+        });
+        while channel_regs.config().get().active().get() != 0 {}
+
+        channel_regs.config().modify(|reg| {
+            reg.enable().clear();
+            reg.halt().clear();
+        });
+
+        let port_regs = get_port_regs(port);
+        port_regs.int_tc_clear().set_with(|reg| reg.set(channel, true));
+        port_regs.int_error_clear().set_with(|reg| reg.set(channel, true));
+
+        let mut callback = None;
+        with_callback(port, channel, |slot| callback = slot.take(), cs);
+        callback
+
+    });
+
+    // Dropped outside of the critical section, the transfer owned by the callback
+    // halts the channel again when dropped.
+    drop(callback);
+
+}
+
+
+/// Type alias for a boxed closure used as a DMA transfer callback, it returns true if
+/// it should be kept for the next terminal count (circular transfers).
+pub(crate) type DmaCallback = Box<dyn FnMut() -> bool + Send>;
 /// Internal type alias for a callbacks array.
 type DmaCallbacks<const CHANNELS: usize> = [Mutex<RefCell<Option<DmaCallback>>>; CHANNELS];
 /// Default value: no callback.
@@ -401,34 +966,43 @@ static DMA1_CALLBACKS: DmaCallbacks<4> = [NO_CALLBACK; 4];
 #[cfg(feature = "bl808-d0")]
 static DMA2_CALLBACKS: DmaCallbacks<8> = [NO_CALLBACK; 8];
 
+/// Internal function to access the callback slot of a channel, this panics if the
+/// port doesn't support interrupts on the current chip.
+fn with_callback(port: u8, channel: u8, func: impl FnOnce(&mut Option<DmaCallback>), cs: CriticalSection) {
+    let callbacks: &[Mutex<RefCell<Option<DmaCallback>>>] = match port {
+        #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+        0 => &DMA0_CALLBACKS,
+        #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+        1 => &DMA1_CALLBACKS,
+        #[cfg(feature = "bl808-d0")]
+        2 => &DMA2_CALLBACKS,
+        _ => panic!("no interrupt support for DMA port {port}")
+    };
+    func(&mut callbacks[channel as usize].borrow_ref_mut(cs));
+}
 
-/// Trait implemented on DMA ports that support interrupts on the current chip.
-pub trait DmaInterruptSupport {
-    /// SAFETY: Caller must ensure that this function is called in an interrupt-free 
-    /// context and is the only possible owner of the DMA channel.
-    fn with_callback(func: impl FnOnce(&mut Option<DmaCallback>), cs: CriticalSection);
+/// Return true if the given DMA port supports interrupts on the current chip.
+pub(crate) const fn has_interrupt_support(port: u8) -> bool {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    { port == 0 || port == 1 }
+    #[cfg(feature = "bl808-d0")]
+    { port == 2 }
 }
 
+
+/// Trait implemented on DMA channels that support interrupts on the current chip.
+pub trait DmaInterruptSupport: DmaChannelHandle { }
+
 #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
-impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<0, CHANNEL> {
-    fn with_callback(func: impl FnOnce(&mut Option<DmaCallback>), cs: CriticalSection) {
-        func(&mut DMA0_CALLBACKS[CHANNEL as usize].borrow_ref_mut(cs));
-    }
-}
+impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<0, CHANNEL> { }
 
 #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
-impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<1, CHANNEL> {
-    fn with_callback(func: impl FnOnce(&mut Option<DmaCallback>), cs: CriticalSection) {
-        func(&mut DMA1_CALLBACKS[CHANNEL as usize].borrow_ref_mut(cs));
-    }
-}
+impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<1, CHANNEL> { }
 
 #[cfg(feature = "bl808-d0")]
-impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<2, CHANNEL> {
-    fn with_callback(func: impl FnOnce(&mut Option<DmaCallback>), cs: CriticalSection) {
-        func(&mut DMA2_CALLBACKS[CHANNEL as usize].borrow_ref_mut(cs));
-    }
-}
+impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<2, CHANNEL> { }
+
+impl<A: DmaInterruptSupport> DmaInterruptSupport for &mut A { }
 
 
 /// Internal generic handler for DMA ports. This handler should be called only for DMA
@@ -436,19 +1010,37 @@ impl<const CHANNEL: u8> DmaInterruptSupport for DmaAccess<2, CHANNEL> {
 #[inline(never)]
 fn dma_handler(port_regs: dma::Dma, callbacks: &[Mutex<RefCell<Option<DmaCallback>>>], cs: CriticalSection) {
 
-    // Get the status and clear all status.
-    let status = port_regs.int_tc_status().get();
-    port_regs.int_tc_clear().set(status);
+    // Get the status and clear all terminal count status.
+    let tc_status = port_regs.int_tc_status().get();
+    port_regs.int_tc_clear().set(tc_status);
+
+    // Errors are only masked, so the raw status can later be reported to the user,
+    // it's cleared when the channel is reused.
+    let error_status = port_regs.int_error_status().get();
+    for i in 0..callbacks.len() as u8 {
+        if error_status.get(i) {
+            port_regs.channel(i as usize).config().modify(|reg| reg.int_error_mask().fill());
+        }
+    }
 
     // Iterate over all callbacks and check if the corresponding interrupt bit has been
     // set, then we remove the callback and call it.
-    for (i, callback) in callbacks.iter().enumerate() {
-        if status.get(i as u8) {
-            if let Some(mut callback) = callback.borrow_ref_mut(cs).take() {
+    for (i, slot) in callbacks.iter().enumerate() {
+        if tc_status.get(i as u8) || error_status.get(i as u8) {
+            // The slot is released before calling, so the callback can start a new
+            // transfer with a callback on the same channel.
+            let callback = slot.borrow_ref_mut(cs).take();
+            if let Some(mut callback) = callback {
                 // The callback will destruct and close the DMA channel, it's safe because
                 // we previously cleared the terminal count interrupt so it should not
                 // spin interrupt.
-                callback();
+                if callback() {
+                    // Circular transfers keep their callback, unless replaced meanwhile.
+                    let mut slot = slot.borrow_ref_mut(cs);
+                    if slot.is_none() {
+                        *slot = Some(callback);
+                    }
+                }
             }
         }
     }
@@ -458,19 +1050,19 @@ fn dma_handler(port_regs: dma::Dma, callbacks: &[Mutex<RefCell<Option<DmaCallbac
 /// Interrupt handler for DMA0 interrupts on M0/LP.
 #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
 pub(crate) fn dma0_handler(_code: usize, cs: CriticalSection) {
-    dma_handler(get_port_regs::<0>(), &DMA0_CALLBACKS[..], cs);
+    dma_handler(get_port_regs(0), &DMA0_CALLBACKS[..], cs);
 }
 
 /// Interrupt handler for DMA1 interrupts on M0/LP.
 #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
 pub(crate) fn dma1_handler(_code: usize, cs: CriticalSection) {
-    dma_handler(get_port_regs::<1>(), &DMA1_CALLBACKS[..], cs);
+    dma_handler(get_port_regs(1), &DMA1_CALLBACKS[..], cs);
 }
 
 /// Interrupt handler for DMA1 interrupts on M0/LP.
 #[cfg(feature = "bl808-d0")]
 pub(crate) fn dma2_handler(_code: usize, cs: CriticalSection) {
-    dma_handler(get_port_regs::<2>(), &DMA2_CALLBACKS[..], cs);
+    dma_handler(get_port_regs(2), &DMA2_CALLBACKS[..], cs);
 }
 
 
@@ -512,6 +1104,21 @@ pub trait DmaSrcEndpoint {
     /// during the DMA transfer.
     unsafe fn configure(&mut self) -> DmaEndpointConfig;
 
+    /// Set to true by endpoints that borrow memory for a limited lifetime, these can
+    /// only be used in [`scoped`] transfers, that cannot be forgotten while running.
+    const BORROWED: bool = false;
+
+    /// Configure the endpoint as a list of segments that are transferred one after the
+    /// other, by default this is a single segment returned by [`configure`]. All
+    /// segments must use the same peripheral.
+    /// 
+    /// SAFETY: Same as [`configure`] for each segment.
+    /// 
+    /// [`configure`]: Self::configure
+    unsafe fn configure_segments(&mut self, segments: &mut Vec<DmaEndpointConfig>) {
+        segments.push(unsafe { self.configure() });
+    }
+
     /// Close this endpoint from a DMA transfer, this is called when the DMA transfer is 
     /// stopped and destructured.
     fn close(&mut self) {}
@@ -529,12 +1136,76 @@ pub trait DmaDstEndpoint {
     /// during the DMA transfer.
     unsafe fn configure(&mut self) -> DmaEndpointConfig;
 
+    /// Set to true by endpoints that borrow memory for a limited lifetime, these can
+    /// only be used in [`scoped`] transfers, that cannot be forgotten while running.
+    const BORROWED: bool = false;
+
+    /// Configure the endpoint as a list of segments that are transferred one after the
+    /// other, by default this is a single segment returned by [`configure`]. All
+    /// segments must use the same peripheral.
+    /// 
+    /// SAFETY: Same as [`configure`] for each segment.
+    /// 
+    /// [`configure`]: Self::configure
+    unsafe fn configure_segments(&mut self, segments: &mut Vec<DmaEndpointConfig>) {
+        segments.push(unsafe { self.configure() });
+    }
+
     /// Close this endpoint from a DMA transfer, this is called when the DMA transfer is 
     /// stopped and destructured.
     fn close(&mut self) {}
 
 }
 
+/// A chain of endpoints used for scatter-gather transfers, each endpoint is a segment
+/// of the transfer and they are transferred one after the other by chaining linked
+/// list items. All endpoints must use the same peripheral, if any.
+pub struct DmaChain<E>(pub Vec<E>);
+
+impl<E: DmaSrcEndpoint> DmaSrcEndpoint for DmaChain<E> {
+
+    const BORROWED: bool = E::BORROWED;
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        panic!("a chain can only be configured as segments")
+    }
+
+    unsafe fn configure_segments(&mut self, segments: &mut Vec<DmaEndpointConfig>) {
+        for endpoint in &mut self.0 {
+            unsafe { endpoint.configure_segments(segments) }
+        }
+    }
+
+    fn close(&mut self) {
+        for endpoint in &mut self.0 {
+            endpoint.close();
+        }
+    }
+
+}
+
+impl<E: DmaDstEndpoint> DmaDstEndpoint for DmaChain<E> {
+
+    const BORROWED: bool = E::BORROWED;
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        panic!("a chain can only be configured as segments")
+    }
+
+    unsafe fn configure_segments(&mut self, segments: &mut Vec<DmaEndpointConfig>) {
+        for endpoint in &mut self.0 {
+            unsafe { endpoint.configure_segments(segments) }
+        }
+    }
+
+    fn close(&mut self) {
+        for endpoint in &mut self.0 {
+            endpoint.close();
+        }
+    }
+
+}
+
 /// Implementation for string slices.
 impl DmaSrcEndpoint for &'static str {
 
@@ -669,17 +1340,207 @@ pub trait DmaPrimitiveType {
 impl<T: DmaPrimitiveType> DmaSrcEndpoint for &'static [T] {
 
     unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        // SAFETY: Read comment above (for &'static str).
+        unsafe { configure_src_slice(self) }
+    }
+
+}
+
+/// Implementation for static mutable slices, the access is exclusive for the whole
+/// program so it can't be aliased while the transfer is running.
+impl<T: DmaPrimitiveType> DmaSrcEndpoint for &'static mut [T] {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { configure_src_slice(self) }
+    }
+
+}
+
+/// Implementation for static mutable slices, see [`configure_dst_slice`] for the cache
+/// alignment requirements.
+impl<T: DmaPrimitiveType> DmaDstEndpoint for &'static mut [T] {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { configure_dst_slice(self) }
+    }
+
+    fn close(&mut self) {
+        close_dst_slice(self);
+    }
+
+}
+
+/// Implementation for vectors, the heap buffer doesn't move with the vector so it can
+/// be transferred while the vector is owned by the transfer. Only initialized elements
+/// (up to the length) are transferred.
+impl<T: DmaPrimitiveType> DmaSrcEndpoint for Vec<T> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { configure_src_slice(self) }
+    }
+
+}
+
+/// Implementation for cache aligned boxed slices with runtime length, see the
+/// implementation for cache aligned boxed types. Vectors cannot be destinations
+/// because their heap buffer is not aligned to cache lines, a boxed cache aligned
+/// array can be coerced to such a slice instead.
+impl<T: DmaPrimitiveType> DmaDstEndpoint for Box<CacheAligned<[T]>> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+
+        // SAFETY: The wrapper is aligned and padded to cache lines, so the slice can
+        // be configured with the size of the whole wrapper.
+        unsafe {
+            let (self_addr, self_size) = cache_aligned_range(self);
+            clean_invalidate_data_range(self_addr, self_size);
+        }
+
         DmaEndpointConfig {
             peripheral: None,
             data_width: T::data_width(),
             burst_size: T::burst_size(),
-            increment: DmaIncrement::Incr(self.len()),
-            addr: self.as_ptr() as _
+            increment: DmaIncrement::Incr(self.0.len()),
+            addr: self.0.as_ptr() as usize,
         }
+
+    }
+
+    fn close(&mut self) {
+        let (self_addr, self_size) = cache_aligned_range(self);
+        // SAFETY: The wrapper is aligned to cache lines and exclusively owned.
+        unsafe { invalidate_data_range(self_addr, self_size) }
+    }
+
+}
+
+/// Internal function to get the address and size of a cache aligned boxed slice,
+/// including the padding up to the cache line boundary.
+fn cache_aligned_range<T>(buffer: &CacheAligned<[T]>) -> (usize, usize) {
+    (buffer as *const CacheAligned<[T]> as *const u8 as usize, core::mem::size_of_val(buffer))
+}
+
+/// An endpoint for any buffer type that can be viewed as a slice of primitive types
+/// through [`AsRef`] (source) or [`AsMut`] (destination). The buffer is boxed so that
+/// its address doesn't change while the transfer is moved.
+pub struct DmaBuffer<B, T> {
+    buffer: Box<B>,
+    _type: PhantomData<T>,
+}
+
+impl<B, T> DmaBuffer<B, T> {
+
+    /// Wrap the given buffer.
+    pub fn new(buffer: B) -> Self {
+        Self { buffer: Box::new(buffer), _type: PhantomData }
+    }
+
+    /// Get a reference to the wrapped buffer.
+    #[inline]
+    pub fn get(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Get back the wrapped buffer.
+    pub fn into_inner(self) -> B {
+        *self.buffer
+    }
+
+}
+
+impl<B, T> DmaSrcEndpoint for DmaBuffer<B, T>
+where
+    B: AsRef<[T]> + 'static,
+    T: DmaPrimitiveType,
+{
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { configure_src_slice((*self.buffer).as_ref()) }
     }
 
 }
 
+impl<B, T> DmaDstEndpoint for DmaBuffer<B, T>
+where
+    B: AsMut<[T]> + 'static,
+    T: DmaPrimitiveType,
+{
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        unsafe { configure_dst_slice((*self.buffer).as_mut()) }
+    }
+
+    fn close(&mut self) {
+        close_dst_slice((*self.buffer).as_mut());
+    }
+
+}
+
+/// Internal function to configure a slice as a source endpoint, the cache lines
+/// overlapping the slice are cleaned so that the latest data is visible to the DMA
+/// controller.
+/// 
+/// SAFETY: The slice must not be modified while the transfer is running.
+unsafe fn configure_src_slice<T: DmaPrimitiveType>(slice: &[T]) -> DmaEndpointConfig {
+
+    let addr = slice.as_ptr() as usize;
+    let size = core::mem::size_of_val(slice);
+    unsafe { clean_src_range(addr, size) }
+
+    DmaEndpointConfig {
+        peripheral: None,
+        data_width: T::data_width(),
+        burst_size: T::burst_size(),
+        increment: DmaIncrement::Incr(slice.len()),
+        addr,
+    }
+
+}
+
+/// Internal function to configure a slice as a destination endpoint. The slice must
+/// start and end on cache line boundaries, otherwise this function panics, because
+/// other data sharing these cache lines could be discarded when invalidating them, or
+/// overwrite the transferred data when evicted. The lines are cleaned and invalidated
+/// now, and invalidated again when closing to discard lines speculatively loaded
+/// during the transfer.
+/// 
+/// SAFETY: The slice must not be accessed while the transfer is running.
+unsafe fn configure_dst_slice<T: DmaPrimitiveType>(slice: &mut [T]) -> DmaEndpointConfig {
+
+    let addr = slice.as_mut_ptr() as usize;
+    let size = core::mem::size_of_val(slice);
+    assert!(size == 0 || (addr.is_multiple_of(LINE_SIZE) && size.is_multiple_of(LINE_SIZE)),
+        "destination slice must be aligned to cache lines, see CacheAligned");
+
+    unsafe { clean_invalidate_data_range(addr, size) }
+
+    DmaEndpointConfig {
+        peripheral: None,
+        data_width: T::data_width(),
+        burst_size: T::burst_size(),
+        increment: DmaIncrement::Incr(slice.len()),
+        addr,
+    }
+
+}
+
+/// Internal function to close a slice destination endpoint, see [`configure_dst_slice`].
+fn close_dst_slice<T>(slice: &mut [T]) {
+    // SAFETY: The slice is aligned to cache lines and exclusively owned.
+    unsafe { invalidate_data_range(slice.as_mut_ptr() as usize, core::mem::size_of_val(slice)) }
+}
+
+/// Internal function to clean all cache lines overlapping the given range, even if
+/// the range is not aligned to cache lines.
+/// 
+/// SAFETY: See [`clean_data_range`].
+pub(crate) unsafe fn clean_src_range(addr: usize, size: usize) {
+    if size != 0 {
+        let start = addr - addr % LINE_SIZE;
+        unsafe { clean_data_range(start, addr + size - start) }
+    }
+}
+
 /// Internal macro used to define DMA primitive integer types, these
 /// are used to implement [`DmaEndpoint`] on generic arrays and slices
 /// for example.
@@ -802,8 +1663,8 @@ pub enum DmaPeripheral {
 
 /// Internal function to get the given port registers array.
 #[inline(always)]
-const fn get_port_regs<const PORT: u8>() -> dma::Dma {
-    match PORT {
+const fn get_port_regs(port: u8) -> dma::Dma {
+    match port {
         0 => DMA0,
         1 => DMA1,
         2 => DMA2,
@@ -813,17 +1674,25 @@ const fn get_port_regs<const PORT: u8>() -> dma::Dma {
 
 /// Internal function to get the registers array of the given channel on given port.
 #[inline(always)]
-const fn get_channel_regs<const PORT: u8, const CHANNEL: u8>() -> dma::DmaChannel {
-    get_port_regs::<PORT>().channel(CHANNEL as usize)
+const fn get_channel_regs(port: u8, channel: u8) -> dma::DmaChannel {
+    get_port_regs(port).channel(channel as usize)
+}
+
+/// Internal function to get a peripheral numeric identifier corresponding
+/// to the given peripheral and port, this panics if the peripheral is not
+/// available on this port.
+fn get_peripheral_id(port: u8, peripheral: DmaPeripheral) -> u32 {
+    try_get_peripheral_id(port, peripheral)
+        .unwrap_or_else(|| panic!("invalid peripheral for port {port}"))
 }
 
 /// Internal function to get a peripheral numeric identifier corresponding
 /// to the given peripheral and port. Not all peripheral are available for
 /// each port.
-fn get_peripheral_id<const PORT: u8>(peripheral: DmaPeripheral) -> u32 {
+pub(crate) fn try_get_peripheral_id(port: u8, peripheral: DmaPeripheral) -> Option<u32> {
     use DmaPeripheral::*;
-    if PORT == 0 || PORT == 1 {
-        match peripheral {
+    if port == 0 || port == 1 {
+        Some(match peripheral {
             Uart0Rx => 0,
             Uart0Tx => 1,
             Uart1Rx => 2,
@@ -845,10 +1714,10 @@ fn get_peripheral_id<const PORT: u8>(peripheral: DmaPeripheral) -> u32 {
             Pdm => 18,
             AdcRx => 22,
             AdcTx => 23,
-            _ => panic!("invalid peripheral for port {PORT}")
-        }
-    } else if PORT == 2 {
-        match peripheral {
+            _ => return None
+        })
+    } else if port == 2 {
+        Some(match peripheral {
             Uart3Rx => 0,
             Uart3Tx => 1,
             Spi1Rx => 2,
@@ -860,8 +1729,8 @@ fn get_peripheral_id<const PORT: u8>(peripheral: DmaPeripheral) -> u32 {
             DsiRx => 10,
             DsiTx => 11,
             DbiTx => 22,
-            _ => panic!("invalid peripheral for port {PORT}")
-        }
+            _ => return None
+        })
     } else {
         panic!("invalid port")
     }
@@ -0,0 +1,213 @@
+//! Runtime allocator of DMA channels.
+//!
+//! Statically typed channels ([`DmaAccess`]) can be given to the allocator, they
+//! can then be allocated at runtime as type-erased [`AnyDmaChannel`] by drivers that
+//! don't want to be generic over the channel they use. Allocated channels are given
+//! back to the allocator when dropped.
+//!
+//! Only channels of the ports that support interrupts on the current chip can be
+//! given to the allocator, so that allocated channels support callbacks.
+
+use core::cell::RefCell;
+
+use alloc::boxed::Box;
+
+use critical_section::Mutex;
+
+use crate::cache::CacheAligned;
+use crate::sealed::Sealed;
+
+use super::{DmaAccess, DmaChannelHandle, DmaInterruptSupport, DmaChannelTransfer,
+    DmaSrcEndpoint, DmaDstEndpoint, DmaPrimitiveType, DmaPeripheral,
+    get_channel_regs, get_port_regs, has_interrupt_support, clear_callback, try_get_peripheral_id};
+use super::circular::DmaCircular;
+
+
+/// Number of channels for each DMA port.
+const PORT_CHANNELS: [u8; 3] = [8, 4, 8];
+
+/// Bit mask of the free channels for each DMA port.
+static FREE_CHANNELS: Mutex<RefCell<[u8; 3]>> = Mutex::new(RefCell::new([0; 3]));
+
+
+/// Give the given channel to the allocator, it can later be allocated with [`alloc`].
+pub fn give<const PORT: u8, const CHANNEL: u8>(access: DmaAccess<PORT, CHANNEL>)
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+    // The access is now owned by the allocator.
+    let _ = access;
+    release(PORT, CHANNEL);
+}
+
+/// Allocate a free channel that supports all the given peripherals, returning `None`
+/// if no such channel has been given to the allocator or if all of them are
+/// already allocated.
+pub fn alloc(peripherals: &[DmaPeripheral]) -> Option<AnyDmaChannel> {
+    critical_section::with(|cs| {
+
+        let mut free = FREE_CHANNELS.borrow_ref_mut(cs);
+
+        for port in 0..PORT_CHANNELS.len() as u8 {
+
+            if !has_interrupt_support(port) {
+                continue;
+            }
+
+            if !peripherals.iter().all(|&p| try_get_peripheral_id(port, p).is_some()) {
+                continue;
+            }
+
+            let mask = &mut free[port as usize];
+            if *mask != 0 {
+                let channel = mask.trailing_zeros() as u8;
+                *mask &= !(1 << channel);
+                return Some(AnyDmaChannel { port, channel });
+            }
+
+        }
+
+        None
+
+    })
+}
+
+/// Internal function to mark a channel as free.
+fn release(port: u8, channel: u8) {
+    debug_assert!(channel < PORT_CHANNELS[port as usize]);
+    critical_section::with(|cs| {
+        FREE_CHANNELS.borrow_ref_mut(cs)[port as usize] |= 1 << channel;
+    });
+}
+
+
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+impl super::Dma0 {
+
+    /// Give all channels of this port to the allocator.
+    pub fn give(self) {
+        give(self.c0);
+        give(self.c1);
+        give(self.c2);
+        give(self.c3);
+        give(self.c4);
+        give(self.c5);
+        give(self.c6);
+        give(self.c7);
+    }
+
+}
+
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+impl super::Dma1 {
+
+    /// Give all channels of this port to the allocator.
+    pub fn give(self) {
+        give(self.c0);
+        give(self.c1);
+        give(self.c2);
+        give(self.c3);
+    }
+
+}
+
+#[cfg(feature = "bl808-d0")]
+impl super::Dma2 {
+
+    /// Give all channels of this port to the allocator.
+    pub fn give(self) {
+        give(self.c0);
+        give(self.c1);
+        give(self.c2);
+        give(self.c3);
+        give(self.c4);
+        give(self.c5);
+        give(self.c6);
+        give(self.c7);
+    }
+
+}
+
+
+/// A DMA channel allocated at runtime with [`alloc`], the port and channel are
+/// only known at runtime. The channel is disabled and given back to the allocator
+/// when dropped.
+pub struct AnyDmaChannel {
+    port: u8,
+    channel: u8,
+}
+
+impl AnyDmaChannel {
+
+    /// Execute a new DMA transfer from the given source endpoint to the given
+    /// destination endpoint, see [`DmaAccess::into_transfer`].
+    #[inline(never)]
+    pub fn into_transfer<Src, Dst>(self,
+        src: Src,
+        dst: Dst,
+    ) -> AnyDmaTransfer<Src, Dst>
+    where
+        Src: DmaSrcEndpoint,
+        Dst: DmaDstEndpoint,
+    {
+        super::start(self, src, dst, false)
+    }
+
+    /// Start a circular transfer from the given source endpoint into the two halves
+    /// of the given buffer, see [`DmaAccess::into_circular`].
+    pub fn into_circular<Src, T>(self,
+        src: Src,
+        buffer: Box<CacheAligned<[T]>>,
+    ) -> DmaCircular<Self, Src, T>
+    where
+        Src: DmaSrcEndpoint,
+        T: DmaPrimitiveType + Send + 'static,
+    {
+        super::circular::start(self, src, buffer)
+    }
+
+}
+
+impl Sealed for AnyDmaChannel {}
+
+impl DmaChannelHandle for AnyDmaChannel {
+
+    #[inline(always)]
+    fn port(&self) -> u8 {
+        self.port
+    }
+
+    #[inline(always)]
+    fn channel(&self) -> u8 {
+        self.channel
+    }
+
+}
+
+impl DmaInterruptSupport for AnyDmaChannel { }
+
+impl Drop for AnyDmaChannel {
+    fn drop(&mut self) {
+
+        let (port, channel) = (self.port, self.channel);
+        get_channel_regs(port, channel).config()
+            .modify(|reg| reg.enable().clear());
+
+        // The next owner of the channel must not inherit the callback or pending
+        // interrupts of this one.
+        if has_interrupt_support(port) {
+            clear_callback(port, channel);
+        }
+
+        let port_regs = get_port_regs(port);
+        port_regs.int_tc_clear().set_with(|reg| reg.set(channel, true));
+        port_regs.int_error_clear().set_with(|reg| reg.set(channel, true));
+
+        release(port, channel);
+
+    }
+}
+
+
+/// Type alias for a transfer running on a channel allocated at runtime.
+pub type AnyDmaTransfer<Src, Dst> = DmaChannelTransfer<AnyDmaChannel, Src, Dst>;
@@ -0,0 +1,358 @@
+//! Circular DMA transfers into the two halves of a buffer.
+//!
+//! The transfer is made of a looping chain of linked list items, so the DMA
+//! controller fills the first half, then the second one, then the first one again
+//! and so on, without any software intervention. A terminal count interrupt is
+//! raised each time a half is completed, the consumer can then access this half
+//! while the other one is being filled.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::task::{Context, Poll, Waker};
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use critical_section::Mutex;
+
+use crate::cache::{CacheAligned, clean_invalidate_data_range, invalidate_data_range};
+
+use super::{DmaAccess, DmaChannelTransfer, DmaInterruptSupport, DmaSrcEndpoint, DmaDstEndpoint,
+    DmaEndpointConfig, DmaIncrement, DmaPrimitiveType, DmaCallback};
+
+
+impl<const PORT: u8, const CHANNEL: u8> DmaAccess<PORT, CHANNEL>
+where
+    DmaAccess<PORT, CHANNEL>: DmaInterruptSupport,
+{
+
+    /// Start a circular transfer from the given source endpoint into the two halves
+    /// of the given buffer, the source is typically a peripheral with constant
+    /// address. The buffer must have an even length.
+    ///
+    /// The transfer runs until stopped with [`DmaCircular::stop`], completed halves
+    /// can be read with [`DmaCircular::try_read`] or from a callback.
+    ///
+    /// *This method is only available on the CPU type that supports
+    /// interrupts for the current DMA port.*
+    pub fn into_circular<Src, T>(self,
+        src: Src,
+        buffer: Box<CacheAligned<[T]>>,
+    ) -> DmaCircular<Self, Src, T>
+    where
+        Src: DmaSrcEndpoint,
+        T: DmaPrimitiveType + Send + 'static,
+    {
+        start(self, src, buffer)
+    }
+
+}
+
+
+/// Internal function to start a circular transfer on the given channel.
+pub(crate) fn start<A, Src, T>(access: A, src: Src, buffer: Box<CacheAligned<[T]>>) -> DmaCircular<A, Src, T>
+where
+    A: DmaInterruptSupport,
+    Src: DmaSrcEndpoint,
+    T: DmaPrimitiveType + Send + 'static,
+{
+
+    assert!(!buffer.0.is_empty() && buffer.0.len().is_multiple_of(2), "circular buffer length must be even");
+
+    let (port, channel) = (access.port(), access.channel());
+    let buffer_addr = buffer.0.as_ptr() as usize;
+    let half_len = buffer.0.len() / 2;
+
+    let shared = Arc::new(Mutex::new(RefCell::new(CircularState {
+        completed: 0,
+        waker: None,
+        callback: None,
+    })));
+
+    let transfer = super::start(access, src, CircularBuffer(buffer), true);
+
+    // The callback is kept in the channel slot until the transfer is stopped.
+    let handler_shared = Arc::clone(&shared);
+    let wrapper: DmaCallback = Box::new(move || {
+        critical_section::with(|cs| {
+
+            let mut state = handler_shared.borrow_ref_mut(cs);
+            let half = DmaHalf::from_count(state.completed);
+            state.completed = state.completed.wrapping_add(1);
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+
+            if let Some(callback) = state.callback.as_mut() {
+                // SAFETY: The buffer lives as long as the transfer, which is stopped
+                // before removing this callback.
+                callback(half, unsafe { get_half(buffer_addr, half_len, half) });
+            }
+
+        });
+        true
+    });
+
+    super::set_callback(port, channel, wrapper);
+
+    DmaCircular {
+        transfer: Some(transfer),
+        shared,
+        buffer_addr,
+        half_len,
+        consumed: 0,
+        overrun: false,
+    }
+
+}
+
+
+/// A running circular DMA transfer, see [`DmaAccess::into_circular`]. The transfer is
+/// stopped when dropped.
+pub struct DmaCircular<A, Src, T>
+where
+    A: DmaInterruptSupport,
+    Src: DmaSrcEndpoint,
+    T: DmaPrimitiveType,
+{
+    /// The underlying transfer, only taken when stopping.
+    transfer: Option<DmaChannelTransfer<A, Src, CircularBuffer<T>>>,
+    /// State shared with the interrupt handler.
+    shared: Arc<Mutex<RefCell<CircularState<T>>>>,
+    /// Address of the first half.
+    buffer_addr: usize,
+    /// Length of each half.
+    half_len: usize,
+    /// Number of halves consumed by `try_read`.
+    consumed: usize,
+    /// Set when an half has not been consumed before being overwritten.
+    overrun: bool,
+}
+
+impl<A, Src, T> DmaCircular<A, Src, T>
+where
+    A: DmaInterruptSupport,
+    Src: DmaSrcEndpoint,
+    T: DmaPrimitiveType,
+{
+
+    /// Get the length of each half of the buffer.
+    #[inline]
+    pub fn half_len(&self) -> usize {
+        self.half_len
+    }
+
+    /// Get the total number of halves completed since the start, wrapping on overflow.
+    pub fn completed(&self) -> usize {
+        critical_section::with(|cs| self.shared.borrow_ref(cs).completed)
+    }
+
+    /// Set a callback to be called from interrupt each time a half is completed, with
+    /// the content of this half. The half must be processed before the other half is
+    /// completed, otherwise it will be overwritten.
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(DmaHalf, &[T]) + Send + 'static,
+    {
+        critical_section::with(|cs| {
+            self.shared.borrow_ref_mut(cs).callback = Some(Box::new(callback));
+        });
+    }
+
+    /// Remove the callback, if any.
+    pub fn clear_callback(&mut self) {
+        critical_section::with(|cs| {
+            self.shared.borrow_ref_mut(cs).callback.take();
+        });
+    }
+
+    /// Try reading the oldest completed half that has not been read yet, returning
+    /// `None` if no half has been completed since the last read.
+    ///
+    /// If the reader is too slow and both halves have been completed since the last
+    /// read, the oldest one is skipped and the overrun flag is set, see
+    /// [`take_overrun`](Self::take_overrun). The flag is also set if the half has
+    /// been overwritten while reading it.
+    pub fn try_read<R>(&mut self, func: impl FnOnce(DmaHalf, &[T]) -> R) -> Option<R> {
+
+        let completed = self.completed();
+        let pending = completed.wrapping_sub(self.consumed);
+        if pending == 0 {
+            return None;
+        }
+
+        if pending > 1 {
+            self.overrun = true;
+            self.consumed = completed.wrapping_sub(1);
+        }
+
+        let half = DmaHalf::from_count(self.consumed);
+        // SAFETY: The buffer is owned by the transfer while we exists.
+        let ret = func(half, unsafe { get_half(self.buffer_addr, self.half_len, half) });
+
+        // The other half completed since this one, so the controller wrapped back
+        // to this half and started overwriting it.
+        if self.completed().wrapping_sub(self.consumed) > 1 {
+            self.overrun = true;
+        }
+
+        self.consumed = self.consumed.wrapping_add(1);
+        Some(ret)
+
+    }
+
+    /// Wait for a completed half and read it, see [`try_read`](Self::try_read).
+    pub fn read<R>(&mut self, mut func: impl FnMut(DmaHalf, &[T]) -> R) -> R {
+        loop {
+            if let Some(ret) = self.try_read(&mut func) {
+                return ret;
+            }
+        }
+    }
+
+    /// Poll for a completed half that has not been read yet, the waker is woken from
+    /// the interrupt handler when the next half is completed.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        critical_section::with(|cs| {
+            let mut state = self.shared.borrow_ref_mut(cs);
+            if state.completed != self.consumed {
+                Poll::Ready(())
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+
+    /// Return a future that resolves once a completed half can be read with
+    /// [`try_read`](Self::try_read).
+    pub fn ready(&mut self) -> impl Future<Output = ()> + '_ {
+        core::future::poll_fn(move |cx| self.poll_ready(cx))
+    }
+
+    /// Return true if some data has been overwritten before being read, and clear
+    /// the flag.
+    #[inline]
+    pub fn take_overrun(&mut self) -> bool {
+        core::mem::take(&mut self.overrun)
+    }
+
+    /// Stop this circular transfer and get back the source, the buffer and the
+    /// channel access.
+    pub fn stop(mut self) -> (Src, Box<CacheAligned<[T]>>, A) {
+        let (src, buffer, access) = self.stop_impl().unwrap();
+        (src, buffer.0, access)
+    }
+
+    /// Internal function to stop the transfer if not already stopped.
+    fn stop_impl(&mut self) -> Option<(Src, CircularBuffer<T>, A)> {
+        let transfer = self.transfer.take()?;
+        // Remove the callback before releasing the buffer.
+        super::abort_callback(transfer.access.port(), transfer.access.channel());
+        Some(transfer.abort())
+    }
+
+}
+
+impl<A, Src, T> Drop for DmaCircular<A, Src, T>
+where
+    A: DmaInterruptSupport,
+    Src: DmaSrcEndpoint,
+    T: DmaPrimitiveType,
+{
+    fn drop(&mut self) {
+        self.stop_impl();
+    }
+}
+
+
+/// A half of the buffer of a circular transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaHalf {
+    First,
+    Second,
+}
+
+impl DmaHalf {
+
+    /// Get the half corresponding to the given count of completed halves.
+    #[inline]
+    fn from_count(count: usize) -> Self {
+        if count.is_multiple_of(2) { Self::First } else { Self::Second }
+    }
+
+}
+
+
+/// Type alias for a boxed closure used as a circular transfer callback.
+type CircularCallback<T> = Box<dyn FnMut(DmaHalf, &[T]) + Send>;
+
+/// State shared between a circular transfer and its interrupt handler.
+struct CircularState<T> {
+    /// Number of halves completed, wrapping.
+    completed: usize,
+    /// Waker to wake when the next half is completed.
+    waker: Option<Waker>,
+    /// Callback called when a half is completed.
+    callback: Option<CircularCallback<T>>,
+}
+
+/// Internal function to get a completed half of the buffer, invalidating the cache
+/// so that the data written by the DMA controller is visible.
+///
+/// SAFETY: The buffer must still be alive.
+unsafe fn get_half<'a, T>(buffer_addr: usize, half_len: usize, half: DmaHalf) -> &'a [T] {
+    let addr = match half {
+        DmaHalf::First => buffer_addr,
+        DmaHalf::Second => buffer_addr + half_len * core::mem::size_of::<T>(),
+    };
+    unsafe {
+        // The CPU never writes to the buffer, so no dirty line can be discarded.
+        invalidate_data_range(addr, half_len * core::mem::size_of::<T>());
+        core::slice::from_raw_parts(addr as *const T, half_len)
+    }
+}
+
+
+/// Internal destination endpoint of a circular transfer, each half of the buffer is
+/// a segment of the transfer.
+struct CircularBuffer<T>(Box<CacheAligned<[T]>>);
+
+impl<T: DmaPrimitiveType> DmaDstEndpoint for CircularBuffer<T> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+
+        // SAFETY: See implementation for cache aligned boxes.
+        unsafe {
+            let self_addr = &*self.0 as *const CacheAligned<[T]> as *const u8 as usize;
+            let self_size = core::mem::size_of_val(&*self.0);
+            clean_invalidate_data_range(self_addr, self_size);
+        }
+
+        DmaEndpointConfig {
+            peripheral: None,
+            data_width: T::data_width(),
+            burst_size: T::burst_size(),
+            increment: DmaIncrement::Incr(self.0.0.len()),
+            addr: self.0.0.as_ptr() as _,
+        }
+
+    }
+
+    unsafe fn configure_segments(&mut self, segments: &mut Vec<DmaEndpointConfig>) {
+        let config = unsafe { self.configure() };
+        let half_len = self.0.0.len() / 2;
+        segments.push(DmaEndpointConfig {
+            increment: DmaIncrement::Incr(half_len),
+            ..config.clone()
+        });
+        segments.push(DmaEndpointConfig {
+            increment: DmaIncrement::Incr(half_len),
+            addr: config.addr + half_len * core::mem::size_of::<T>(),
+            ..config
+        });
+    }
+
+}
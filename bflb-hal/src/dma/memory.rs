@@ -0,0 +1,152 @@
+//! Memory-to-memory copy and fill using channels of the runtime allocator.
+//!
+//! Unlike transfers started from a channel access, these functions accept borrowed
+//! buffers with no alignment requirement, and block until the transfer is done. The
+//! cache is maintained on the given ranges: the partial cache lines at both ends of
+//! the destination are handled by the CPU so that the DMA controller only writes
+//! whole cache lines, that can safely be invalidated.
+//!
+//! If no channel can be allocated, see [`allocator`](super::allocator), the CPU is
+//! used instead.
+
+use crate::cache::{self, CacheAligned, LINE_SIZE};
+
+use super::{DmaSrcEndpoint, DmaDstEndpoint, DmaEndpointConfig, DmaIncrement,
+    DmaPrimitiveType, DmaError, allocator, clean_src_range};
+
+
+/// Copy all elements from `src` into `dst` using a free DMA channel, the two slices
+/// must have the same length and must not overlap (guaranteed by borrowing rules).
+///
+/// This function panics if the two slices have different lengths.
+pub fn memcpy<T: DmaPrimitiveType + Copy>(dst: &mut [T], src: &[T]) -> Result<(), DmaError> {
+
+    assert_eq!(dst.len(), src.len(), "source and destination length must be equal");
+
+    let (head, mid, tail) = split_lines(dst);
+    let (src_head, src) = src.split_at(head.len());
+    let (src_mid, src_tail) = src.split_at(mid.len());
+
+    head.copy_from_slice(src_head);
+    tail.copy_from_slice(src_tail);
+
+    if mid.is_empty() {
+        return Ok(());
+    }
+
+    // SAFETY: The source is borrowed for the whole transfer and only read.
+    unsafe { clean_src_range(src_mid.as_ptr() as usize, core::mem::size_of_val(src_mid)) };
+
+    let src_region = MemoryRegion::new::<T>(src_mid.as_ptr() as usize, DmaIncrement::Incr(src_mid.len()));
+    transfer(src_region, mid, |mid| mid.copy_from_slice(src_mid))
+
+}
+
+/// Fill all elements of `dst` with the given value using a free DMA channel.
+pub fn memset<T: DmaPrimitiveType + Copy>(dst: &mut [T], value: T) -> Result<(), DmaError> {
+
+    let (head, mid, tail) = split_lines(dst);
+    head.fill(value);
+    tail.fill(value);
+
+    if mid.is_empty() {
+        return Ok(());
+    }
+
+    // The value is read at a constant address for the whole transfer.
+    let value = CacheAligned(value);
+    let value_addr = &value.0 as *const T as usize;
+    // SAFETY: The value lives until the end of this function and is only read.
+    unsafe { clean_src_range(value_addr, core::mem::size_of::<T>()) };
+
+    let src_region = MemoryRegion::new::<T>(value_addr, DmaIncrement::Const);
+    transfer(src_region, mid, |mid| mid.fill(value.0))
+
+}
+
+
+/// Internal function to run a blocking transfer from the given source region into the
+/// given destination that must be made of whole cache lines. The fallback is called
+/// if no channel is available.
+fn transfer<T: DmaPrimitiveType>(
+    src: MemoryRegion,
+    dst: &mut [T],
+    fallback: impl FnOnce(&mut [T]),
+) -> Result<(), DmaError> {
+
+    let Some(channel) = allocator::alloc(&[]) else {
+        fallback(dst);
+        return Ok(());
+    };
+
+    let dst_addr = dst.as_mut_ptr() as usize;
+    let dst_size = core::mem::size_of_val(dst);
+
+    // SAFETY: The destination is made of whole cache lines that are fully overwritten,
+    // so dirty lines can be discarded. It's borrowed until the end of the transfer.
+    unsafe { cache::invalidate_data_range(dst_addr, dst_size) };
+
+    let dst_region = MemoryRegion::new::<T>(dst_addr, DmaIncrement::Incr(dst.len()));
+    let transfer = channel.into_transfer(src, dst_region);
+
+    while !transfer.completed() {}
+    let error = transfer.error();
+
+    // The channel is given back to the allocator when dropped.
+    let _ = transfer.wait();
+
+    // SAFETY: Lines may have been speculatively loaded during the transfer.
+    unsafe { cache::invalidate_data_range(dst_addr, dst_size) };
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+
+}
+
+/// Internal function to split the given slice into a head and a tail that are partial
+/// cache lines, and a middle part made of whole cache lines.
+fn split_lines<T>(slice: &mut [T]) -> (&mut [T], &mut [T], &mut [T]) {
+
+    let size = core::mem::size_of::<T>();
+    let addr = slice.as_ptr() as usize;
+
+    let head_len = ((addr.next_multiple_of(LINE_SIZE) - addr) / size).min(slice.len());
+    let (head, rest) = slice.split_at_mut(head_len);
+    let mid_len = rest.len() - rest.len() % (LINE_SIZE / size);
+    let (mid, tail) = rest.split_at_mut(mid_len);
+
+    (head, mid, tail)
+
+}
+
+
+/// Internal endpoint for a memory region, used as both source and destination.
+struct MemoryRegion(DmaEndpointConfig);
+
+impl MemoryRegion {
+
+    fn new<T: DmaPrimitiveType>(addr: usize, increment: DmaIncrement) -> Self {
+        Self(DmaEndpointConfig {
+            peripheral: None,
+            data_width: T::data_width(),
+            burst_size: T::burst_size(),
+            increment,
+            addr,
+        })
+    }
+
+}
+
+impl DmaSrcEndpoint for MemoryRegion {
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        self.0.clone()
+    }
+}
+
+impl DmaDstEndpoint for MemoryRegion {
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        self.0.clone()
+    }
+}
@@ -0,0 +1,106 @@
+//! Scoped transfers on borrowed buffers.
+//!
+//! Regular transfers own their endpoints because the transfer handle can be forgotten
+//! while the DMA controller is still accessing them. Scoped transfers instead run a
+//! closure while the transfer is running and then block until it's completed, so the
+//! borrowed buffers, for example on the stack, cannot be released or accessed before.
+//!
+//! ```ignore
+//! let mut buf = CacheAligned([0u8; 64]);
+//! let (_, _, res) = scoped::transfer(&mut dma.p0.c0, uart, DmaSliceMut(&mut buf.0), |_| ());
+//! ```
+
+use super::{DmaChannelHandle, DmaChannelTransfer, DmaSrcEndpoint, DmaDstEndpoint,
+    DmaEndpointConfig, DmaPrimitiveType, DmaError, configure_src_slice, configure_dst_slice,
+    close_dst_slice, start_unchecked};
+
+
+/// Run a transfer from the given source endpoint to the given destination endpoint,
+/// the given closure is called while the transfer is running and this function then
+/// blocks until the transfer is completed. The closure is given the transfer to check
+/// its progress.
+///
+/// The source and destination endpoints are returned with the result of the closure,
+/// or the error that stopped the transfer.
+pub fn transfer<A, Src, Dst, R>(
+    access: &mut A,
+    src: Src,
+    dst: Dst,
+    func: impl FnOnce(&DmaChannelTransfer<&mut A, Src, Dst>) -> R,
+) -> (Src, Dst, Result<R, DmaError>)
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+
+    let mut guard = ScopeGuard(Some(start_unchecked(access, src, dst, false)));
+    let ret = func(guard.0.as_ref().unwrap());
+
+    let transfer = guard.0.take().unwrap();
+    while !transfer.completed() {}
+    let error = transfer.error();
+    let (src, dst, _) = transfer.wait();
+
+    match error {
+        Some(error) => (src, dst, Err(error)),
+        None => (src, dst, Ok(ret)),
+    }
+
+}
+
+/// Internal guard that blocks until the transfer is completed when dropped, this is
+/// only owned by [`transfer`] so it cannot be forgotten.
+struct ScopeGuard<A, Src, Dst>(Option<DmaChannelTransfer<A, Src, Dst>>)
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint;
+
+impl<A, Src, Dst> Drop for ScopeGuard<A, Src, Dst>
+where
+    A: DmaChannelHandle,
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+    fn drop(&mut self) {
+        if let Some(transfer) = self.0.take() {
+            transfer.wait();
+        }
+    }
+}
+
+
+/// A source endpoint borrowing a slice for a scoped transfer, see [`transfer`].
+pub struct DmaSlice<'a, T>(pub &'a [T]);
+
+impl<T: DmaPrimitiveType> DmaSrcEndpoint for DmaSlice<'_, T> {
+
+    const BORROWED: bool = true;
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        // SAFETY: The slice is borrowed until the end of the scoped transfer.
+        unsafe { configure_src_slice(self.0) }
+    }
+
+}
+
+/// A destination endpoint mutably borrowing a slice for a scoped transfer, see
+/// [`transfer`]. The slice must be aligned to cache lines, for example by wrapping
+/// the buffer in [`CacheAligned`](crate::cache::CacheAligned).
+pub struct DmaSliceMut<'a, T>(pub &'a mut [T]);
+
+impl<T: DmaPrimitiveType> DmaDstEndpoint for DmaSliceMut<'_, T> {
+
+    const BORROWED: bool = true;
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        // SAFETY: The slice is exclusively borrowed until the end of the scoped transfer.
+        unsafe { configure_dst_slice(self.0) }
+    }
+
+    fn close(&mut self) {
+        close_dst_slice(self.0);
+    }
+
+}
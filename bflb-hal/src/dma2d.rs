@@ -0,0 +1,562 @@
+//! 2D DMA engine, only available on D0.
+//!
+//! The engine copies or fills rectangles of images with arbitrary row strides, which
+//! allows cropping and composing camera frames or UI buffers without CPU loops. An
+//! optional color key can be used when copying to skip transparent source pixels.
+//!
+//! Operations are blocking and work on borrowed images, the cache is cleaned on the
+//! source image and invalidated on the destination image. For this reason the buffer
+//! of a destination image must be aligned to cache lines, see [`CacheAligned`].
+//!
+//! [`CacheAligned`]: crate::cache::CacheAligned
+
+use crate::arch::bl808::{DMA2D, MM_GLB};
+use crate::cache::{self, CacheAligned, LINE_SIZE};
+use crate::dma::{DmaError, DmaDataWidth, clean_src_range};
+
+
+/// Maximum number of transfers of a single pass.
+const MAX_TRANSFERS: usize = 4095;
+
+/// The channel used for all operations.
+const CHANNEL: u8 = 0;
+
+
+/// Exclusive access to the DMA2D engine.
+pub struct Dma2dAccess(pub(crate) ());
+
+impl Dma2dAccess {
+
+    /// Initialize the DMA2D engine, the peripheral is reset.
+    pub fn init(self) -> Dma2d {
+
+        MM_GLB.sw_reset_mm_peri().modify(|reg| reg.swrst_dma2d().fill());
+        MM_GLB.sw_reset_mm_peri().modify(|reg| reg.swrst_dma2d().clear());
+
+        DMA2D.config().modify(|reg| reg.smdma_enable().fill());
+
+        Dma2d(())
+
+    }
+
+}
+
+
+/// An initialized DMA2D engine.
+pub struct Dma2d(());
+
+impl Dma2d {
+
+    /// Disable the engine.
+    pub fn downgrade(self) -> Dma2dAccess {
+        // Drop will be called at the end, effectively disabling the engine.
+        Dma2dAccess(())
+    }
+
+    /// Copy the given rectangle of the source image to the destination image, at the
+    /// given position. Both images must have the same pixel format.
+    ///
+    /// This function panics if the rectangles don't fit in their images.
+    pub fn copy(&mut self,
+        src: &Dma2dImage,
+        src_rect: Dma2dRect,
+        dst: &mut Dma2dImageMut,
+        dst_x: u32,
+        dst_y: u32,
+    ) -> Result<(), DmaError> {
+        self.copy_impl(src, src_rect, dst, dst_x, dst_y, None)
+    }
+
+    /// Copy the given rectangle of the source image to the destination image, at the
+    /// given position, source pixels equal to the given key color are not written, so
+    /// the destination is visible through them. The key is given in the raw pixel
+    /// format of the images.
+    ///
+    /// This function panics if the rectangles don't fit in their images.
+    pub fn copy_keyed(&mut self,
+        src: &Dma2dImage,
+        src_rect: Dma2dRect,
+        dst: &mut Dma2dImageMut,
+        dst_x: u32,
+        dst_y: u32,
+        key: u32,
+    ) -> Result<(), DmaError> {
+        self.copy_impl(src, src_rect, dst, dst_x, dst_y, Some(key))
+    }
+
+    /// Fill the given rectangle of the destination image with a color, given in the
+    /// raw pixel format of the image (little endian).
+    ///
+    /// This function panics if the rectangle doesn't fit in the image.
+    pub fn fill(&mut self, dst: &mut Dma2dImageMut, rect: Dma2dRect, color: u32) -> Result<(), DmaError> {
+
+        let format = dst.format;
+        let dst_offset = dst.offset(rect);
+
+        // The color is stored in a cache line of its own, read as a repeated row of
+        // one pixel by the controller.
+        let color = CacheAligned(color.to_le_bytes());
+        let color_addr = color.0.as_ptr() as usize;
+        // SAFETY: The color lives until the end of this function and is only read.
+        unsafe { clean_src_range(color_addr, format.bytes_per_pixel()) };
+
+        let pixel = Plane::new(color_addr, format, 1, 0);
+        let dst_addr = dst.data.as_mut_ptr() as usize + dst_offset;
+        let dst_stride = dst.stride;
+
+        dst.run(|| {
+            run_passes(format, rect.width, rect.height, |x, width, row, rows| {
+                let src = pixel.with_rows(rows * width);
+                let dst = Plane::new(dst_addr + plane_offset(format, x, row, dst_stride), format, width, dst_stride)
+                    .with_rows(rows);
+                // SAFETY: The color and the destination are borrowed until the end.
+                unsafe { run_pass(src, dst, None) }
+            })
+        })
+
+    }
+
+    /// Internal function to copy, with an optional color key.
+    fn copy_impl(&mut self,
+        src: &Dma2dImage,
+        src_rect: Dma2dRect,
+        dst: &mut Dma2dImageMut,
+        dst_x: u32,
+        dst_y: u32,
+        key: Option<u32>,
+    ) -> Result<(), DmaError> {
+
+        assert_eq!(src.format, dst.format, "source and destination must have the same pixel format");
+
+        let format = src.format;
+        let src_offset = src.offset(src_rect);
+        let dst_offset = dst.offset(Dma2dRect::new(dst_x, dst_y, src_rect.width, src_rect.height));
+
+        // SAFETY: The source image is borrowed until the end and only read.
+        unsafe { clean_src_range(src.data.as_ptr() as usize, src.data.len()) };
+
+        let src_addr = src.data.as_ptr() as usize + src_offset;
+        let dst_addr = dst.data.as_mut_ptr() as usize + dst_offset;
+        let (src_stride, dst_stride) = (src.stride, dst.stride);
+
+        dst.run(|| {
+            run_passes(format, src_rect.width, src_rect.height, |x, width, row, rows| {
+                let src = Plane::new(src_addr + plane_offset(format, x, row, src_stride), format, width, src_stride)
+                    .with_rows(rows);
+                let dst = Plane::new(dst_addr + plane_offset(format, x, row, dst_stride), format, width, dst_stride)
+                    .with_rows(rows);
+                // SAFETY: Both images are borrowed until the end.
+                unsafe { run_pass(src, dst, key.map(|key| (key, format))) }
+            })
+        })
+
+    }
+
+}
+
+impl Drop for Dma2d {
+    fn drop(&mut self) {
+        DMA2D.channel(CHANNEL as usize).config().modify(|reg| reg.enable().clear());
+        DMA2D.config().modify(|reg| reg.smdma_enable().clear());
+    }
+}
+
+
+/// A rectangle in an image, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dma2dRect {
+    /// Left column of the rectangle.
+    pub x: u32,
+    /// Top row of the rectangle.
+    pub y: u32,
+    /// Width of the rectangle.
+    pub width: u32,
+    /// Height of the rectangle.
+    pub height: u32,
+}
+
+impl Dma2dRect {
+
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+}
+
+
+/// Pixel formats supported by the engine, this is used to compute the layout of
+/// images and the size of transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dma2dPixelFormat {
+    /// 8 bits luminance (or any byte-sized pixel).
+    L8,
+    /// 16 bits RGB, 5 bits red, 6 bits green and 5 bits blue.
+    Rgb565,
+    /// 24 bits RGB, one byte per component.
+    Rgb888,
+    /// 32 bits ARGB, one byte per component.
+    Argb8888,
+}
+
+impl Dma2dPixelFormat {
+
+    /// Get the number of bytes of a pixel.
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::L8 => 1,
+            Self::Rgb565 => 2,
+            Self::Rgb888 => 3,
+            Self::Argb8888 => 4,
+        }
+    }
+
+    /// Internal function to get the data width of transfers, 24 bits pixels are
+    /// transferred byte per byte.
+    const fn data_width(self) -> DmaDataWidth {
+        match self {
+            Self::L8 | Self::Rgb888 => DmaDataWidth::Byte,
+            Self::Rgb565 => DmaDataWidth::Hword,
+            Self::Argb8888 => DmaDataWidth::Word,
+        }
+    }
+
+    /// Internal function to get the color key mode.
+    const fn key_mode(self) -> u32 {
+        self.bytes_per_pixel() as u32 - 1
+    }
+
+}
+
+
+/// A source image borrowed for an operation.
+#[derive(Debug, Clone, Copy)]
+pub struct Dma2dImage<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: Dma2dPixelFormat,
+}
+
+impl<'a> Dma2dImage<'a> {
+
+    /// Create an image with contiguous rows.
+    ///
+    /// This function panics if the data is too small or not aligned, see
+    /// [`with_stride`](Self::with_stride).
+    pub fn new(data: &'a [u8], width: u32, height: u32, format: Dma2dPixelFormat) -> Self {
+        Self::with_stride(data, width, height, width as usize * format.bytes_per_pixel(), format)
+    }
+
+    /// Create an image with the given distance in bytes between rows.
+    ///
+    /// This function panics if the data is too small or the stride is smaller than
+    /// a row, or if the data or the stride is not aligned to the pixel data width
+    /// (2 bytes for [`Rgb565`], 4 bytes for [`Argb8888`]).
+    ///
+    /// [`Rgb565`]: Dma2dPixelFormat::Rgb565
+    /// [`Argb8888`]: Dma2dPixelFormat::Argb8888
+    pub fn with_stride(data: &'a [u8], width: u32, height: u32, stride: usize, format: Dma2dPixelFormat) -> Self {
+        check_layout(data, width, height, stride, format);
+        Self { data, width, height, stride, format }
+    }
+
+    /// Get the width of this image, in pixels.
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of this image, in pixels.
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the pixel format of this image.
+    #[inline]
+    pub fn format(&self) -> Dma2dPixelFormat {
+        self.format
+    }
+
+    /// Internal function to get the byte offset of the given rectangle.
+    fn offset(&self, rect: Dma2dRect) -> usize {
+        rect_offset(rect, self.width, self.height, self.stride, self.format)
+    }
+
+}
+
+
+/// A destination image mutably borrowed for an operation, the buffer must start and
+/// end on cache line boundaries.
+#[derive(Debug)]
+pub struct Dma2dImageMut<'a> {
+    data: &'a mut [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: Dma2dPixelFormat,
+}
+
+impl<'a> Dma2dImageMut<'a> {
+
+    /// Create an image with contiguous rows.
+    ///
+    /// This function panics if the data is too small or not aligned to cache lines.
+    pub fn new(data: &'a mut [u8], width: u32, height: u32, format: Dma2dPixelFormat) -> Self {
+        Self::with_stride(data, width, height, width as usize * format.bytes_per_pixel(), format)
+    }
+
+    /// Create an image with the given distance in bytes between rows.
+    ///
+    /// This function panics if the data is too small or not aligned to cache lines,
+    /// or if the stride is smaller than a row or not aligned to the pixel data width,
+    /// see [`Dma2dImage::with_stride`].
+    pub fn with_stride(data: &'a mut [u8], width: u32, height: u32, stride: usize, format: Dma2dPixelFormat) -> Self {
+        check_layout(data, width, height, stride, format);
+        let addr = data.as_ptr() as usize;
+        assert!(addr.is_multiple_of(LINE_SIZE) && data.len().is_multiple_of(LINE_SIZE),
+            "destination image must be aligned to cache lines, see CacheAligned");
+        Self { data, width, height, stride, format }
+    }
+
+    /// Get the width of this image, in pixels.
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of this image, in pixels.
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the pixel format of this image.
+    #[inline]
+    pub fn format(&self) -> Dma2dPixelFormat {
+        self.format
+    }
+
+    /// Get a read-only view of this image, usable as the source of a copy to another
+    /// image.
+    pub fn as_image(&self) -> Dma2dImage<'_> {
+        Dma2dImage {
+            data: self.data,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            format: self.format,
+        }
+    }
+
+    /// Internal function to get the byte offset of the given rectangle.
+    fn offset(&self, rect: Dma2dRect) -> usize {
+        rect_offset(rect, self.width, self.height, self.stride, self.format)
+    }
+
+    /// Internal function to run the given function that writes this image, the cache
+    /// is maintained before and after.
+    fn run<R>(&mut self, func: impl FnOnce() -> R) -> R {
+
+        let addr = self.data.as_mut_ptr() as usize;
+        let size = self.data.len();
+
+        // SAFETY: The buffer is aligned to cache lines and exclusively borrowed.
+        unsafe { cache::clean_invalidate_data_range(addr, size) };
+        let ret = func();
+        // Lines may have been speculatively loaded during the transfer.
+        unsafe { cache::invalidate_data_range(addr, size) };
+
+        ret
+
+    }
+
+}
+
+
+/// Internal function to check that an image layout fits in the given data, and that
+/// the data and the stride are aligned to the data width of the format, so that all
+/// pixels are accessed with aligned transfers.
+fn check_layout(data: &[u8], width: u32, height: u32, stride: usize, format: Dma2dPixelFormat) {
+    let row_size = width as usize * format.bytes_per_pixel();
+    assert!(stride >= row_size, "stride is smaller than a row");
+    if height != 0 {
+        assert!(data.len() >= stride * (height as usize - 1) + row_size, "image data is too small");
+    }
+    let align = 1usize << format.data_width() as u32;
+    assert!((data.as_ptr() as usize).is_multiple_of(align) && stride.is_multiple_of(align),
+        "image data and stride must be aligned to the pixel data width");
+}
+
+/// Internal function to check that a rectangle fits in an image and return its
+/// byte offset.
+fn rect_offset(rect: Dma2dRect, width: u32, height: u32, stride: usize, format: Dma2dPixelFormat) -> usize {
+    assert!(rect.x.checked_add(rect.width).is_some_and(|end| end <= width)
+        && rect.y.checked_add(rect.height).is_some_and(|end| end <= height),
+        "rectangle doesn't fit in the image");
+    rect.y as usize * stride + rect.x as usize * format.bytes_per_pixel()
+}
+
+
+/// Internal description of the 2D addressing of one side of a pass.
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    addr: usize,
+    data_width: DmaDataWidth,
+    /// Number of transfers in a row.
+    x_count: u32,
+    /// Number of rows.
+    y_count: u32,
+    x_incr: i32,
+    y_incr: i32,
+}
+
+impl Plane {
+
+    /// Create a plane of rows of the given width in pixels, separated by the given
+    /// stride in bytes. The plane has a single row.
+    fn new(addr: usize, format: Dma2dPixelFormat, width: u32, stride: usize) -> Self {
+        let transfer_size = 1i32 << format.data_width() as u32;
+        let x_count = width * Self::transfers_per_pixel(format);
+        Self {
+            addr,
+            data_width: format.data_width(),
+            x_count,
+            y_count: 1,
+            x_incr: transfer_size,
+            // From the last transfer of a row to the first one of the next row.
+            y_incr: stride as i32 - (x_count as i32 - 1) * transfer_size,
+        }
+    }
+
+    /// Set the number of rows of this plane.
+    fn with_rows(self, rows: u32) -> Self {
+        Self { y_count: rows, ..self }
+    }
+
+    /// Number of transfers of a pixel of the given format.
+    const fn transfers_per_pixel(format: Dma2dPixelFormat) -> u32 {
+        match format {
+            Dma2dPixelFormat::Rgb888 => 3,
+            _ => 1,
+        }
+    }
+
+}
+
+/// Internal function to split a rectangle of the given size in pixels in passes that
+/// fit in a single transfer, the given function is called with the first column and
+/// the width, and with the first row and the number of rows of each pass. Rows that
+/// are too wide for a single pass are split in multiple columns.
+fn run_passes(
+    format: Dma2dPixelFormat,
+    width: u32,
+    height: u32,
+    mut func: impl FnMut(u32, u32, u32, u32) -> Result<(), DmaError>,
+) -> Result<(), DmaError> {
+
+    let transfers_per_pixel = Plane::transfers_per_pixel(format);
+    let max_width = MAX_TRANSFERS as u32 / transfers_per_pixel;
+
+    let mut x = 0;
+    while x < width {
+
+        let pass_width = max_width.min(width - x);
+        let max_rows = MAX_TRANSFERS as u32 / (pass_width * transfers_per_pixel);
+
+        let mut row = 0;
+        while row < height {
+            let rows = max_rows.min(height - row);
+            func(x, pass_width, row, rows)?;
+            row += rows;
+        }
+
+        x += pass_width;
+
+    }
+
+    Ok(())
+
+}
+
+/// Internal function to get the byte offset of the given pixel, relative to a plane.
+#[inline]
+fn plane_offset(format: Dma2dPixelFormat, x: u32, row: u32, stride: usize) -> usize {
+    row as usize * stride + x as usize * format.bytes_per_pixel()
+}
+
+/// Internal function to run a single pass and wait for its completion.
+///
+/// SAFETY: Both planes must be valid for the whole pass, and the destination must
+/// not be accessed until this returns.
+unsafe fn run_pass(src: Plane, dst: Plane, key: Option<(u32, Dma2dPixelFormat)>) -> Result<(), DmaError> {
+
+    let channel_regs = DMA2D.channel(CHANNEL as usize);
+    let transfers = dst.x_count * dst.y_count;
+
+    channel_regs.config().modify(|reg| reg.enable().clear());
+
+    channel_regs.src_addr().set(src.addr as u32);
+    channel_regs.dst_addr().set(dst.addr as u32);
+    channel_regs.lli().set(0);
+
+    channel_regs.src_count().set_with(|reg| {
+        reg.x_count().set(src.x_count);
+        reg.y_count().set(src.y_count);
+    });
+    channel_regs.src_x_incr().set(src.x_incr as u32);
+    channel_regs.src_y_incr().set(src.y_incr as u32);
+    channel_regs.dst_count().set_with(|reg| reg.x_count().set(dst.x_count));
+    channel_regs.dst_x_incr().set(dst.x_incr as u32);
+    channel_regs.dst_y_incr().set(dst.y_incr as u32);
+    channel_regs.mode().set_with(|reg| reg.enable_2d().fill());
+
+    match key {
+        Some((key, format)) => {
+            channel_regs.key().set(key);
+            channel_regs.key_config().set_with(|reg| {
+                reg.enable().fill();
+                reg.mode().set(format.key_mode());
+            });
+        }
+        None => channel_regs.key_config().set_with(|reg| reg.enable().clear()),
+    }
+
+    channel_regs.control().set_with(|reg| {
+        reg.transfer_size().set(transfers);
+        reg.src_width().set(src.data_width as _);
+        reg.dst_width().set(dst.data_width as _);
+        // Single transfers because rows may not be contiguous.
+        reg.src_burst_size().clear();
+        reg.dst_burst_size().clear();
+        reg.src_increment().fill();
+        reg.dst_increment().fill();
+    });
+
+    DMA2D.int_tc_clear().set_with(|reg| reg.set(CHANNEL, true));
+    DMA2D.int_error_clear().set_with(|reg| reg.set(CHANNEL, true));
+
+    channel_regs.config().modify(|reg| {
+        reg.src_peripheral().clear();
+        reg.dst_peripheral().clear();
+        reg.flow_control().clear();
+        reg.int_tc_mask().fill();
+        reg.int_error_mask().fill();
+        reg.enable().fill();
+    });
+
+    // The controller disables the channel at the end of the transfer or on error.
+    while DMA2D.channel_enable_status().get().get(CHANNEL) {}
+
+    let error = DMA2D.raw_int_error_status().get().get(CHANNEL);
+    DMA2D.int_tc_clear().set_with(|reg| reg.set(CHANNEL, true));
+    DMA2D.int_error_clear().set_with(|reg| reg.set(CHANNEL, true));
+
+    if error {
+        Err(DmaError::Bus)
+    } else {
+        Ok(())
+    }
+
+}
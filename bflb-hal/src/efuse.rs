@@ -0,0 +1,28 @@
+//! Factory trim values stored in eFuse.
+//!
+//! Each trim value has an enable bit and an even parity bit, a value is only
+//! returned if it's enabled and its parity is valid.
+
+use crate::arch::bl808::EF_DATA;
+
+
+/// Get the GPADC gain correction, in 1/2048 units. The conversion code should be
+/// multiplied by `(2048 - trim) / 2048`.
+pub fn get_adc_gain_trim() -> Option<i16> {
+    let mut trim = EF_DATA.adc_trim().get();
+    let value = checked_value(trim.gpadc_gain_trim().get(), trim.gpadc_gain_trim_parity().get(), trim.gpadc_gain_trim_en().get())?;
+    // Sign extend the 12-bit value.
+    Some(((value << 4) as i16) >> 4)
+}
+
+/// Get the temperature sensor offset, in GPADC 16-bit codes.
+pub fn get_tsen_trim() -> Option<u16> {
+    let mut trim = EF_DATA.adc_trim().get();
+    checked_value(trim.tsen_trim().get(), trim.tsen_trim_parity().get(), trim.tsen_trim_en().get())
+        .map(|value| value as u16)
+}
+
+/// Internal function to check enable and parity bits of a trim value.
+fn checked_value(value: u32, parity: u32, enable: u32) -> Option<u32> {
+    (enable != 0 && value.count_ones() & 1 == parity).then_some(value)
+}
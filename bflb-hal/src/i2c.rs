@@ -10,6 +10,10 @@ use crate::arch::bl808::i2c;
 use crate::gpio::{Pin, PinPull, PinDrive, PinFunction, Alternate};
 use crate::sealed::Sealed;
 use crate::clock;
+use crate::time::{Duration, Instant};
+
+
+pub mod bus;
 
 
 /// A generic I²C port trait, used to abstract complexity of the underlying 
@@ -22,6 +26,12 @@ pub trait I2cDev {
     /// Blocking write to an I²C slave on the bus.
     fn write(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool;
 
+    /// Change the frequency of the bus for the next transactions. The default 
+    /// implementation ignores the request, for buses that have a fixed frequency.
+    fn set_frequency(&mut self, frequency: u32) {
+        let _ = frequency;
+    }
+
 }
 
 
@@ -57,13 +67,6 @@ impl<const PORT: u8> I2cAccess<PORT> {
             _ => panic!("invalid i2c port {PORT}")
         };
 
-        let hw_freq = match PORT {
-            0 | 1 => clock::i2c::get_mcu_i2c_freq(),
-            2 => clock::i2c::get_mm_i2c0_freq(),
-            3 => clock::i2c::get_mm_i2c1_freq(),
-            _ => unreachable!()
-        };
-
         let mut scl = scl.into();
         let mut sda = sda.into();
 
@@ -81,16 +84,6 @@ impl<const PORT: u8> I2cAccess<PORT> {
             config.set_pull(PinPull::Up);
         });
 
-        // hw_freq / freq / 4
-        let phase = hw_freq / (config.frequency * 4);
-        let tmp;
-
-        if config.frequency <= 100_000 {
-            tmp = phase / 4;
-        } else {
-            tmp = phase / 2;
-        }
-
         // Setup registers
         let regs = get_registers::<PORT>();
         
@@ -112,22 +105,9 @@ impl<const PORT: u8> I2cAccess<PORT> {
             reg.fer_mask().fill();
         });
 
-        let mut prd_start_stop = i2c::I2cPrd::default();
-        prd_start_stop.phase_0().set(phase - tmp);
-        prd_start_stop.phase_1().set(phase + tmp);
-        prd_start_stop.phase_2().set(phase);
-        prd_start_stop.phase_3().set(phase);
-        regs.prd_start().set(prd_start_stop);
-        regs.prd_stop().set(prd_start_stop);
-
-        regs.prd_data().set_with(|reg| {
-            reg.phase_0().set(phase - tmp);
-            reg.phase_1().set(phase + tmp);
-            reg.phase_2().set(phase + tmp);
-            reg.phase_3().set(phase - tmp);
-        });
+        set_frequency::<PORT>(config.frequency);
         
-        I2c { scl, sda }
+        I2c { scl, sda, timeout: config.timeout }
 
     }
 
@@ -143,6 +123,8 @@ pub struct I2c<const PORT: u8, Scl: I2cPin, Sda: I2cPin> {
     scl: Scl,
     /// The bus SDA pin (data).
     sda: Sda,
+    /// Maximum duration of a transaction.
+    timeout: Duration,
 }
 
 impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2c<PORT, Scl, Sda> {
@@ -153,6 +135,39 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2c<PORT, Scl, Sda> {
         unsafe { (I2cAccess(()), addr_of!(self.scl).read(), addr_of!(self.sda).read()) }
     }
 
+    /// Change the frequency of the bus, this will be used by the next transactions.
+    pub fn set_frequency(&mut self, frequency: u32) {
+        set_frequency::<PORT>(frequency);
+    }
+
+    /// Change the maximum duration of the next transactions, a transaction that
+    /// takes longer, for example because a slave is stretching the clock forever,
+    /// is aborted and fails. Use [`Duration::MAX`] to disable the timeout.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the maximum duration of transactions.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Internal function to wait until the given condition is true, returning false
+    /// if the slave did not acknowledge or if the deadline is reached, in which case
+    /// the controller is disabled to abort the transaction.
+    #[inline]
+    fn wait_until(deadline: Instant, mut cond: impl FnMut() -> bool) -> bool {
+        while !cond() {
+            if Self::is_nak() {
+                return false
+            } else if Instant::now() >= deadline {
+                Self::disable();
+                return false
+            }
+        }
+        true
+    }
+
     /// Internal function used to setup transfer for I²C read or write
     /// transaction.
     #[inline(never)]
@@ -254,13 +269,12 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2cDev for I2c<PORT, Scl, Sda> {
         Self::enable();
 
         let regs = get_registers::<PORT>();
+        let deadline = Instant::now().saturating_add(self.timeout);
 
         for chunk in data.chunks_mut(4) {
 
-            while regs.fifo_config_1().get().rx_fifo_cnt().get() == 0 {
-                if Self::is_nak() {
-                    return false
-                }
+            if !Self::wait_until(deadline, || regs.fifo_config_1().get().rx_fifo_cnt().get() != 0) {
+                return false
             }
 
             let data = regs.fifo_rdata().get().to_le_bytes();
@@ -268,10 +282,8 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2cDev for I2c<PORT, Scl, Sda> {
 
         }
 
-        while Self::is_busy() || !Self::is_end() {
-            if Self::is_nak() {
-                return false
-            }
+        if !Self::wait_until(deadline, || !Self::is_busy() && Self::is_end()) {
+            return false
         }
 
         Self::disable();
@@ -285,13 +297,12 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2cDev for I2c<PORT, Scl, Sda> {
         Self::init_transfer(slave_addr, sub_addr, I2cDirection::Write, data.len());
         
         let regs = get_registers::<PORT>();
+        let deadline = Instant::now().saturating_add(self.timeout);
         
         for chunk in data.chunks(4) {
 
-            while regs.fifo_config_1().get().tx_fifo_cnt().get() == 0 {
-                if Self::is_nak() {
-                    return false
-                }
+            if !Self::wait_until(deadline, || regs.fifo_config_1().get().tx_fifo_cnt().get() != 0) {
+                return false
             }
             
             let mut chunk_padded = [0; 4];
@@ -304,10 +315,8 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2cDev for I2c<PORT, Scl, Sda> {
 
         }
 
-        while Self::is_busy() || !Self::is_end() {
-            if Self::is_nak() {
-                return false
-            }
+        if !Self::wait_until(deadline, || !Self::is_busy() && Self::is_end()) {
+            return false
         }
         
         Self::disable();
@@ -315,6 +324,10 @@ impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> I2cDev for I2c<PORT, Scl, Sda> {
 
     }
 
+    fn set_frequency(&mut self, frequency: u32) {
+        set_frequency::<PORT>(frequency);
+    }
+
 }
 
 impl<const PORT: u8, Scl: I2cPin, Sda: I2cPin> Drop for I2c<PORT, Scl, Sda> {
@@ -343,6 +356,8 @@ pub struct I2cConfig {
     pub scl_sync: bool,
     /// De-glitch function cycle count (default to 0).
     pub de_glitch_count: u8,
+    /// Maximum duration of a transaction before it's aborted (default to 100 ms).
+    pub timeout: Duration,
 }
 
 impl I2cConfig {
@@ -353,6 +368,7 @@ impl I2cConfig {
             frequency,
             scl_sync: true, 
             de_glitch_count: 0,
+            timeout: Duration::from_millis(100),
         }
     }
     
@@ -420,3 +436,43 @@ fn get_registers<const PORT: u8>() -> I2cRegs {
         _ => unreachable!()
     }
 }
+
+/// Internal function to configure the phases of the given port in order to run at the
+/// given bus frequency.
+fn set_frequency<const PORT: u8>(frequency: u32) {
+
+    let hw_freq = match PORT {
+        0 | 1 => clock::i2c::get_mcu_i2c_freq(),
+        2 => clock::i2c::get_mm_i2c0_freq(),
+        3 => clock::i2c::get_mm_i2c1_freq(),
+        _ => unreachable!()
+    };
+
+    // hw_freq / freq / 4
+    let phase = hw_freq / (frequency * 4);
+    let tmp;
+
+    if frequency <= 100_000 {
+        tmp = phase / 4;
+    } else {
+        tmp = phase / 2;
+    }
+
+    let regs = get_registers::<PORT>();
+
+    let mut prd_start_stop = i2c::I2cPrd::default();
+    prd_start_stop.phase_0().set(phase - tmp);
+    prd_start_stop.phase_1().set(phase + tmp);
+    prd_start_stop.phase_2().set(phase);
+    prd_start_stop.phase_3().set(phase);
+    regs.prd_start().set(prd_start_stop);
+    regs.prd_stop().set(prd_start_stop);
+
+    regs.prd_data().set_with(|reg| {
+        reg.phase_0().set(phase - tmp);
+        reg.phase_1().set(phase + tmp);
+        reg.phase_2().set(phase + tmp);
+        reg.phase_3().set(phase - tmp);
+    });
+
+}
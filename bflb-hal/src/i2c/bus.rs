@@ -0,0 +1,402 @@
+//! Sharing of a single I²C bus between multiple device drivers.
+//!
+//! Drivers such as `Imx477` take ownership of an [`I2cDev`], this module provides
+//! wrappers around a shared bus that hands out one [`I2cDev`] handle per device.
+//! Two variants are provided depending on how the bus is shared:
+//! - [`RefCellDevice`] for sharing within a single execution context (no interrupt);
+//! - [`CriticalSectionDevice`] for sharing between interrupt handlers and main code,
+//!   each transaction runs in a critical section.
+//!
+//! For sharing between async tasks, [`AsyncMutexDevice`] provides an async-only API
+//! where transactions wait for the bus to be released without blocking other tasks.
+//! It doesn't implement [`I2cDev`], so it cannot be given to blocking drivers.
+//!
+//! Each device can be given a default address and a bus frequency, see
+//! [`I2cDeviceConfig`]. If a frequency is given, the bus is reconfigured before each
+//! transaction of that device.
+
+use core::cell::{RefCell, UnsafeCell};
+use core::ops::{Deref, DerefMut};
+use core::task::{Context, Poll, Waker};
+use core::future::Future;
+use core::pin::Pin;
+
+use alloc::vec::Vec;
+
+use critical_section::Mutex;
+
+use super::{I2cDev, I2cAddr, I2cSubAddr};
+
+
+/// Per-device configuration of a shared I²C bus handle.
+#[derive(Debug, Clone, Default)]
+pub struct I2cDeviceConfig {
+    /// Default slave address of the device, used by `read_default` and
+    /// `write_default` methods of the handles.
+    pub addr: Option<I2cAddr>,
+    /// Bus frequency to set before each transaction of the device, `None` to keep
+    /// the current bus frequency.
+    pub frequency: Option<u32>,
+}
+
+impl I2cDeviceConfig {
+
+    /// Create a new device configuration with the given default address and the
+    /// current bus frequency.
+    pub const fn new(addr: I2cAddr) -> Self {
+        Self {
+            addr: Some(addr),
+            frequency: None,
+        }
+    }
+
+    /// Internal function to get the default address, panicking if not present.
+    #[inline]
+    fn default_addr(&self) -> I2cAddr {
+        self.addr.expect("no default address for this i2c device")
+    }
+
+    /// Internal function to prepare the bus before a transaction of this device.
+    #[inline]
+    fn prepare(&self, bus: &mut impl I2cDev) {
+        if let Some(frequency) = self.frequency {
+            bus.set_frequency(frequency);
+        }
+    }
+
+}
+
+
+/// A handle to a device on a bus shared through a [`RefCell`]. Transactions will
+/// panic if the bus is already borrowed, this is typically the case if the bus is
+/// also accessed from an interrupt handler, use [`CriticalSectionDevice`] instead.
+pub struct RefCellDevice<'a, D: I2cDev> {
+    bus: &'a RefCell<D>,
+    config: I2cDeviceConfig,
+}
+
+impl<'a, D: I2cDev> RefCellDevice<'a, D> {
+
+    /// Create a new device handle on the given shared bus.
+    pub fn new(bus: &'a RefCell<D>, config: I2cDeviceConfig) -> Self {
+        Self { bus, config }
+    }
+
+    /// Get the configuration of this device.
+    #[inline]
+    pub fn config(&self) -> &I2cDeviceConfig {
+        &self.config
+    }
+
+    /// Blocking read of this device at its default address.
+    pub fn read_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        self.read(self.config.default_addr(), sub_addr, data)
+    }
+
+    /// Blocking write to this device at its default address.
+    pub fn write_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        self.write(self.config.default_addr(), sub_addr, data)
+    }
+
+}
+
+impl<'a, D: I2cDev> I2cDev for RefCellDevice<'a, D> {
+
+    fn read(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        let mut bus = self.bus.borrow_mut();
+        self.config.prepare(&mut *bus);
+        bus.read(slave_addr, sub_addr, data)
+    }
+
+    fn write(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        let mut bus = self.bus.borrow_mut();
+        self.config.prepare(&mut *bus);
+        bus.write(slave_addr, sub_addr, data)
+    }
+
+    fn set_frequency(&mut self, frequency: u32) {
+        self.config.frequency = Some(frequency);
+    }
+
+}
+
+
+/// A handle to a device on a bus shared through an interrupt-safe mutex. Each
+/// transaction is run in a critical section, so the bus can safely be shared with
+/// interrupt handlers, at the cost of interrupt latency for the duration of the
+/// transaction.
+pub struct CriticalSectionDevice<'a, D: I2cDev> {
+    bus: &'a Mutex<RefCell<D>>,
+    config: I2cDeviceConfig,
+}
+
+impl<'a, D: I2cDev> CriticalSectionDevice<'a, D> {
+
+    /// Create a new device handle on the given shared bus.
+    pub fn new(bus: &'a Mutex<RefCell<D>>, config: I2cDeviceConfig) -> Self {
+        Self { bus, config }
+    }
+
+    /// Get the configuration of this device.
+    #[inline]
+    pub fn config(&self) -> &I2cDeviceConfig {
+        &self.config
+    }
+
+    /// Blocking read of this device at its default address.
+    pub fn read_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        self.read(self.config.default_addr(), sub_addr, data)
+    }
+
+    /// Blocking write to this device at its default address.
+    pub fn write_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        self.write(self.config.default_addr(), sub_addr, data)
+    }
+
+}
+
+impl<'a, D: I2cDev> I2cDev for CriticalSectionDevice<'a, D> {
+
+    fn read(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        critical_section::with(|cs| {
+            let mut bus = self.bus.borrow_ref_mut(cs);
+            self.config.prepare(&mut *bus);
+            bus.read(slave_addr, sub_addr, data)
+        })
+    }
+
+    fn write(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        critical_section::with(|cs| {
+            let mut bus = self.bus.borrow_ref_mut(cs);
+            self.config.prepare(&mut *bus);
+            bus.write(slave_addr, sub_addr, data)
+        })
+    }
+
+    fn set_frequency(&mut self, frequency: u32) {
+        self.config.frequency = Some(frequency);
+    }
+
+}
+
+
+/// An I²C bus shared between async tasks. Tasks wanting to run a transaction while
+/// the bus is locked by another task are suspended until the bus is released.
+pub struct AsyncMutex<D> {
+    /// Locked state and tasks waiting for the bus to be released.
+    state: Mutex<RefCell<AsyncMutexState>>,
+    /// The bus, only accessed through a guard.
+    bus: UnsafeCell<D>,
+}
+
+/// Internal state of the async mutex.
+struct AsyncMutexState {
+    locked: bool,
+    /// Wakers of the pending futures, with their registration id.
+    waiters: Vec<(usize, Waker)>,
+    /// Registration id given to the next pending future.
+    next_id: usize,
+}
+
+// SAFETY: The bus is only accessed through a guard, and only one guard can exist at
+// a time thanks to the locked state.
+unsafe impl<D: Send> Sync for AsyncMutex<D> {}
+
+impl<D> AsyncMutex<D> {
+
+    /// Create a new async mutex around the given bus.
+    pub const fn new(bus: D) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(AsyncMutexState {
+                locked: false,
+                waiters: Vec::new(),
+                next_id: 0,
+            })),
+            bus: UnsafeCell::new(bus),
+        }
+    }
+
+    /// Lock the bus, the returned future resolves to a guard when the bus is available.
+    pub fn lock(&self) -> AsyncMutexLock<'_, D> {
+        AsyncMutexLock { mutex: self, id: None }
+    }
+
+    /// Try locking the bus, returning `None` if it's already locked.
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, D>> {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            if state.locked {
+                None
+            } else {
+                state.locked = true;
+                Some(AsyncMutexGuard { mutex: self })
+            }
+        })
+    }
+
+    /// Get back the bus from the mutex.
+    pub fn into_inner(self) -> D {
+        self.bus.into_inner()
+    }
+
+}
+
+/// Future returned by [`AsyncMutex::lock`].
+pub struct AsyncMutexLock<'a, D> {
+    mutex: &'a AsyncMutex<D>,
+    /// Registration id of the waker of this future in the mutex, while pending.
+    id: Option<usize>,
+}
+
+impl<'a, D> AsyncMutexLock<'a, D> {
+
+    /// Internal function to register or update the waker of this future in the
+    /// mutex. The previous registration may have been removed when the bus has been
+    /// released, a new one is then created.
+    fn register(&mut self, state: &mut AsyncMutexState, waker: &Waker) {
+        if let Some(id) = self.id {
+            if let Some((_, registered)) = state.waiters.iter_mut().find(|(other, _)| *other == id) {
+                if !registered.will_wake(waker) {
+                    *registered = waker.clone();
+                }
+                return;
+            }
+        }
+        let id = state.next_id;
+        state.next_id = id.wrapping_add(1);
+        state.waiters.push((id, waker.clone()));
+        self.id = Some(id);
+    }
+
+    /// Internal function to remove the waker registered by this future from the
+    /// mutex, if any.
+    fn unregister(&mut self, state: &mut AsyncMutexState) {
+        if let Some(id) = self.id.take() {
+            if let Some(idx) = state.waiters.iter().position(|(other, _)| *other == id) {
+                state.waiters.swap_remove(idx);
+            }
+        }
+    }
+
+}
+
+impl<'a, D> Future for AsyncMutexLock<'a, D> {
+
+    type Output = AsyncMutexGuard<'a, D>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mutex = this.mutex;
+        critical_section::with(|cs| {
+            let mut state = mutex.state.borrow_ref_mut(cs);
+            if state.locked {
+                this.register(&mut state, cx.waker());
+                Poll::Pending
+            } else {
+                this.unregister(&mut state);
+                state.locked = true;
+                Poll::Ready(AsyncMutexGuard { mutex })
+            }
+        })
+    }
+
+}
+
+impl<'a, D> Drop for AsyncMutexLock<'a, D> {
+    fn drop(&mut self) {
+        if self.id.is_some() {
+            let mutex = self.mutex;
+            critical_section::with(|cs| self.unregister(&mut mutex.state.borrow_ref_mut(cs)));
+        }
+    }
+}
+
+/// Exclusive access to the bus of an [`AsyncMutex`], the bus is released on drop.
+pub struct AsyncMutexGuard<'a, D> {
+    mutex: &'a AsyncMutex<D>,
+}
+
+impl<'a, D> Deref for AsyncMutexGuard<'a, D> {
+    type Target = D;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: We are the only guard.
+        unsafe { &*self.mutex.bus.get() }
+    }
+}
+
+impl<'a, D> DerefMut for AsyncMutexGuard<'a, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: We are the only guard.
+        unsafe { &mut *self.mutex.bus.get() }
+    }
+}
+
+impl<'a, D> Drop for AsyncMutexGuard<'a, D> {
+    fn drop(&mut self) {
+        // Wake every waiting task, the first one to be polled will get the bus.
+        let waiters = critical_section::with(|cs| {
+            let mut state = self.mutex.state.borrow_ref_mut(cs);
+            state.locked = false;
+            core::mem::take(&mut state.waiters)
+        });
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+}
+
+
+/// A handle to a device on a bus shared between async tasks through an
+/// [`AsyncMutex`]. This is an async-only API: this handle doesn't implement
+/// [`I2cDev`] because transactions need to wait for the bus, so it cannot be given
+/// to blocking drivers, but it provides equivalent async methods.
+pub struct AsyncMutexDevice<'a, D: I2cDev> {
+    bus: &'a AsyncMutex<D>,
+    config: I2cDeviceConfig,
+}
+
+impl<'a, D: I2cDev> AsyncMutexDevice<'a, D> {
+
+    /// Create a new device handle on the given shared bus.
+    pub fn new(bus: &'a AsyncMutex<D>, config: I2cDeviceConfig) -> Self {
+        Self { bus, config }
+    }
+
+    /// Get the configuration of this device.
+    #[inline]
+    pub fn config(&self) -> &I2cDeviceConfig {
+        &self.config
+    }
+
+    /// Change the frequency of the bus for the next transactions of this device.
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.config.frequency = Some(frequency);
+    }
+
+    /// Read of an I²C slave on the bus, waiting for the bus to be available.
+    pub async fn read(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        let mut bus = self.bus.lock().await;
+        self.config.prepare(&mut *bus);
+        bus.read(slave_addr, sub_addr, data)
+    }
+
+    /// Write to an I²C slave on the bus, waiting for the bus to be available.
+    pub async fn write(&mut self, slave_addr: I2cAddr, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        let mut bus = self.bus.lock().await;
+        self.config.prepare(&mut *bus);
+        bus.write(slave_addr, sub_addr, data)
+    }
+
+    /// Read of this device at its default address.
+    pub async fn read_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &mut [u8]) -> bool {
+        let addr = self.config.default_addr();
+        self.read(addr, sub_addr, data).await
+    }
+
+    /// Write to this device at its default address.
+    pub async fn write_default(&mut self, sub_addr: Option<I2cSubAddr>, data: &[u8]) -> bool {
+        let addr = self.config.default_addr();
+        self.write(addr, sub_addr, data).await
+    }
+
+}
@@ -145,29 +145,41 @@ pub fn noop_handler(_code: usize, _cs: CriticalSection) { }
 
 /// This constant array can be used as a base interrupt vector to be used by the HAL and 
 /// supported by the runtime. Each handle takes a critical section token for proving that
-/// the handler is effectively running with interrupts disabled.
-pub const VECTOR: [InterruptHandler; COUNT] = {
+/// the handler is effectively running with interrupts disabled. Interrupts that are not
+/// handled by the HAL are `None`, the runtime is free to dispatch them.
+pub const VECTOR: [Option<InterruptHandler>; COUNT] = {
 
-    let mut handlers: [InterruptHandler; COUNT] = [noop_handler; COUNT];
+    let mut handlers: [Option<InterruptHandler>; COUNT] = [None; COUNT];
 
-    handlers[MACHINE_TIMER.code] = crate::time::mtimer_handler;
+    handlers[MACHINE_TIMER.code] = Some(crate::time::mtimer_handler);
 
     #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
     {
-        handlers[DMA0_ALL.code] = super::dma::dma0_handler;
-        handlers[DMA1_ALL.code] = super::dma::dma1_handler;
+        handlers[DMA0_ALL.code] = Some(super::dma::dma0_handler);
+        handlers[DMA1_ALL.code] = Some(super::dma::dma1_handler);
+        handlers[PWN.code] = Some(super::pwm::pwm_handler);
+        handlers[GPADC_DMA.code] = Some(super::adc::gpadc_handler);
+        handlers[TIMER0_CH0.code] = Some(super::timer::timer_ch0_handler);
+        handlers[TIMER0_CH1.code] = Some(super::timer::timer_ch1_handler);
+        handlers[TIMER0_WDT.code] = Some(super::watchdog::watchdog_handler);
+        handlers[HBN_OUT0.code] = Some(super::rtc::rtc_handler);
+        handlers[HBN_OUT1.code] = Some(super::acomp::acomp_handler);
     }
 
     #[cfg(feature = "bl808-d0")]
     {
-        handlers[DMA2_INT0.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT1.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT2.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT3.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT4.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT5.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT6.code] = super::dma::dma2_handler;
-        handlers[DMA2_INT7.code] = super::dma::dma2_handler;
+        handlers[DMA2_INT0.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT1.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT2.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT3.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT4.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT5.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT6.code] = Some(super::dma::dma2_handler);
+        handlers[DMA2_INT7.code] = Some(super::dma::dma2_handler);
+        handlers[PWM.code] = Some(super::pwm::pwm_handler);
+        handlers[TIMER1_CH0.code] = Some(super::timer::timer_ch0_handler);
+        handlers[TIMER1_CH1.code] = Some(super::timer::timer_ch1_handler);
+        handlers[TIMER1_WDT.code] = Some(super::watchdog::watchdog_handler);
     }
 
     handlers
@@ -35,20 +35,45 @@ pub mod power;
 pub mod time;
 pub mod cpu;
 pub mod dma;
+#[cfg(feature = "bl808-d0")]
+pub mod dma2d;
+pub mod efuse;
 
 // I/O abstractions.
 pub mod gpio;
 pub mod uart;
 pub mod i2c;
+pub mod spi;
+pub mod pwm;
+pub mod timer;
+pub mod watchdog;
 pub mod adc;
+pub mod dac;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub mod acomp;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub mod rtc;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub mod sleep;
 
 // Internal reuses.
 use cpu::CpuControl;
 
 use gpio::PinAccess;
 use uart::UartAccess;
+use spi::SpiAccess;
+use pwm::PwmAccess;
+use timer::TimerAccess;
+use watchdog::WatchdogAccess;
 use adc::AdcAccess;
+use dac::DacAccess;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+use acomp::AcompAccess;
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+use rtc::RtcAccess;
 use dma::Dma;
+#[cfg(feature = "bl808-d0")]
+use dma2d::Dma2dAccess;
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
@@ -118,10 +143,29 @@ pub struct Peripherals {
     pub gpio: Gpio,
     /// UART ports access.
     pub uart: Uart,
+    /// SPI ports access.
+    pub spi: Spi,
+    /// PWM groups access.
+    pub pwm: Pwm,
+    /// Timer channels access, for the timer of the current core.
+    pub timer: Timer,
+    /// Watchdog access, for the watchdog of the current core.
+    pub watchdog: WatchdogAccess,
     /// DMA ports access.
     pub dma: Dma,
+    /// DMA2D engine access.
+    #[cfg(feature = "bl808-d0")]
+    pub dma2d: Dma2dAccess,
     /// ADC peripheral access.
     pub adc: AdcAccess,
+    /// DAC peripheral access.
+    pub dac: DacAccess,
+    /// Analog comparators access.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub acomp: Acomp,
+    /// Real-time clock access.
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    pub rtc: RtcAccess,
 }
 
 impl Peripherals {
@@ -214,8 +258,31 @@ impl Peripherals {
                 p1: UartAccess(()),
                 p2: UartAccess(()),
             },
+            spi: Spi {
+                p0: SpiAccess(()),
+                p1: SpiAccess(()),
+            },
+            pwm: Pwm {
+                p0: PwmAccess(()),
+                p1: PwmAccess(()),
+            },
+            timer: Timer {
+                c0: TimerAccess(()),
+                c1: TimerAccess(()),
+            },
+            watchdog: WatchdogAccess(()),
             dma: Dma::new(),
+            #[cfg(feature = "bl808-d0")]
+            dma2d: Dma2dAccess(()),
             adc: AdcAccess(()),
+            dac: DacAccess(()),
+            #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+            acomp: Acomp {
+                c0: AcompAccess(()),
+                c1: AcompAccess(()),
+            },
+            #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+            rtc: RtcAccess(()),
         }
     }
 
@@ -281,3 +348,28 @@ pub struct Uart {
     pub p1: UartAccess<1>,
     pub p2: UartAccess<2>,
 }
+
+/// This peripheral structure wrap ports of SPI controller.
+pub struct Spi {
+    pub p0: SpiAccess<0>,
+    pub p1: SpiAccess<1>,
+}
+
+/// This peripheral structure wrap groups of PWM controller.
+pub struct Pwm {
+    pub p0: PwmAccess<0>,
+    pub p1: PwmAccess<1>,
+}
+
+/// This peripheral structure wrap channels of the timer controller.
+pub struct Timer {
+    pub c0: TimerAccess<0>,
+    pub c1: TimerAccess<1>,
+}
+
+/// This peripheral structure wrap the analog comparators.
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+pub struct Acomp {
+    pub c0: AcompAccess<0>,
+    pub c1: AcompAccess<1>,
+}
@@ -0,0 +1,493 @@
+//! PWM (Pulse-Width Modulation) management on BL808.
+//!
+//! Two PWM groups are available, each group has its own counter, running at a common
+//! frequency for its 4 channels. Each channel has a positive output and a negative
+//! (complementary) output, with an optional dead time between them.
+//!
+//! Each channel can be attached to any pin, the pin number defines the output: pin
+//! `8n+c` is the positive output of channel `c` and pin `8n+4+c` is its negative
+//! output.
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use core::ptr::addr_of;
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{PWM, pwm::{PwmGroup, PwmIntBits}};
+use crate::gpio::{Pin, PinPull, PinDrive, PinFunction, Alternate};
+use crate::sealed::Sealed;
+use crate::clock;
+
+
+/// Definition of an exclusive access to a PWM group. This group need to be configured
+/// in order to obtain a [`Pwm`] structure that is actually usable.
+///
+/// Available groups: 0, 1.
+pub struct PwmAccess<const GROUP: u8>(pub(crate) ());
+
+impl<const GROUP: u8> PwmAccess<GROUP> {
+
+    /// Initialize this PWM group with the given configuration. The group is started
+    /// with all channels disabled, see [`Pwm::channel`].
+    pub fn init(self, config: &PwmConfig) -> Pwm<GROUP> {
+
+        let regs = get_registers::<GROUP>();
+
+        stop::<GROUP>();
+
+        regs.config0().modify(|reg| {
+            reg.reg_clk_sel().set(config.clock_source as _);
+            reg.stop_on_rept().clear();
+            reg.sw_break_en().clear();
+            reg.ext_break_en().clear();
+            reg.stop_mode().fill();
+        });
+
+        regs.config1().set(Default::default());
+        regs.dead_time().set(Default::default());
+
+        for channel in 0..4 {
+            regs.thre(channel).set(Default::default());
+        }
+
+        regs.int_en().set(PwmIntBits(0));
+        regs.int_mask().set(PwmIntBits(PwmIntBits::ALL));
+        regs.int_clear().set(PwmIntBits(PwmIntBits::ALL));
+
+        set_frequency::<GROUP>(config.frequency);
+
+        regs.config0().modify(|reg| reg.stop_en().clear());
+
+        Pwm {
+            channels: Cell::new(0),
+        }
+
+    }
+
+}
+
+
+/// An initialized PWM group.
+pub struct Pwm<const GROUP: u8> {
+    /// Bit mask of channels currently in use.
+    channels: Cell<u8>,
+}
+
+impl<const GROUP: u8> Pwm<GROUP> {
+
+    /// Downgrade this PWM group and stop it.
+    pub fn downgrade(self) -> PwmAccess<GROUP> {
+        // Drop will be called at the end, effectively stopping the group.
+        PwmAccess(())
+    }
+
+    /// Change the frequency of the group counter, the duty cycle of all channels is
+    /// reset to zero because the period changes.
+    pub fn set_frequency(&self, frequency: u32) {
+        let regs = get_registers::<GROUP>();
+        for channel in 0..4 {
+            regs.thre(channel).set(Default::default());
+        }
+        set_frequency::<GROUP>(frequency);
+    }
+
+    /// Get the real frequency of the group counter.
+    pub fn frequency(&self) -> u32 {
+        let regs = get_registers::<GROUP>();
+        let div = regs.config0().get().clk_div().get().max(1);
+        let period = regs.period().get().period().get().max(1);
+        get_source_freq::<GROUP>() / div / period
+    }
+
+    /// Get the period of the counter, this is also the maximum duty cycle.
+    #[inline]
+    pub fn period(&self) -> u16 {
+        get_registers::<GROUP>().period().get().period().get() as u16
+    }
+
+    /// Start the group counter, this is done at initialization.
+    pub fn start(&self) {
+        get_registers::<GROUP>().config0().modify(|reg| reg.stop_en().clear());
+    }
+
+    /// Stop the group counter at the end of the current period, outputs return to
+    /// their idle state.
+    pub fn stop(&self) {
+        stop::<GROUP>();
+    }
+
+    /// Attach a channel of this group to the given positive and negative pins, both
+    /// can be `()` if not used. The channel starts with a zero duty cycle.
+    ///
+    /// This function panics if the channel is already in use, or if a pin cannot be
+    /// attached to the requested output.
+    pub fn channel<const CHANNEL: u8, P, N>(&self, mut positive: P, mut negative: N, config: &PwmChannelConfig) -> PwmChannel<'_, GROUP, CHANNEL, P, N>
+    where
+        P: PwmPin,
+        N: PwmPin,
+    {
+
+        assert!(CHANNEL < 4, "invalid pwm channel {CHANNEL}");
+
+        let channels = self.channels.get();
+        if channels & (1 << CHANNEL) != 0 {
+            panic!("pwm channel {CHANNEL} already in use");
+        }
+        self.channels.set(channels | (1 << CHANNEL));
+
+        let func = match GROUP {
+            0 => PinFunction::Pwm0,
+            1 => PinFunction::Pwm1,
+            _ => unreachable!()
+        };
+
+        positive.attach(CHANNEL, false, func);
+        negative.attach(CHANNEL, true, func);
+
+        let regs = get_registers::<GROUP>();
+
+        regs.thre(CHANNEL as usize).set(Default::default());
+
+        critical_section::with(|_| {
+
+            regs.dead_time().modify(|reg| reg.set(CHANNEL, config.dead_time));
+
+            regs.config1().modify(|reg| {
+                reg.set_positive_polarity(CHANNEL, config.positive_polarity == PwmPolarity::ActiveHigh);
+                reg.set_negative_polarity(CHANNEL, config.negative_polarity == PwmPolarity::ActiveHigh);
+                reg.set_positive_idle(CHANNEL, config.positive_polarity == PwmPolarity::ActiveLow);
+                reg.set_negative_idle(CHANNEL, config.negative_polarity == PwmPolarity::ActiveLow);
+                reg.set_positive_break(CHANNEL, config.positive_polarity == PwmPolarity::ActiveLow);
+                reg.set_negative_break(CHANNEL, config.negative_polarity == PwmPolarity::ActiveLow);
+                reg.set_positive_enable(CHANNEL, P::PRESENT);
+                reg.set_negative_enable(CHANNEL, N::PRESENT);
+            });
+
+        });
+
+        PwmChannel {
+            pwm: self,
+            positive,
+            negative,
+        }
+
+    }
+
+    /// Set a callback to be called at the end of each period of the group counter.
+    ///
+    /// *This method is only available on the CPU type that supports interrupts for
+    /// PWM.*
+    pub fn set_period_callback<F>(&self, callback: F)
+    where
+        Self: PwmInterruptSupport,
+        F: FnMut() + Send + 'static,
+    {
+
+        let regs = get_registers::<GROUP>();
+
+        critical_section::with(|cs| {
+            *CALLBACKS[GROUP as usize].borrow_ref_mut(cs) = Some(Box::new(callback));
+            regs.int_clear().set(PwmIntBits(PwmIntBits::PERIOD));
+            regs.int_en().modify(|reg| reg.set(PwmIntBits::PERIOD, true));
+            regs.int_mask().modify(|reg| reg.set(PwmIntBits::PERIOD, false));
+        });
+
+        unsafe { <Self as PwmInterruptSupport>::enable_interrupt() }
+
+    }
+
+    /// Remove the period callback, if any.
+    pub fn clear_period_callback(&self) {
+
+        let regs = get_registers::<GROUP>();
+
+        critical_section::with(|cs| {
+            regs.int_mask().modify(|reg| reg.set(PwmIntBits::PERIOD, true));
+            regs.int_en().modify(|reg| reg.set(PwmIntBits::PERIOD, false));
+            regs.int_clear().set(PwmIntBits(PwmIntBits::PERIOD));
+            CALLBACKS[GROUP as usize].borrow_ref_mut(cs).take();
+        });
+
+    }
+
+}
+
+impl<const GROUP: u8> Drop for Pwm<GROUP> {
+    fn drop(&mut self) {
+        self.clear_period_callback();
+        stop::<GROUP>();
+    }
+}
+
+
+/// A channel of a PWM group, with its positive and negative outputs.
+pub struct PwmChannel<'a, const GROUP: u8, const CHANNEL: u8, P, N> {
+    pwm: &'a Pwm<GROUP>,
+    positive: P,
+    negative: N,
+}
+
+impl<'a, const GROUP: u8, const CHANNEL: u8, P, N> PwmChannel<'a, GROUP, CHANNEL, P, N> {
+
+    /// Free this channel and get back the pins.
+    pub fn free(self) -> (P, N) {
+        // Drop will be called at the end, effectively disabling the channel.
+        unsafe { (
+            addr_of!(self.positive).read(),
+            addr_of!(self.negative).read(),
+        ) }
+    }
+
+    /// Get the current duty cycle, in counter cycles.
+    #[inline]
+    pub fn duty_cycle(&self) -> u16 {
+        get_registers::<GROUP>().thre(CHANNEL as usize).get().threh().get() as u16
+    }
+
+    /// Set the duty cycle, in counter cycles, it's clamped to the period of the group
+    /// (see [`Pwm::period`]).
+    pub fn set_duty_cycle(&mut self, duty: u16) {
+        let duty = duty.min(self.pwm.period());
+        get_registers::<GROUP>().thre(CHANNEL as usize).set_with(|reg| {
+            reg.threl().clear();
+            reg.threh().set(duty as u32);
+        });
+    }
+
+    /// Change the dead time between the positive and negative outputs, in divided
+    /// clock cycles.
+    pub fn set_dead_time(&mut self, dead_time: u8) {
+        critical_section::with(|_| {
+            get_registers::<GROUP>().dead_time().modify(|reg| reg.set(CHANNEL, dead_time));
+        });
+    }
+
+}
+
+impl<'a, const GROUP: u8, const CHANNEL: u8, P, N> Drop for PwmChannel<'a, GROUP, CHANNEL, P, N> {
+    fn drop(&mut self) {
+        let regs = get_registers::<GROUP>();
+        critical_section::with(|_| {
+            regs.config1().modify(|reg| {
+                reg.set_positive_enable(CHANNEL, false);
+                reg.set_negative_enable(CHANNEL, false);
+            });
+        });
+        regs.thre(CHANNEL as usize).set(Default::default());
+        self.pwm.channels.set(self.pwm.channels.get() & !(1 << CHANNEL));
+    }
+}
+
+impl<'a, const GROUP: u8, const CHANNEL: u8, P, N> embedded_hal::pwm::ErrorType for PwmChannel<'a, GROUP, CHANNEL, P, N> {
+    type Error = Infallible;
+}
+
+impl<'a, const GROUP: u8, const CHANNEL: u8, P, N> embedded_hal::pwm::SetDutyCycle for PwmChannel<'a, GROUP, CHANNEL, P, N> {
+
+    fn max_duty_cycle(&self) -> u16 {
+        self.pwm.period()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        PwmChannel::set_duty_cycle(self, duty);
+        Ok(())
+    }
+
+}
+
+
+/// Internal trait used to attach an optional pin to a PWM output.
+pub trait PwmPin: Sealed {
+    /// True if this is an actual pin.
+    const PRESENT: bool;
+    /// Attach this pin to the given channel output, panicking if the pin cannot carry
+    /// it.
+    fn attach(&mut self, channel: u8, negative: bool, func: PinFunction);
+}
+
+impl PwmPin for () {
+    const PRESENT: bool = false;
+    fn attach(&mut self, _channel: u8, _negative: bool, _func: PinFunction) {}
+}
+
+impl<const NUM: u8> PwmPin for Pin<NUM, Alternate> {
+    const PRESENT: bool = true;
+    fn attach(&mut self, channel: u8, negative: bool, func: PinFunction) {
+        if NUM % 8 != channel + if negative { 4 } else { 0 } {
+            panic!("invalid pwm channel {channel} {} pin {NUM}", if negative { "negative" } else { "positive" });
+        }
+        self.modify_config(|cfg| {
+            cfg.set_function(func);
+            cfg.set_pull(PinPull::Float);
+            cfg.set_drive(PinDrive::Drive1);
+        });
+    }
+}
+
+
+/// Trait implemented on PWM groups that support interrupts on the current chip.
+pub trait PwmInterruptSupport {
+    /// SAFETY: Caller must ensure that enabling the interrupt is not an issue.
+    unsafe fn enable_interrupt();
+}
+
+#[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+impl<const GROUP: u8> PwmInterruptSupport for Pwm<GROUP> {
+    unsafe fn enable_interrupt() {
+        unsafe { crate::interrupt::PWN.set_enabled(true) }
+    }
+}
+
+#[cfg(feature = "bl808-d0")]
+impl<const GROUP: u8> PwmInterruptSupport for Pwm<GROUP> {
+    unsafe fn enable_interrupt() {
+        unsafe { crate::interrupt::PWM.set_enabled(true) }
+    }
+}
+
+
+/// Type alias for a boxed closure used as a PWM period callback.
+type PwmCallback = Box<dyn FnMut() + Send>;
+/// Default value: no callback.
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CALLBACK: Mutex<RefCell<Option<PwmCallback>>> = Mutex::new(RefCell::new(None));
+/// Period callbacks for each group.
+static CALLBACKS: [Mutex<RefCell<Option<PwmCallback>>>; 2] = [NO_CALLBACK; 2];
+
+/// Interrupt handler for PWM interrupts.
+pub(crate) fn pwm_handler(_code: usize, cs: CriticalSection) {
+
+    // Clear the global interrupt status of both groups.
+    PWM.int_config().modify(|reg| reg.int_clear().set(0b11));
+
+    for (group, callback) in CALLBACKS.iter().enumerate() {
+        let regs = PWM.group(group);
+        let status = regs.int_sts().get();
+        regs.int_clear().set(status);
+        if status.get(PwmIntBits::PERIOD) {
+            if let Some(callback) = callback.borrow_ref_mut(cs).as_mut() {
+                callback();
+            }
+        }
+    }
+
+    PWM.int_config().modify(|reg| reg.int_clear().clear());
+
+}
+
+
+/// Return the registers of the given group.
+#[inline]
+fn get_registers<const GROUP: u8>() -> PwmGroup {
+    match GROUP {
+        0 | 1 => PWM.group(GROUP as usize),
+        _ => unreachable!()
+    }
+}
+
+/// Internal function to get the frequency of the clock source of the group.
+fn get_source_freq<const GROUP: u8>() -> u32 {
+    match get_registers::<GROUP>().config0().get().reg_clk_sel().get() {
+        0 => clock::get_xclk_freq(),
+        1 => clock::mcu::get_mcu_pbclk_freq(),
+        _ => clock::get_f32k_freq(),
+    }
+}
+
+/// Internal function to configure the divider and period of the given group in order
+/// to run at the given frequency, a null frequency is handled as the slowest frequency.
+fn set_frequency<const GROUP: u8>(frequency: u32) {
+
+    let src_freq = get_source_freq::<GROUP>();
+    let frequency = frequency.max(1);
+
+    // Find the smallest divider giving a period that fits in 16 bits, in order to
+    // get the best duty cycle resolution.
+    let div = src_freq.div_ceil(frequency.saturating_mul(0xFFFF)).clamp(1, 0xFFFF);
+    let period = (src_freq / div / frequency).clamp(1, 0xFFFF);
+
+    let regs = get_registers::<GROUP>();
+    regs.config0().modify(|reg| reg.clk_div().set(div));
+    regs.period().modify(|reg| reg.period().set(period));
+
+}
+
+/// Internal function to stop the group counter and wait for it to be stopped.
+fn stop<const GROUP: u8>() {
+    let regs = get_registers::<GROUP>();
+    regs.config0().modify(|reg| reg.stop_en().fill());
+    while regs.config0().get().sts_stop().get() == 0 {}
+}
+
+
+/// Configuration structure for PWM group initialization.
+#[derive(Debug, Clone)]
+pub struct PwmConfig {
+    /// Frequency of the group counter, this is the frequency of output signals.
+    pub frequency: u32,
+    /// Clock source of the group counter.
+    pub clock_source: PwmClockSource,
+}
+
+impl PwmConfig {
+
+    /// Create a new basic config for the given frequency, using XCLK as clock source.
+    pub const fn new(frequency: u32) -> Self {
+        Self {
+            frequency,
+            clock_source: PwmClockSource::Xclk,
+        }
+    }
+
+}
+
+/// Configuration structure for a PWM channel.
+#[derive(Debug, Clone)]
+pub struct PwmChannelConfig {
+    /// Polarity of the positive output.
+    pub positive_polarity: PwmPolarity,
+    /// Polarity of the negative output.
+    pub negative_polarity: PwmPolarity,
+    /// Dead time between the positive and negative outputs, in divided clock cycles.
+    pub dead_time: u8,
+}
+
+impl PwmChannelConfig {
+
+    /// Create a new basic channel config, with active high outputs and no dead time.
+    pub const fn new() -> Self {
+        Self {
+            positive_polarity: PwmPolarity::ActiveHigh,
+            negative_polarity: PwmPolarity::ActiveHigh,
+            dead_time: 0,
+        }
+    }
+
+}
+
+impl Default for PwmChannelConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Clock source of a PWM group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmClockSource {
+    Xclk = 0,
+    /// MCU peripheral bus clock.
+    Bclk = 1,
+    F32k = 2,
+}
+
+/// Polarity of a PWM output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmPolarity {
+    /// The output is high during the duty cycle.
+    ActiveHigh,
+    /// The output is low during the duty cycle.
+    ActiveLow,
+}
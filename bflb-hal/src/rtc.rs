@@ -0,0 +1,546 @@
+//! Real-time clock on the HBN (hibernate) RTC.
+//!
+//! The RTC is a 40-bit counter clocked by the F32k clock in the always-on domain, it
+//! keeps counting across core resets and HBN sleep, as long as the chip is powered.
+//! The wall-clock time is the counter plus a base offset that is saved, with the
+//! counter frequency, in the last bytes of the HBN RAM, so that it's retained as well.
+//! The last counter value seen is also saved, in order to detect when the counter
+//! wraps, about every 388 days at 32768 Hz, so the time must be read at least once
+//! in this interval.
+//! The time is expressed as a [`Duration`] since the UNIX epoch or as a [`DateTime`]
+//! in UTC.
+//!
+//! An alarm can be set to trigger the `HBN_OUT0` interrupt, it also wakes the chip up
+//! from PDS mode.
+//!
+//! When the F32k clock is the RC32K oscillator, its frequency is not accurate and can
+//! be measured against the crystal with [`Rtc::calibrate`].
+
+use core::cell::{Cell, RefCell};
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{HBN, PDS, addr};
+use crate::time::{self, Duration, Instant};
+use crate::clock;
+use crate::interrupt;
+
+
+/// Exclusive access to the RTC. It need to be initialized in order to obtain a
+/// [`Rtc`] structure that is actually usable.
+pub struct RtcAccess(pub(crate) ());
+
+impl RtcAccess {
+
+    /// Initialize the RTC, the counter is started if not already running, and the
+    /// wall-clock time is kept if it has been set before a core reset or HBN sleep.
+    pub fn init(self) -> Rtc {
+
+        enable_counter();
+
+        let mut rtc = Rtc { state: Cell::new(RetainedState::load()) };
+        rtc.clear_alarm();
+        rtc
+
+    }
+
+}
+
+
+/// An initialized RTC.
+pub struct Rtc {
+    /// Copy of the retained state, updated when reading the time.
+    state: Cell<RetainedState>,
+}
+
+impl Rtc {
+
+    /// Downgrade this RTC and disable its alarm, the counter keeps running.
+    pub fn downgrade(self) -> RtcAccess {
+        // Drop will be called at the end, effectively disabling the alarm.
+        RtcAccess(())
+    }
+
+    /// Get the current value of the 40-bit counter.
+    #[inline]
+    pub fn counter(&self) -> u64 {
+        read_counter()
+    }
+
+    /// Get the frequency of the counter, the one of the F32k clock unless it has
+    /// been calibrated.
+    #[inline]
+    pub fn frequency(&self) -> u32 {
+        self.state.get().frequency
+    }
+
+    /// Return true if the wall-clock time has been set since the chip was powered.
+    #[inline]
+    pub fn is_set(&self) -> bool {
+        self.state.get().set
+    }
+
+    /// Get the time elapsed since the UNIX epoch, or since the counter was started if
+    /// the time has not been set.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp_at(self.counter())
+    }
+
+    /// Set the time elapsed since the UNIX epoch.
+    pub fn set_timestamp(&mut self, since_epoch: Duration) {
+        let mut state = self.state.get();
+        let counter = self.counter();
+        state.base = since_epoch.to_ticks(state.frequency).wrapping_sub(counter);
+        state.last = counter;
+        state.set = true;
+        self.update_state(state);
+    }
+
+    /// Get the current date and time, in UTC.
+    pub fn datetime(&self) -> DateTime {
+        DateTime::from_timestamp(self.timestamp().as_secs())
+    }
+
+    /// Set the current date and time, in UTC.
+    ///
+    /// This function panics if the date and time is not valid, see
+    /// [`DateTime::is_valid`].
+    pub fn set_datetime(&mut self, datetime: &DateTime) {
+        self.set_timestamp(Duration::from_secs(datetime.timestamp()));
+    }
+
+    /// Measure the frequency of the counter against the core timer during the given
+    /// duration and use it for all later conversions, returning the measured frequency.
+    /// The wall-clock time is kept.
+    ///
+    /// The core timer is derived from the crystal when it's the root clock, so this
+    /// is typically used to compensate for the inaccuracy of the RC32K oscillator.
+    /// A longer duration gives a better precision, one second gives a precision of
+    /// about 30 ppm.
+    pub fn calibrate(&mut self, duration: Duration) -> u32 {
+
+        let start_counter = self.counter();
+        let start = Instant::now();
+        time::wait(duration);
+        let end_counter = self.counter();
+        let elapsed = start.elapsed();
+
+        let ticks = counter_elapsed(start_counter, end_counter);
+        let frequency = (ticks as u128 * 1_000_000 / elapsed.as_micros().max(1) as u128) as u32;
+
+        // Rebase the wall-clock time on the new frequency.
+        let counter = self.counter();
+        let timestamp = self.timestamp_at(counter);
+        let mut state = self.state.get();
+        state.frequency = frequency.max(1);
+        state.base = timestamp.to_ticks(state.frequency).wrapping_sub(counter);
+        self.update_state(state);
+
+        frequency
+
+    }
+
+    /// Set the alarm to the given time elapsed since the UNIX epoch, replacing any
+    /// previous alarm. An alarm in the past triggers immediately.
+    ///
+    /// The alarm triggers once, setting the triggered status, or calling the
+    /// callback if set with [`set_callback`](Self::set_callback).
+    ///
+    /// The alarm cannot be set further than half the counter period in the future,
+    /// about 194 days at 32768 Hz, an error is returned in this case and the previous
+    /// alarm is kept.
+    pub fn set_alarm(&mut self, since_epoch: Duration) -> Result<(), AlarmTooFar> {
+
+        // The time is read first so that a wrap of the counter is accounted in the base.
+        let counter = self.counter();
+        self.timestamp_at(counter);
+        let state = self.state.get();
+        let now = state.base.wrapping_add(counter);
+        let ticks = since_epoch.to_ticks(state.frequency);
+
+        // The comparison is an equality, so an alarm in the past is set just after
+        // the current counter value.
+        let delta = ticks.saturating_sub(now).max(2);
+        if delta > COUNTER_MASK / 2 {
+            return Err(AlarmTooFar);
+        }
+        let target = (counter + delta) & COUNTER_MASK;
+
+        HBN.ctl().modify(|reg| {
+            let ctl = reg.rtc_ctl().get();
+            reg.rtc_ctl().set(ctl & !RTC_CTL_COMP);
+        });
+        clear_status();
+
+        HBN.time_l().set_with(|reg| reg.time_l().set(target as u32));
+        HBN.time_h().set_with(|reg| reg.time_h().set((target >> 32) as u32));
+
+        HBN.ctl().modify(|reg| {
+            reg.rtc_dly_option().fill();
+            let ctl = reg.rtc_ctl().get();
+            reg.rtc_ctl().set(ctl | RTC_CTL_COMP);
+        });
+
+        PDS.int().modify(|reg| {
+            let src = reg.cr_pds_wakeup_src_en().get();
+            reg.cr_pds_wakeup_src_en().set(src | PDS_WAKEUP_HBN_OUT0);
+        });
+
+        Ok(())
+
+    }
+
+    /// Set the alarm to the given date and time, in UTC, see [`set_alarm`].
+    ///
+    /// This function panics if the date and time is not valid, see
+    /// [`DateTime::is_valid`].
+    ///
+    /// [`set_alarm`]: Self::set_alarm
+    pub fn set_alarm_datetime(&mut self, datetime: &DateTime) -> Result<(), AlarmTooFar> {
+        self.set_alarm(Duration::from_secs(datetime.timestamp()))
+    }
+
+    /// Set the alarm to trigger after the given duration, see [`set_alarm`].
+    ///
+    /// [`set_alarm`]: Self::set_alarm
+    pub fn set_alarm_after(&mut self, duration: Duration) -> Result<(), AlarmTooFar> {
+        self.set_alarm(self.timestamp().saturating_add(duration))
+    }
+
+    /// Disable the alarm, the triggered status is cleared.
+    pub fn clear_alarm(&mut self) {
+        HBN.ctl().modify(|reg| {
+            let ctl = reg.rtc_ctl().get();
+            reg.rtc_ctl().set(ctl & !RTC_CTL_COMP);
+        });
+        clear_status();
+        PDS.int().modify(|reg| {
+            let src = reg.cr_pds_wakeup_src_en().get();
+            reg.cr_pds_wakeup_src_en().set(src & !PDS_WAKEUP_HBN_OUT0);
+        });
+    }
+
+    /// Return true if the alarm triggered since the last time it was cleared.
+    #[inline]
+    pub fn alarm_triggered(&self) -> bool {
+        HBN.irq_stat().get().irq_stat().get() & STATUS_RTC != 0
+    }
+
+    /// Set a callback to be called when the alarm triggers.
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        critical_section::with(|cs| {
+            CALLBACK.borrow_ref_mut(cs).replace(Box::new(callback));
+        });
+        unsafe { interrupt::HBN_OUT0.set_enabled(true) }
+    }
+
+    /// Remove the callback, if any.
+    pub fn clear_callback(&mut self) {
+        unsafe { interrupt::HBN_OUT0.set_enabled(false) }
+        critical_section::with(|cs| {
+            CALLBACK.borrow_ref_mut(cs).take();
+        });
+    }
+
+    /// Internal function to convert a counter value to a time since the epoch, the
+    /// counter must not be older than the last one seen. If it's lower than the last
+    /// one, the counter wrapped and the base is advanced by the counter period.
+    fn timestamp_at(&self, counter: u64) -> Duration {
+        let mut state = self.state.get();
+        if counter < state.last {
+            state.base = state.base.wrapping_add(COUNTER_MASK + 1);
+        }
+        if counter != state.last {
+            state.last = counter;
+            self.update_state(state);
+        }
+        Duration::from_ticks(state.base.wrapping_add(counter), state.frequency)
+    }
+
+    /// Internal function to replace the retained state and store it.
+    fn update_state(&self, state: RetainedState) {
+        state.store();
+        self.state.set(state);
+    }
+
+}
+
+impl Drop for Rtc {
+    fn drop(&mut self) {
+        self.clear_callback();
+        self.clear_alarm();
+    }
+}
+
+/// Error returned when setting an alarm further than half the counter period in the
+/// future, see [`Rtc::set_alarm`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmTooFar;
+
+
+/// A date and time in UTC, in the proleptic Gregorian calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    /// The year, from 1970.
+    pub year: u16,
+    /// The month, from 1 to 12.
+    pub month: u8,
+    /// The day of the month, from 1 to 31.
+    pub day: u8,
+    /// The hour, from 0 to 23.
+    pub hour: u8,
+    /// The minute, from 0 to 59.
+    pub minute: u8,
+    /// The second, from 0 to 59.
+    pub second: u8,
+}
+
+impl DateTime {
+
+    /// Create a new date and time, returning `None` if it's not valid.
+    pub const fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        let datetime = Self { year, month, day, hour, minute, second };
+        if datetime.is_valid() { Some(datetime) } else { None }
+    }
+
+    /// Get the date and time of the given number of seconds since the UNIX epoch.
+    ///
+    /// This function panics if the year overflows.
+    pub const fn from_timestamp(secs: u64) -> Self {
+
+        let days = secs / 86_400;
+        let secs = secs % 86_400;
+
+        // Civil from days, shifting the epoch to 0000-03-01.
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let doe = days % 146_097;
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as u64;
+
+        assert!(year <= u16::MAX as u64, "year overflow");
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3_600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+
+    }
+
+    /// Get the number of seconds since the UNIX epoch.
+    ///
+    /// This function panics if the date and time is not valid.
+    pub const fn timestamp(&self) -> u64 {
+        assert!(self.is_valid(), "invalid date and time");
+        self.days() * 86_400
+            + self.hour as u64 * 3_600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// Get the day of the week.
+    ///
+    /// This function panics if the date and time is not valid.
+    pub const fn weekday(&self) -> Weekday {
+        assert!(self.is_valid(), "invalid date and time");
+        // The UNIX epoch is a Thursday.
+        match (self.days() + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    /// Return true if this date and time is valid and not before the UNIX epoch.
+    pub const fn is_valid(&self) -> bool {
+        self.year >= 1970
+            && self.month >= 1 && self.month <= 12
+            && self.day >= 1 && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+
+    /// Internal function to get the number of days since the UNIX epoch.
+    const fn days(&self) -> u64 {
+        // Days from civil, shifting the epoch to 0000-03-01.
+        let year = self.year as u64 - (self.month <= 2) as u64;
+        let era = year / 400;
+        let yoe = year % 400;
+        let month = self.month as u64;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+}
+
+/// Day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Return the number of days in the given month of the given year.
+const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+
+/// State of the RTC retained in the HBN RAM.
+#[derive(Debug, Clone, Copy)]
+struct RetainedState {
+    /// True if the wall-clock time has been set.
+    set: bool,
+    /// Frequency of the counter.
+    frequency: u32,
+    /// Wall-clock time, in counter ticks, when the counter was last zero (wrapping).
+    base: u64,
+    /// Last counter value seen, used to detect when the counter wraps.
+    last: u64,
+}
+
+impl RetainedState {
+
+    /// Load the state from the HBN RAM, or the default state if the magic is wrong
+    /// because the chip has been powered off.
+    fn load() -> Self {
+        // SAFETY: The end of the HBN RAM is reserved for the RTC.
+        let raw = unsafe { RETAINED_STATE.read_volatile() };
+        if raw[0] == RETAINED_MAGIC && raw[1] != 0 {
+            Self {
+                set: raw[1] & RETAINED_SET != 0,
+                frequency: raw[1] & !RETAINED_SET,
+                base: (raw[3] as u64) << 32 | raw[2] as u64,
+                last: (raw[5] as u64) << 32 | raw[4] as u64,
+            }
+        } else {
+            Self {
+                set: false,
+                frequency: clock::get_f32k_freq(),
+                base: 0,
+                last: 0,
+            }
+        }
+    }
+
+    /// Store the state into the HBN RAM.
+    fn store(&self) {
+        let raw = [
+            RETAINED_MAGIC,
+            self.frequency | if self.set { RETAINED_SET } else { 0 },
+            self.base as u32,
+            (self.base >> 32) as u32,
+            self.last as u32,
+            (self.last >> 32) as u32,
+        ];
+        // SAFETY: The end of the HBN RAM is reserved for the RTC.
+        unsafe { RETAINED_STATE.write_volatile(raw) }
+    }
+
+}
+
+
+/// Bit of `rtc_ctl` that enables the counter, clearing it resets the counter.
+const RTC_CTL_ENABLE: u32 = 1 << 0;
+/// Bit of `rtc_ctl` that enables the comparison of the 40 bits of the counter.
+const RTC_CTL_COMP: u32 = 1 << 1;
+/// Mask of the 40 bits of the counter.
+const COUNTER_MASK: u64 = (1 << 40) - 1;
+/// Bit of the RTC in HBN interrupt status and clear registers.
+const STATUS_RTC: u32 = 1 << 16;
+/// Bit of `hbn_irq_out[0]` in the PDS wake-up sources.
+const PDS_WAKEUP_HBN_OUT0: u32 = 1 << 1;
+
+/// Location of the retained state, in the last 24 bytes of the 4 KB HBN RAM.
+const RETAINED_STATE: *mut [u32; 6] = (addr::HBN_RAM_BASE + 0x1000 - 24) as _;
+/// Magic word of the retained state ("RTC!").
+const RETAINED_MAGIC: u32 = 0x52544321;
+/// Flag in the frequency word of the retained state, set when the time is set.
+const RETAINED_SET: u32 = 1 << 31;
+
+
+/// Callback called when the alarm triggers.
+static CALLBACK: Mutex<RefCell<Option<RtcCallback>>> = Mutex::new(RefCell::new(None));
+
+/// Type alias for a boxed closure used as an RTC callback.
+type RtcCallback = Box<dyn FnMut() + Send>;
+
+/// Interrupt handler of the alarm, on `HBN_OUT0`.
+pub(crate) fn rtc_handler(_code: usize, cs: CriticalSection) {
+    if HBN.irq_stat().get().irq_stat().get() & STATUS_RTC != 0 {
+        // The alarm triggers once, disable the comparison so that the status
+        // can be cleared.
+        HBN.ctl().modify(|reg| {
+            let ctl = reg.rtc_ctl().get();
+            reg.rtc_ctl().set(ctl & !RTC_CTL_COMP);
+        });
+        clear_status();
+        if let Some(callback) = CALLBACK.borrow_ref_mut(cs).as_mut() {
+            callback();
+        }
+    }
+}
+
+/// Internal function to start the counter if not already running.
+pub(crate) fn enable_counter() {
+    if HBN.ctl().get().rtc_ctl().get() & RTC_CTL_ENABLE == 0 {
+        HBN.ctl().modify(|reg| {
+            let ctl = reg.rtc_ctl().get();
+            reg.rtc_ctl().set(ctl | RTC_CTL_ENABLE);
+        });
+    }
+}
+
+/// Internal function to read the current value of the 40-bit counter.
+pub(crate) fn read_counter() -> u64 {
+    HBN.rtc_time_h().modify(|reg| reg.rtc_time_latch().fill());
+    HBN.rtc_time_h().modify(|reg| reg.rtc_time_latch().clear());
+    let low = HBN.rtc_time_l().get().rtc_time_latch_l().get() as u64;
+    let high = HBN.rtc_time_h().get().rtc_time_latch_h().get() as u64;
+    (high << 32) | low
+}
+
+/// Internal function to get the frequency of the counter, calibrated if possible.
+pub(crate) fn counter_frequency() -> u32 {
+    RetainedState::load().frequency
+}
+
+/// Internal function to get the number of counter ticks elapsed between two values.
+#[inline]
+pub(crate) fn counter_elapsed(start: u64, end: u64) -> u64 {
+    end.wrapping_sub(start) & COUNTER_MASK
+}
+
+/// Internal function to clear the interrupt status of the RTC.
+fn clear_status() {
+    HBN.irq_clr().modify(|reg| reg.irq_clr().set(STATUS_RTC));
+    HBN.irq_clr().modify(|reg| reg.irq_clr().set(0));
+}
@@ -7,3 +7,7 @@ pub trait Sealed { }
 
 impl Sealed for () {}
 impl<const NUM: u8> Sealed for crate::gpio::Pin<NUM, crate::gpio::Alternate> {}
+impl<const NUM: u8> Sealed for crate::gpio::Pin<NUM, crate::gpio::Output> {}
+impl Sealed for u8 {}
+impl Sealed for u16 {}
+impl Sealed for u32 {}
@@ -0,0 +1,142 @@
+//! Low power sleep of the current core until a deadline.
+//!
+//! The core can simply wait for an interrupt, or enter the PDS (power down sleep)
+//! mode where its clocks are gated and optionally the crystal and PLLs are powered
+//! down. In PDS mode, the PDS timer wakes the core a bit before the deadline, then
+//! the core timer is resynchronized from the HBN RTC counter, which keeps counting
+//! during the sleep, and the core waits for the timer interrupt as usual.
+//!
+//! In PDS mode, only the PDS wake-up sources can wake the core before the deadline:
+//! the RTC alarm and comparators (see [`rtc`](crate::rtc) and [`acomp`](crate::acomp))
+//! and GPIO. Other interrupts, such as DMA or UART, are only handled after waking
+//! up, so the PDS modes should only be used when such interrupts are not expected.
+
+use crate::arch::bl808::PDS;
+use crate::time::{self, Duration, Instant};
+use crate::interrupt;
+use crate::rtc;
+
+
+/// Sleep mode of the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum SleepMode {
+    /// Simply wait for any interrupt, clocks are kept running.
+    Wait = 0,
+    /// Enter PDS mode with the clocks of the core gated.
+    ClockGated = 1,
+    /// Enter PDS mode with the clocks of the core gated and the crystal and PLLs
+    /// powered down, they are powered up again before the core is resumed. This
+    /// affects all cores, so other cores should be idle as well.
+    PowerDown = 2,
+}
+
+impl SleepMode {
+
+    /// Get the minimum duration of a sleep for this mode to be worth entering,
+    /// shorter sleeps fall back to [`SleepMode::Wait`].
+    pub const fn min_duration(self) -> Duration {
+        match self {
+            SleepMode::Wait => Duration::ZERO,
+            SleepMode::ClockGated => Duration::from_millis(1),
+            SleepMode::PowerDown => Duration::from_millis(5),
+        }
+    }
+
+    /// Internal function to get the duration before the deadline when the PDS
+    /// timer should wake the core, to account for the wake-up latency.
+    const fn wake_margin(self) -> Duration {
+        match self {
+            SleepMode::Wait => Duration::ZERO,
+            SleepMode::ClockGated => Duration::from_micros(500),
+            SleepMode::PowerDown => Duration::from_millis(3),
+        }
+    }
+
+}
+
+
+/// Sleep in the given mode until an interrupt is pending or the given deadline is
+/// reached, the deadline is typically the next timer callback, see
+/// [`time::next_deadline`]. Without deadline, the core sleeps until an interrupt
+/// or PDS wake-up source.
+///
+/// This function returns when an interrupt is pending, it should be called in a
+/// critical section so that the interrupt is handled after this function returns
+/// and the deadline cannot be changed by an interrupt before sleeping.
+pub fn sleep_until(mode: SleepMode, deadline: Option<Instant>) {
+
+    let remaining = deadline.map(|deadline| deadline.duration_since(Instant::now()));
+
+    if mode == SleepMode::Wait || remaining.is_some_and(|remaining| remaining < mode.min_duration()) {
+        crate::hart::wait_for_interrupt();
+        return;
+    }
+
+    rtc::enable_counter();
+    let frequency = rtc::counter_frequency();
+    let start_counter = rtc::read_counter();
+    let start = Instant::now();
+
+    let duration = remaining.map(|remaining| {
+        let ticks = remaining.saturating_sub(mode.wake_margin()).to_ticks(frequency);
+        ticks.clamp(1, u32::MAX as u64) as u32
+    });
+
+    enter_pds(mode, duration);
+
+    // Resynchronize the core timer that may have been stopped during the sleep.
+    let slept = Duration::from_ticks(rtc::counter_elapsed(start_counter, rtc::read_counter()), frequency);
+    let expected = start.saturating_add(slept);
+    if Instant::now() < expected {
+        // SAFETY: The time is only moved forward, timer callbacks that reached their
+        // deadline are called on the next interrupt.
+        unsafe { time::set_time(expected.as_micros()) }
+    }
+
+}
+
+
+/// Internal function to enter PDS mode for the given number of F32k ticks, or until
+/// a wake-up source if no duration.
+fn enter_pds(mode: SleepMode, duration: Option<u32>) {
+
+    let power_down = mode == SleepMode::PowerDown;
+
+    PDS.int().modify(|reg| {
+        reg.cr_pds_int_clr().fill();
+        reg.cr_pds_wake_int_mask().clear();
+    });
+    PDS.int().modify(|reg| reg.cr_pds_int_clr().clear());
+
+    // The wake-up interrupt is only enabled to leave the WFI, it's never handled.
+    let wake_enabled = interrupt::PDS_WAKE_UP.enabled();
+    unsafe { interrupt::PDS_WAKE_UP.set_enabled(true) }
+
+    PDS.time1().set_with(|reg| reg.cr_sleep_duration().set(duration.unwrap_or(0)));
+
+    PDS.ctl().modify(|reg| {
+        reg.cr_sleep_forever().set(duration.is_none() as _);
+        reg.cr_pds_gate_clk().fill();
+        reg.cr_pds_pd_xtal().set(power_down as _);
+        reg.cr_pds_wait_xtal_rdy().set(power_down as _);
+        reg.cr_pds_ctrl_cpupll_pd().set(power_down as _);
+        reg.cr_pds_ctrl_wifipll_pd().set(power_down as _);
+        reg.cr_pds_ctrl_aupll_pd().set(power_down as _);
+        reg.cr_pds_ctrl_usbpll_pd().set(power_down as _);
+        reg.start_ps().fill();
+    });
+
+    // The PDS state machine waits for the core to be in WFI before gating it.
+    crate::hart::wait_for_interrupt();
+
+    PDS.ctl().modify(|reg| reg.start_ps().clear());
+    PDS.int().modify(|reg| {
+        reg.cr_pds_int_clr().fill();
+        reg.cr_pds_wake_int_mask().fill();
+    });
+    PDS.int().modify(|reg| reg.cr_pds_int_clr().clear());
+
+    unsafe { interrupt::PDS_WAKE_UP.set_enabled(wake_enabled) }
+
+}
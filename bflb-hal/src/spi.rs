@@ -0,0 +1,703 @@
+//! Serial Peripheral Interface (SPI) management on BL808.
+//!
+//! Two SPI controllers are available, SPI0 on the MCU subsystem and SPI1 on the MM
+//! subsystem. Each controller can be attached to any pin, but the signal is defined
+//! by the pin number: pin `4n` is CS, `4n+1` is SCK, `4n+2` is MISO and `4n+3` is
+//! MOSI.
+//!
+//! Ports can be configured as master with [`SpiAccess::init_master`], or as slave with
+//! [`SpiAccess::init_slave`], see the [`slave`] module.
+
+use core::ptr::addr_of;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use crate::arch::bl808::{Spi as SpiRegs, GLB, SPI0, SPI1};
+
+use crate::gpio::{Pin, PinPull, PinDrive, PinFunction, Alternate, Output};
+use crate::dma::{DmaSrcEndpoint, DmaDstEndpoint, DmaEndpointConfig,
+    DmaPeripheral, DmaDataWidth, DmaBurstSize, DmaIncrement};
+use crate::sealed::Sealed;
+use crate::clock;
+
+pub mod slave;
+
+
+/// Maximum number of frames pushed in the TX FIFO before reading the RX FIFO, this is
+/// used to avoid overflowing the RX FIFO when polling.
+const FIFO_IN_FLIGHT: usize = 8;
+
+
+/// Definition of an exclusive access to a SPI port. This port need to be configured
+/// in order to obtain a [`Spi`] structure that is actually usable for transfers.
+///
+/// Available ports: 0, 1.
+pub struct SpiAccess<const PORT: u8>(pub(crate) ());
+
+impl<const PORT: u8> SpiAccess<PORT> {
+
+    /// Configure this SPI port as a master. The MOSI and MISO pins can be `()` if the
+    /// port is used only for transmitting or receiving. The chip select can be an
+    /// alternate pin for hardware chip select, an output pin for software chip select
+    /// or `()` if the chip select is externally managed.
+    pub fn init_master<const SCK_PIN: u8, Mosi, Miso, Cs>(self,
+        sck: impl Into<Pin<SCK_PIN, Alternate>>,
+        mut mosi: Mosi,
+        mut miso: Miso,
+        mut cs: Cs,
+        config: &SpiConfig,
+    ) -> Spi<PORT, Pin<SCK_PIN, Alternate>, Mosi, Miso, Cs>
+    where
+        Mosi: SpiPin,
+        Miso: SpiPin,
+        Cs: SpiCs,
+    {
+
+        let func = match PORT {
+            0 => PinFunction::Spi0,
+            1 => PinFunction::Spi1,
+            _ => panic!("invalid spi port {PORT}")
+        };
+
+        let mut sck = sck.into();
+        sck.attach(SpiSignal::Sck, func);
+        mosi.attach(SpiSignal::Mosi, func);
+        miso.attach(SpiSignal::Miso, func);
+        cs.attach_cs(func);
+
+        setup_clock::<PORT>();
+
+        // Select master mode for the port.
+        GLB.parm_cfg0().modify(|reg| match PORT {
+            0 => reg.spi_0_master_mode().fill(),
+            1 => reg.mm_spi_master_mode().fill(),
+            _ => unreachable!()
+        });
+
+        let regs = get_registers::<PORT>();
+
+        regs.config().modify(|reg| {
+            reg.m_en().clear();
+            reg.s_en().clear();
+            reg.frame_size().set(config.frame_size as _);
+            reg.bit_inv().set((config.bit_order == SpiBitOrder::LsbFirst) as _);
+            reg.byte_inv().clear();
+            reg.rxd_ignr_en().clear();
+            reg.m_cont_en().fill();
+            reg.deg_en().clear();
+        });
+
+        set_mode::<PORT>(config.mode);
+        set_frequency::<PORT>(config.frequency);
+
+        regs.int_sts().modify(|reg| {
+            reg.end_mask().fill();
+            reg.txf_mask().fill();
+            reg.rxf_mask().fill();
+            reg.sto_mask().fill();
+            reg.txu_mask().fill();
+            reg.fer_mask().fill();
+        });
+
+        regs.fifo_config_0().modify(|reg| {
+            reg.tx_fifo_clr().fill();
+            reg.rx_fifo_clr().fill();
+            reg.dma_tx_en().clear();
+            reg.dma_rx_en().clear();
+        });
+
+        regs.fifo_config_1().modify(|reg| {
+            reg.tx_fifo_th().set(config.tx_fifo_threshold as _);
+            reg.rx_fifo_th().set(config.rx_fifo_threshold as _);
+        });
+
+        regs.config().modify(|reg| reg.m_en().fill());
+
+        Spi {
+            sck,
+            mosi,
+            miso,
+            cs,
+            frame_size: config.frame_size,
+        }
+
+    }
+
+}
+
+
+/// SPI signals, the value is the pin number modulo 4 that can carry the signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiSignal {
+    Cs = 0,
+    Sck = 1,
+    Miso = 2,
+    Mosi = 3,
+}
+
+/// Internal trait used to attach an optional pin to a SPI signal.
+pub trait SpiPin: Sealed {
+    /// Attach this pin to the given signal, panicking if the pin cannot carry it.
+    fn attach(&mut self, signal: SpiSignal, func: PinFunction);
+}
+
+impl SpiPin for () {
+    #[inline]
+    fn attach(&mut self, _signal: SpiSignal, _func: PinFunction) {}
+}
+
+impl<const NUM: u8> SpiPin for Pin<NUM, Alternate> {
+    fn attach(&mut self, signal: SpiSignal, func: PinFunction) {
+        if NUM % 4 != signal as u8 {
+            panic!("invalid spi {signal:?} pin {NUM}");
+        }
+        self.modify_config(|cfg| {
+            cfg.set_function(func);
+            cfg.set_input_enable(true);
+            cfg.set_smt(true);
+            cfg.set_pull(PinPull::Up);
+            cfg.set_drive(PinDrive::Drive1);
+        });
+    }
+}
+
+/// Internal trait used to abstract the chip select kind:
+/// - `()` when not managed by the driver;
+/// - `Pin<_, Alternate>` for hardware chip select, asserted by the controller while
+///   frames are transferred;
+/// - `Pin<_, Output>` for software chip select, asserted by [`Spi::select`].
+pub trait SpiCs: Sealed {
+    /// Attach the chip select pin to the port.
+    fn attach_cs(&mut self, func: PinFunction);
+    /// Assert the chip select (software only).
+    fn select(&mut self);
+    /// De-assert the chip select (software only).
+    fn deselect(&mut self);
+}
+
+impl SpiCs for () {
+    fn attach_cs(&mut self, _func: PinFunction) {}
+    fn select(&mut self) {}
+    fn deselect(&mut self) {}
+}
+
+impl<const NUM: u8> SpiCs for Pin<NUM, Alternate> {
+    fn attach_cs(&mut self, func: PinFunction) {
+        self.attach(SpiSignal::Cs, func);
+    }
+    fn select(&mut self) {}
+    fn deselect(&mut self) {}
+}
+
+impl<const NUM: u8> SpiCs for Pin<NUM, Output> {
+    fn attach_cs(&mut self, _func: PinFunction) {
+        self.set_high();
+    }
+    fn select(&mut self) {
+        self.set_low();
+    }
+    fn deselect(&mut self) {
+        self.set_high();
+    }
+}
+
+
+/// A word that can be transferred in a SPI frame:
+/// - `u8` for 8 bits frames;
+/// - `u16` for 16 bits frames;
+/// - `u32` for 24 and 32 bits frames.
+pub trait SpiWord: Copy + Default + Sealed {
+
+    /// Return true if this word type can be used with the given frame size.
+    fn accept(frame_size: SpiFrameSize) -> bool;
+
+    /// Convert this word to a FIFO entry.
+    fn to_entry(self) -> u32;
+
+    /// Convert a FIFO entry to this word.
+    fn from_entry(entry: u32) -> Self;
+
+}
+
+impl SpiWord for u8 {
+    #[inline]
+    fn accept(frame_size: SpiFrameSize) -> bool { frame_size == SpiFrameSize::Bits8 }
+    #[inline]
+    fn to_entry(self) -> u32 { self as u32 }
+    #[inline]
+    fn from_entry(entry: u32) -> Self { entry as u8 }
+}
+
+impl SpiWord for u16 {
+    #[inline]
+    fn accept(frame_size: SpiFrameSize) -> bool { frame_size == SpiFrameSize::Bits16 }
+    #[inline]
+    fn to_entry(self) -> u32 { self as u32 }
+    #[inline]
+    fn from_entry(entry: u32) -> Self { entry as u16 }
+}
+
+impl SpiWord for u32 {
+    #[inline]
+    fn accept(frame_size: SpiFrameSize) -> bool {
+        matches!(frame_size, SpiFrameSize::Bits24 | SpiFrameSize::Bits32)
+    }
+    #[inline]
+    fn to_entry(self) -> u32 { self }
+    #[inline]
+    fn from_entry(entry: u32) -> Self { entry }
+}
+
+
+/// An initialized SPI port in master mode.
+pub struct Spi<const PORT: u8, Sck, Mosi, Miso, Cs: SpiCs> {
+    sck: Sck,
+    mosi: Mosi,
+    miso: Miso,
+    cs: Cs,
+    /// Current frame size, cached to check word types of transfers.
+    frame_size: SpiFrameSize,
+}
+
+impl<const PORT: u8, Sck, Mosi, Miso, Cs: SpiCs> Spi<PORT, Sck, Mosi, Miso, Cs> {
+
+    /// Downgrade this SPI port and disable it.
+    pub fn downgrade(self) -> (SpiAccess<PORT>, Sck, Mosi, Miso, Cs) {
+        // Drop will be called at the end, effectively closing the port.
+        unsafe { (
+            SpiAccess(()),
+            addr_of!(self.sck).read(),
+            addr_of!(self.mosi).read(),
+            addr_of!(self.miso).read(),
+            addr_of!(self.cs).read(),
+        ) }
+    }
+
+    /// Change the frequency of the SPI clock, see [`SpiConfig::frequency`].
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.flush();
+        set_frequency::<PORT>(frequency);
+    }
+
+    /// Change the SPI mode (clock polarity and phase).
+    pub fn set_mode(&mut self, mode: SpiMode) {
+        self.flush();
+        set_mode::<PORT>(mode);
+    }
+
+    /// Get the current frame size.
+    #[inline]
+    pub fn frame_size(&self) -> SpiFrameSize {
+        self.frame_size
+    }
+
+    /// Change the frame size, this also defines which word type can be used for
+    /// transfers, see [`SpiWord`].
+    pub fn set_frame_size(&mut self, frame_size: SpiFrameSize) {
+        self.flush();
+        get_registers::<PORT>().config().modify(|reg| reg.frame_size().set(frame_size as _));
+        self.frame_size = frame_size;
+    }
+
+    /// Assert the chip select, this only has effect for software chip select.
+    #[inline]
+    pub fn select(&mut self) {
+        self.cs.select();
+    }
+
+    /// De-assert the chip select, this only has effect for software chip select.
+    #[inline]
+    pub fn deselect(&mut self) {
+        self.cs.deselect();
+    }
+
+    /// Run the given function while the chip select is asserted, the bus is flushed
+    /// before de-asserting the chip select.
+    pub fn transaction<F, R>(&mut self, func: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        self.select();
+        let ret = func(self);
+        self.flush();
+        self.deselect();
+        ret
+    }
+
+    /// Wait for all frames to be transferred on the bus.
+    pub fn flush(&mut self) {
+        while get_registers::<PORT>().bus_busy().get().bus_busy().get() != 0 {}
+    }
+
+    /// Transfer frames, the number of frames transferred is the maximum length of both
+    /// buffers, zeros are written after the end of the write buffer and frames read
+    /// after the end of the read buffer are discarded.
+    pub fn transfer_frames<W: SpiWord>(&mut self, read: &mut [W], write: &[W]) {
+
+        assert!(W::accept(self.frame_size), "invalid word type for spi frame size {:?}", self.frame_size);
+
+        let regs = get_registers::<PORT>();
+        let len = read.len().max(write.len());
+        let mut tx_idx = 0;
+        let mut rx_idx = 0;
+
+        while rx_idx < len {
+            if tx_idx < len && tx_idx - rx_idx < FIFO_IN_FLIGHT && regs.fifo_config_1().get().tx_fifo_cnt().get() != 0 {
+                let word = write.get(tx_idx).copied().unwrap_or_default();
+                regs.fifo_wdata().set(word.to_entry());
+                tx_idx += 1;
+            }
+            if regs.fifo_config_1().get().rx_fifo_cnt().get() != 0 {
+                let word = W::from_entry(regs.fifo_rdata().get());
+                if let Some(dst) = read.get_mut(rx_idx) {
+                    *dst = word;
+                }
+                rx_idx += 1;
+            }
+        }
+
+    }
+
+    /// Transfer frames from the given buffer, replacing each written frame with the
+    /// read one.
+    pub fn transfer_frames_in_place<W: SpiWord>(&mut self, data: &mut [W]) {
+
+        assert!(W::accept(self.frame_size), "invalid word type for spi frame size {:?}", self.frame_size);
+
+        let regs = get_registers::<PORT>();
+        let mut tx_idx = 0;
+        let mut rx_idx = 0;
+
+        while rx_idx < data.len() {
+            if tx_idx < data.len() && tx_idx - rx_idx < FIFO_IN_FLIGHT && regs.fifo_config_1().get().tx_fifo_cnt().get() != 0 {
+                regs.fifo_wdata().set(data[tx_idx].to_entry());
+                tx_idx += 1;
+            }
+            if regs.fifo_config_1().get().rx_fifo_cnt().get() != 0 {
+                data[rx_idx] = W::from_entry(regs.fifo_rdata().get());
+                rx_idx += 1;
+            }
+        }
+
+    }
+
+    /// Write frames, discarding read frames.
+    #[inline]
+    pub fn write_frames<W: SpiWord>(&mut self, write: &[W]) {
+        self.transfer_frames(&mut [], write)
+    }
+
+    /// Read frames, writing zeros.
+    #[inline]
+    pub fn read_frames<W: SpiWord>(&mut self, read: &mut [W]) {
+        self.transfer_frames(read, &[])
+    }
+
+    /// Split this port into DMA endpoints for both directions, this can be used to run
+    /// full-duplex DMA transfers on two channels.
+    pub fn split_dma(&mut self) -> (SpiDmaTx<'_, PORT>, SpiDmaRx<'_, PORT>) {
+        let frame_size = self.frame_size;
+        (
+            SpiDmaTx { frame_size, _spi: PhantomData },
+            SpiDmaRx { frame_size, _spi: PhantomData },
+        )
+    }
+
+}
+
+impl<const PORT: u8, Sck, Mosi, Miso, Cs: SpiCs> Drop for Spi<PORT, Sck, Mosi, Miso, Cs> {
+    fn drop(&mut self) {
+        let regs = get_registers::<PORT>();
+        regs.config().modify(|reg| reg.m_en().clear());
+        regs.fifo_config_0().modify(|reg| {
+            reg.tx_fifo_clr().fill();
+            reg.rx_fifo_clr().fill();
+            reg.dma_tx_en().clear();
+            reg.dma_rx_en().clear();
+        });
+    }
+}
+
+impl<const PORT: u8, Sck, Mosi, Miso, Cs: SpiCs> embedded_hal::spi::ErrorType for Spi<PORT, Sck, Mosi, Miso, Cs> {
+    type Error = Infallible;
+}
+
+impl<const PORT: u8, Sck, Mosi, Miso, Cs: SpiCs, W: SpiWord + 'static> embedded_hal::spi::SpiBus<W> for Spi<PORT, Sck, Mosi, Miso, Cs> {
+
+    fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.read_frames(words);
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.write_frames(words);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.transfer_frames(read, write);
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.transfer_frames_in_place(words);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Spi::flush(self);
+        Ok(())
+    }
+
+}
+
+/// The port can be used as a DMA source, reading frames from the RX FIFO.
+impl<const PORT: u8, Sck, Mosi, Miso: SpiPin, Cs: SpiCs> DmaSrcEndpoint for Spi<PORT, Sck, Mosi, Miso, Cs> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        configure_dma_rx::<PORT>(self.frame_size)
+    }
+
+    fn close(&mut self) {
+        close_dma_rx::<PORT>();
+    }
+
+}
+
+/// The port can be used as a DMA destination, writing frames to the TX FIFO.
+impl<const PORT: u8, Sck, Mosi: SpiPin, Miso, Cs: SpiCs> DmaDstEndpoint for Spi<PORT, Sck, Mosi, Miso, Cs> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        configure_dma_tx::<PORT>(self.frame_size)
+    }
+
+    fn close(&mut self) {
+        close_dma_tx::<PORT>();
+    }
+
+}
+
+
+/// DMA destination endpoint for the TX FIFO of a SPI port, see [`Spi::split_dma`].
+pub struct SpiDmaTx<'a, const PORT: u8> {
+    frame_size: SpiFrameSize,
+    _spi: PhantomData<&'a mut ()>,
+}
+
+impl<'a, const PORT: u8> DmaDstEndpoint for SpiDmaTx<'a, PORT> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        configure_dma_tx::<PORT>(self.frame_size)
+    }
+
+    fn close(&mut self) {
+        close_dma_tx::<PORT>();
+    }
+
+}
+
+/// DMA source endpoint for the RX FIFO of a SPI port, see [`Spi::split_dma`].
+pub struct SpiDmaRx<'a, const PORT: u8> {
+    frame_size: SpiFrameSize,
+    _spi: PhantomData<&'a mut ()>,
+}
+
+impl<'a, const PORT: u8> DmaSrcEndpoint for SpiDmaRx<'a, PORT> {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        configure_dma_rx::<PORT>(self.frame_size)
+    }
+
+    fn close(&mut self) {
+        close_dma_rx::<PORT>();
+    }
+
+}
+
+
+/// Return the SPI registers for the given port.
+#[inline]
+fn get_registers<const PORT: u8>() -> SpiRegs {
+    match PORT {
+        0 => SPI0,
+        1 => SPI1,
+        _ => unreachable!()
+    }
+}
+
+/// Internal function to setup the clock of the given port. The MCU SPI clock is
+/// already configured on startup, but the MM SPI clock is only configured when used
+/// because the MM subsystem may not be clocked before. If already enabled, the MM SPI
+/// clock is kept as configured by the application.
+fn setup_clock<const PORT: u8>() {
+    if PORT == 1 && !clock::spi::get_mm_spi_enable() {
+        unsafe { clock::spi::setup_mm_spi(clock::spi::MmSpiSel::MmXclk, 1, true); }
+    }
+}
+
+/// Internal function to configure the clock phases of the given port in order to run
+/// at the given SCK frequency, a null frequency is handled as the slowest frequency.
+fn set_frequency<const PORT: u8>(frequency: u32) {
+
+    let hw_freq = match PORT {
+        0 => clock::spi::get_mcu_spi_freq(),
+        1 => clock::spi::get_mm_spi_freq(),
+        _ => unreachable!()
+    };
+
+    // Each phase of the clock is half a period, the divider is rounded up so that the
+    // frequency is never above the requested one.
+    let div = (hw_freq / 2).div_ceil(frequency.max(1)).clamp(1, 256) - 1;
+
+    let regs = get_registers::<PORT>();
+
+    regs.prd_0().set_with(|reg| {
+        reg.prd_s().set(div);
+        reg.prd_p().set(div);
+        reg.prd_d_ph_0().set(div);
+        reg.prd_d_ph_1().set(div);
+    });
+
+    regs.prd_1().modify(|reg| reg.prd_i().set(div));
+
+}
+
+/// Internal function to configure the clock polarity and phase of the given port.
+fn set_mode<const PORT: u8>(mode: SpiMode) {
+    // Note that the phase bit is inverted compared to the usual CPHA.
+    let (pol, ph) = match mode {
+        SpiMode::Mode0 => (0, 1),
+        SpiMode::Mode1 => (0, 0),
+        SpiMode::Mode2 => (1, 1),
+        SpiMode::Mode3 => (1, 0),
+    };
+    get_registers::<PORT>().config().modify(|reg| {
+        reg.sclk_pol().set(pol);
+        reg.sclk_ph().set(ph);
+    });
+}
+
+/// Internal function to get the DMA data width for a frame size.
+fn get_dma_data_width(frame_size: SpiFrameSize) -> DmaDataWidth {
+    match frame_size {
+        SpiFrameSize::Bits8 => DmaDataWidth::Byte,
+        SpiFrameSize::Bits16 => DmaDataWidth::Hword,
+        SpiFrameSize::Bits24 | SpiFrameSize::Bits32 => DmaDataWidth::Word,
+    }
+}
+
+/// Internal function to enable DMA for the TX FIFO and return the endpoint config.
+fn configure_dma_tx<const PORT: u8>(frame_size: SpiFrameSize) -> DmaEndpointConfig {
+
+    let regs = get_registers::<PORT>();
+    regs.fifo_config_0().modify(|reg| reg.dma_tx_en().fill());
+
+    DmaEndpointConfig {
+        peripheral: Some(match PORT {
+            0 => DmaPeripheral::Spi0Tx,
+            1 => DmaPeripheral::Spi1Tx,
+            _ => unreachable!()
+        }),
+        data_width: get_dma_data_width(frame_size),
+        burst_size: DmaBurstSize::Incr1,
+        increment: DmaIncrement::Const,
+        addr: regs.fifo_wdata().0 as _
+    }
+
+}
+
+/// Internal function to disable DMA for the TX FIFO.
+fn close_dma_tx<const PORT: u8>() {
+    get_registers::<PORT>().fifo_config_0().modify(|reg| reg.dma_tx_en().clear());
+}
+
+/// Internal function to enable DMA for the RX FIFO and return the endpoint config.
+fn configure_dma_rx<const PORT: u8>(frame_size: SpiFrameSize) -> DmaEndpointConfig {
+
+    let regs = get_registers::<PORT>();
+    regs.fifo_config_0().modify(|reg| reg.dma_rx_en().fill());
+
+    DmaEndpointConfig {
+        peripheral: Some(match PORT {
+            0 => DmaPeripheral::Spi0Rx,
+            1 => DmaPeripheral::Spi1Rx,
+            _ => unreachable!()
+        }),
+        data_width: get_dma_data_width(frame_size),
+        burst_size: DmaBurstSize::Incr1,
+        increment: DmaIncrement::Const,
+        addr: regs.fifo_rdata().0 as _
+    }
+
+}
+
+/// Internal function to disable DMA for the RX FIFO.
+fn close_dma_rx<const PORT: u8>() {
+    get_registers::<PORT>().fifo_config_0().modify(|reg| reg.dma_rx_en().clear());
+}
+
+
+/// Configuration structure for SPI initialization.
+#[derive(Debug, Clone)]
+pub struct SpiConfig {
+    /// Frequency of the SPI clock, the highest reachable frequency not above this one
+    /// is used, or the lowest reachable frequency if this one is too low.
+    pub frequency: u32,
+    /// Clock polarity and phase.
+    pub mode: SpiMode,
+    /// Size of each frame.
+    pub frame_size: SpiFrameSize,
+    /// Order of bits in each byte.
+    pub bit_order: SpiBitOrder,
+    /// TX FIFO threshold, used for DMA requests.
+    pub tx_fifo_threshold: u8,
+    /// RX FIFO threshold, used for DMA requests.
+    pub rx_fifo_threshold: u8,
+}
+
+impl SpiConfig {
+
+    /// Create a new basic config for the given frequency, in mode 0 with 8 bits frames
+    /// sent MSB-first.
+    pub const fn new(frequency: u32) -> Self {
+        Self {
+            frequency,
+            mode: SpiMode::Mode0,
+            frame_size: SpiFrameSize::Bits8,
+            bit_order: SpiBitOrder::MsbFirst,
+            tx_fifo_threshold: 0,
+            rx_fifo_threshold: 0,
+        }
+    }
+
+}
+
+/// SPI clock polarity (CPOL) and phase (CPHA).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiMode {
+    /// CPOL = 0, CPHA = 0.
+    Mode0,
+    /// CPOL = 0, CPHA = 1.
+    Mode1,
+    /// CPOL = 1, CPHA = 0.
+    Mode2,
+    /// CPOL = 1, CPHA = 1.
+    Mode3,
+}
+
+/// SPI frame size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SpiFrameSize {
+    Bits8 = 0,
+    Bits16 = 1,
+    Bits24 = 2,
+    Bits32 = 3,
+}
+
+/// SPI bit order for each byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiBitOrder {
+    MsbFirst,
+    LsbFirst,
+}
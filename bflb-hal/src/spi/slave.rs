@@ -0,0 +1,664 @@
+//! SPI slave mode with DMA-fed ring buffers.
+//!
+//! In slave mode, the master may clock frames at any time, so the CPU cannot be
+//! expected to feed the FIFOs. This module keeps one DMA transfer armed in each
+//! direction between two ring buffers and the FIFOs of the port:
+//! - frames received are appended to the RX ring, to be read with [`SpiSlave::read`];
+//! - frames queued with [`SpiSlave::write`] to the TX ring are sent on the next frames
+//!   clocked by the master.
+//!
+//! Transaction boundaries are reported when the master de-asserts the chip select,
+//! see [`SpiSlave::poll`], which must be called regularly to re-arm transfers. Only
+//! 8 bits frames are supported in this mode.
+
+use core::ptr::addr_of;
+use core::marker::PhantomData;
+
+use alloc::boxed::Box;
+
+use crate::gpio::{Pin, Alternate, PinFunction};
+use crate::dma::{DmaAccess, DmaTransfer, DmaDstEndpoint, DmaSrcEndpoint,
+    DmaEndpointConfig, DmaDataWidth, DmaBurstSize, DmaIncrement};
+use crate::cache::{CacheAligned, clean_data_range, clean_invalidate_data_range,
+    invalidate_data_range};
+use crate::arch::bl808::GLB;
+
+use super::{SpiAccess, SpiPin, SpiSignal, SpiMode, SpiBitOrder, SpiFrameSize,
+    SpiDmaRx, SpiDmaTx, get_registers, set_mode, setup_clock};
+
+
+/// Maximum length of a single DMA transfer.
+const MAX_SEGMENT_LEN: usize = 4064;
+
+/// Depth of the FIFOs, in frames.
+const FIFO_DEPTH: usize = 32;
+
+
+impl<const PORT: u8> SpiAccess<PORT> {
+
+    /// Configure this SPI port as a slave, using the given DMA channels to feed the
+    /// given RX and TX ring buffers. The MOSI and MISO pins can be `()` if the port is
+    /// used only for receiving or transmitting, the chip select is mandatory because
+    /// it delimits transactions.
+    ///
+    /// The DMA channels must be on a DMA port that can reach this SPI port, DMA 0 or 1
+    /// for SPI0 and DMA 2 for SPI1.
+    ///
+    /// Both ring buffers must not be empty, even if one direction is not used, because
+    /// frames are always shifted in both directions. This function panics otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub fn init_slave<const SCK_PIN: u8, const CS_PIN: u8, Mosi, Miso, const DMA_PORT: u8, const RX_CHANNEL: u8, const TX_CHANNEL: u8>(self,
+        sck: impl Into<Pin<SCK_PIN, Alternate>>,
+        mut mosi: Mosi,
+        mut miso: Miso,
+        cs: impl Into<Pin<CS_PIN, Alternate>>,
+        config: &SpiSlaveConfig,
+        rx_dma: DmaAccess<DMA_PORT, RX_CHANNEL>,
+        tx_dma: DmaAccess<DMA_PORT, TX_CHANNEL>,
+        rx_ring: Box<CacheAligned<[u8]>>,
+        tx_ring: Box<CacheAligned<[u8]>>,
+    ) -> SpiSlave<PORT, DMA_PORT, RX_CHANNEL, TX_CHANNEL, Pin<SCK_PIN, Alternate>, Mosi, Miso, Pin<CS_PIN, Alternate>>
+    where
+        Mosi: SpiPin,
+        Miso: SpiPin,
+    {
+
+        assert!(!rx_ring.is_empty() && !tx_ring.is_empty(), "slave ring buffers must not be empty");
+
+        let func = match PORT {
+            0 => PinFunction::Spi0,
+            1 => PinFunction::Spi1,
+            _ => panic!("invalid spi port {PORT}")
+        };
+
+        let mut sck = sck.into();
+        let mut cs = cs.into();
+        sck.attach(SpiSignal::Sck, func);
+        mosi.attach(SpiSignal::Mosi, func);
+        miso.attach(SpiSignal::Miso, func);
+        cs.attach(SpiSignal::Cs, func);
+
+        setup_clock::<PORT>();
+
+        // Select slave mode for the port.
+        GLB.parm_cfg0().modify(|reg| match PORT {
+            0 => reg.spi_0_master_mode().clear(),
+            1 => reg.mm_spi_master_mode().clear(),
+            _ => unreachable!()
+        });
+
+        let regs = get_registers::<PORT>();
+
+        regs.config().modify(|reg| {
+            reg.m_en().clear();
+            reg.s_en().clear();
+            reg.frame_size().set(SpiFrameSize::Bits8 as _);
+            reg.bit_inv().set((config.bit_order == SpiBitOrder::LsbFirst) as _);
+            reg.byte_inv().clear();
+            reg.rxd_ignr_en().clear();
+            reg.s_3pin_mode().clear();
+            reg.deg_en().clear();
+        });
+
+        set_mode::<PORT>(config.mode);
+
+        regs.int_sts().modify(|reg| {
+            reg.end_mask().fill();
+            reg.txf_mask().fill();
+            reg.rxf_mask().fill();
+            reg.sto_mask().fill();
+            reg.txu_mask().fill();
+            reg.fer_mask().fill();
+            reg.end_clr().fill();
+            reg.sto_clr().fill();
+            reg.txu_clr().fill();
+        });
+
+        regs.fifo_config_0().modify(|reg| {
+            reg.tx_fifo_clr().fill();
+            reg.rx_fifo_clr().fill();
+        });
+
+        regs.fifo_config_1().modify(|reg| {
+            reg.tx_fifo_th().clear();
+            reg.rx_fifo_th().clear();
+        });
+
+        let mut slave = SpiSlave {
+            sck,
+            mosi,
+            miso,
+            cs,
+            rx: SpiSlaveRx {
+                ring: rx_ring,
+                head: 0,
+                len: 0,
+                channel: SpiSlaveChannel::Idle(rx_dma),
+            },
+            tx: SpiSlaveTx {
+                ring: tx_ring,
+                tail: 0,
+                len: 0,
+                in_fifo: 0,
+                channel: SpiSlaveChannel::Idle(tx_dma),
+            },
+            transaction: SpiSlaveTransaction::default(),
+            status: SpiSlaveStatus::default(),
+        };
+
+        slave.rx.arm();
+        slave.tx.arm();
+
+        regs.config().modify(|reg| reg.s_en().fill());
+
+        slave
+
+    }
+
+}
+
+
+/// An initialized SPI port in slave mode, see the [module documentation](self).
+pub struct SpiSlave<const PORT: u8, const DMA_PORT: u8, const RX_CHANNEL: u8, const TX_CHANNEL: u8, Sck, Mosi, Miso, Cs> {
+    sck: Sck,
+    mosi: Mosi,
+    miso: Miso,
+    cs: Cs,
+    rx: SpiSlaveRx<PORT, DMA_PORT, RX_CHANNEL>,
+    tx: SpiSlaveTx<PORT, DMA_PORT, TX_CHANNEL>,
+    /// Frames transferred since the start of the current transaction.
+    transaction: SpiSlaveTransaction,
+    /// Accumulated error status.
+    status: SpiSlaveStatus,
+}
+
+impl<const PORT: u8, const DMA_PORT: u8, const RX_CHANNEL: u8, const TX_CHANNEL: u8, Sck, Mosi, Miso, Cs> SpiSlave<PORT, DMA_PORT, RX_CHANNEL, TX_CHANNEL, Sck, Mosi, Miso, Cs> {
+
+    /// Downgrade this SPI port, stop DMA transfers and disable it. The DMA channels
+    /// and the ring buffers are returned.
+    #[allow(clippy::type_complexity)]
+    pub fn downgrade(mut self) -> (SpiAccess<PORT>, Sck, Mosi, Miso, Cs,
+        DmaAccess<DMA_PORT, RX_CHANNEL>, DmaAccess<DMA_PORT, TX_CHANNEL>,
+        Box<CacheAligned<[u8]>>, Box<CacheAligned<[u8]>>)
+    {
+
+        self.disable();
+
+        // SAFETY: Fields are all read once and self is forgotten, disabling has been
+        // done above instead of drop.
+        unsafe {
+            let rx = addr_of!(self.rx).read();
+            let tx = addr_of!(self.tx).read();
+            let ret = (
+                SpiAccess(()),
+                addr_of!(self.sck).read(),
+                addr_of!(self.mosi).read(),
+                addr_of!(self.miso).read(),
+                addr_of!(self.cs).read(),
+                rx.channel.into_idle(),
+                tx.channel.into_idle(),
+                rx.ring,
+                tx.ring,
+            );
+            core::mem::forget(self);
+            ret
+        }
+
+    }
+
+    /// Return true if a transaction is currently running, the chip select being
+    /// asserted by the master.
+    #[inline]
+    pub fn busy(&self) -> bool {
+        get_registers::<PORT>().bus_busy().get().bus_busy().get() != 0
+    }
+
+    /// Poll the state of the port, this must be called regularly in order to re-arm
+    /// DMA transfers once they reach the end of ring buffers. When the master ends a
+    /// transaction by de-asserting the chip select, the number of frames transferred
+    /// in this transaction is returned.
+    pub fn poll(&mut self) -> Option<SpiSlaveTransaction> {
+
+        let regs = get_registers::<PORT>();
+
+        let mut fifo_config = regs.fifo_config_0().get();
+        if fifo_config.rx_fifo_overflow().get() != 0 {
+            self.status.rx_overrun = true;
+            regs.fifo_config_0().modify(|reg| reg.rx_fifo_clr().fill());
+        }
+
+        let mut int_sts = regs.int_sts().get();
+        if int_sts.txu_int().get() != 0 {
+            self.status.tx_underrun = true;
+            regs.int_sts().modify(|reg| reg.txu_clr().fill());
+        }
+
+        if int_sts.end_int().get() != 0 {
+
+            regs.int_sts().modify(|reg| reg.end_clr().fill());
+
+            self.drain_rx();
+
+            // Frames that were pushed into the TX FIFO but not sent are restored into
+            // the ring when the FIFO is cleared.
+            self.tx.stop();
+            self.transaction.sent += self.tx.commit(tx_fifo_len::<PORT>());
+            regs.fifo_config_0().modify(|reg| reg.tx_fifo_clr().fill());
+            self.tx.in_fifo = 0;
+
+            self.rx.arm();
+            self.tx.arm();
+
+            return Some(core::mem::take(&mut self.transaction));
+
+        }
+
+        if self.rx.channel.is_completed() {
+            self.transaction.received += self.rx.stop();
+            self.rx.arm();
+        }
+
+        if self.tx.channel.is_completed() {
+            self.tx.stop();
+            self.transaction.sent += self.tx.commit(tx_fifo_len::<PORT>());
+            self.tx.arm();
+        }
+
+        None
+
+    }
+
+    /// Return the number of received bytes available for reading.
+    #[inline]
+    pub fn rx_available(&self) -> usize {
+        self.rx.len
+    }
+
+    /// Read received bytes, returning the number of bytes read.
+    pub fn read(&mut self, data: &mut [u8]) -> usize {
+        let read = self.rx.read(data);
+        // Space has been freed, the transfer can be armed if it was stopped.
+        if !self.rx.channel.is_running() {
+            self.rx.arm();
+        }
+        read
+    }
+
+    /// Return the number of bytes that can be queued for writing.
+    #[inline]
+    pub fn tx_free(&self) -> usize {
+        self.tx.ring.len() - self.tx.len
+    }
+
+    /// Queue bytes to be sent on next frames clocked by the master, returning the
+    /// number of bytes queued.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let written = self.tx.write(data);
+        if !self.tx.channel.is_running() {
+            self.tx.arm();
+        }
+        written
+    }
+
+    /// Return the accumulated error status since the last call to this function and
+    /// clear it. The status is updated by [`poll`](Self::poll).
+    pub fn take_status(&mut self) -> SpiSlaveStatus {
+        core::mem::take(&mut self.status)
+    }
+
+    /// Internal function to move all frames of the RX FIFO to the ring at the end of
+    /// a transaction, the RX transfer is stopped when returning. If the ring is full,
+    /// the remaining frames are dropped.
+    fn drain_rx(&mut self) {
+
+        let regs = get_registers::<PORT>();
+
+        loop {
+
+            // Wait for the DMA to drain the FIFO, unless the current segment completes
+            // before, in which case the next segment is armed to continue.
+            while regs.fifo_config_1().get().rx_fifo_cnt().get() != 0 && self.rx.channel.is_active() {}
+            self.transaction.received += self.rx.stop();
+
+            if regs.fifo_config_1().get().rx_fifo_cnt().get() == 0 {
+                break;
+            }
+
+            self.rx.arm();
+            if !self.rx.channel.is_running() {
+                self.status.rx_overrun = true;
+                regs.fifo_config_0().modify(|reg| reg.rx_fifo_clr().fill());
+                break;
+            }
+
+        }
+
+    }
+
+    /// Internal function to disable the port and stop DMA transfers.
+    fn disable(&mut self) {
+        let regs = get_registers::<PORT>();
+        regs.config().modify(|reg| reg.s_en().clear());
+        self.rx.stop();
+        self.tx.stop();
+        self.tx.in_fifo = 0;
+        regs.fifo_config_0().modify(|reg| {
+            reg.tx_fifo_clr().fill();
+            reg.rx_fifo_clr().fill();
+        });
+    }
+
+}
+
+impl<const PORT: u8, const DMA_PORT: u8, const RX_CHANNEL: u8, const TX_CHANNEL: u8, Sck, Mosi, Miso, Cs> Drop for SpiSlave<PORT, DMA_PORT, RX_CHANNEL, TX_CHANNEL, Sck, Mosi, Miso, Cs> {
+    fn drop(&mut self) {
+        self.disable();
+    }
+}
+
+
+/// Frames transferred in a transaction, returned by [`SpiSlave::poll`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpiSlaveTransaction {
+    /// Number of bytes received and appended to the RX ring.
+    pub received: usize,
+    /// Number of bytes sent from the TX ring.
+    pub sent: usize,
+}
+
+/// Error status of a SPI slave port.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpiSlaveStatus {
+    /// The RX FIFO overflowed, some received frames have been lost. This happens when
+    /// the RX ring is full.
+    pub rx_overrun: bool,
+    /// The master clocked frames while the TX FIFO was empty. This happens when the
+    /// TX ring is empty.
+    pub tx_underrun: bool,
+}
+
+/// Configuration structure for SPI slave initialization.
+#[derive(Debug, Clone)]
+pub struct SpiSlaveConfig {
+    /// Clock polarity and phase.
+    pub mode: SpiMode,
+    /// Order of bits in each byte.
+    pub bit_order: SpiBitOrder,
+}
+
+impl SpiSlaveConfig {
+
+    /// Create a new basic slave config in mode 0 with bytes sent MSB-first.
+    pub const fn new() -> Self {
+        Self {
+            mode: SpiMode::Mode0,
+            bit_order: SpiBitOrder::MsbFirst,
+        }
+    }
+
+}
+
+impl Default for SpiSlaveConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Internal state of a DMA channel used by the slave.
+enum SpiSlaveChannel<const DMA_PORT: u8, const CHANNEL: u8, Src, Dst>
+where
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+    /// The channel is not running.
+    Idle(DmaAccess<DMA_PORT, CHANNEL>),
+    /// The channel is running a transfer of the given length.
+    Running(DmaTransfer<DMA_PORT, CHANNEL, Src, Dst>, usize),
+    /// Temporary state while the channel is being moved.
+    Empty,
+}
+
+impl<const DMA_PORT: u8, const CHANNEL: u8, Src, Dst> SpiSlaveChannel<DMA_PORT, CHANNEL, Src, Dst>
+where
+    Src: DmaSrcEndpoint,
+    Dst: DmaDstEndpoint,
+{
+
+    #[inline]
+    fn is_running(&self) -> bool {
+        matches!(self, Self::Running(..))
+    }
+
+    /// Return true if the channel is running a transfer that is not completed.
+    #[inline]
+    fn is_active(&self) -> bool {
+        matches!(self, Self::Running(transfer, _) if !transfer.completed())
+    }
+
+    #[inline]
+    fn is_completed(&self) -> bool {
+        matches!(self, Self::Running(transfer, _) if transfer.completed())
+    }
+
+    /// Start a new transfer of the given length if the channel is idle.
+    fn start(&mut self, src: Src, dst: Dst, len: usize) {
+        if let Self::Idle(access) = core::mem::replace(self, Self::Empty) {
+            *self = Self::Running(access.into_transfer(src, dst), len);
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Stop the channel if running, returning the number of transfers done.
+    fn stop(&mut self) -> usize {
+        match core::mem::replace(self, Self::Empty) {
+            Self::Running(transfer, len) => {
+                let done = len - transfer.remaining();
+                let (_, _, access) = transfer.abort();
+                *self = Self::Idle(access);
+                done
+            }
+            other => {
+                *self = other;
+                0
+            }
+        }
+    }
+
+    /// Get back the channel, that must be idle.
+    fn into_idle(self) -> DmaAccess<DMA_PORT, CHANNEL> {
+        match self {
+            Self::Idle(access) => access,
+            _ => unreachable!()
+        }
+    }
+
+}
+
+
+/// Internal receive side of the slave, the DMA writes at `head`.
+struct SpiSlaveRx<const PORT: u8, const DMA_PORT: u8, const CHANNEL: u8> {
+    ring: Box<CacheAligned<[u8]>>,
+    /// Index where the DMA writes.
+    head: usize,
+    /// Number of bytes available for reading, before head.
+    len: usize,
+    channel: SpiSlaveChannel<DMA_PORT, CHANNEL, SpiDmaRx<'static, PORT>, RingSegment>,
+}
+
+impl<const PORT: u8, const DMA_PORT: u8, const CHANNEL: u8> SpiSlaveRx<PORT, DMA_PORT, CHANNEL> {
+
+    /// Arm a transfer to the largest contiguous free space after head, if any.
+    fn arm(&mut self) {
+
+        let cap = self.ring.len();
+        let seg_len = (cap - self.len).min(cap - self.head).min(MAX_SEGMENT_LEN);
+        if seg_len == 0 {
+            return;
+        }
+
+        let segment = RingSegment {
+            addr: self.ring.as_ptr() as usize + self.head,
+            len: seg_len,
+        };
+
+        let src = SpiDmaRx { frame_size: SpiFrameSize::Bits8, _spi: PhantomData };
+        self.channel.start(src, segment, seg_len);
+
+    }
+
+    /// Stop the transfer and account received bytes.
+    fn stop(&mut self) -> usize {
+        let received = self.channel.stop();
+        self.head = (self.head + received) % self.ring.len();
+        self.len += received;
+        received
+    }
+
+    /// Read available bytes into the given buffer.
+    fn read(&mut self, data: &mut [u8]) -> usize {
+
+        let cap = self.ring.len();
+        let mut tail = (self.head + cap - self.len) % cap;
+        let count = data.len().min(self.len);
+
+        for dst in &mut data[..count] {
+            *dst = self.ring[tail];
+            tail = (tail + 1) % cap;
+        }
+
+        self.len -= count;
+        count
+
+    }
+
+}
+
+
+/// Internal transmit side of the slave, the DMA reads after the bytes already pushed
+/// to the FIFO, that are kept in the ring until known to be sent.
+struct SpiSlaveTx<const PORT: u8, const DMA_PORT: u8, const CHANNEL: u8> {
+    ring: Box<CacheAligned<[u8]>>,
+    /// Index of the first byte not known to be sent.
+    tail: usize,
+    /// Number of bytes queued for sending, after tail.
+    len: usize,
+    /// Number of bytes after tail pushed to the FIFO by stopped transfers, some of
+    /// them may have been sent since.
+    in_fifo: usize,
+    channel: SpiSlaveChannel<DMA_PORT, CHANNEL, RingSegment, SpiDmaTx<'static, PORT>>,
+}
+
+impl<const PORT: u8, const DMA_PORT: u8, const CHANNEL: u8> SpiSlaveTx<PORT, DMA_PORT, CHANNEL> {
+
+    /// Arm a transfer from the largest contiguous queued data not yet pushed to the
+    /// FIFO, if any.
+    fn arm(&mut self) {
+
+        let cap = self.ring.len();
+        let start = (self.tail + self.in_fifo) % cap;
+        let seg_len = (self.len - self.in_fifo).min(cap - start).min(MAX_SEGMENT_LEN);
+        if seg_len == 0 {
+            return;
+        }
+
+        let segment = RingSegment {
+            addr: self.ring.as_ptr() as usize + start,
+            len: seg_len,
+        };
+
+        let dst = SpiDmaTx { frame_size: SpiFrameSize::Bits8, _spi: PhantomData };
+        self.channel.start(segment, dst, seg_len);
+
+    }
+
+    /// Stop the transfer, bytes pushed to the FIFO are accounted with
+    /// [`commit`](Self::commit).
+    fn stop(&mut self) {
+        self.in_fifo += self.channel.stop();
+    }
+
+    /// Account the bytes that left the FIFO given the number of bytes still in the
+    /// FIFO, the transfer must be stopped. Return the number of bytes sent.
+    fn commit(&mut self, fifo_len: usize) -> usize {
+        let sent = self.in_fifo.saturating_sub(fifo_len);
+        self.tail = (self.tail + sent) % self.ring.len();
+        self.len -= sent;
+        self.in_fifo -= sent;
+        sent
+    }
+
+    /// Queue bytes from the given buffer.
+    fn write(&mut self, data: &[u8]) -> usize {
+
+        let cap = self.ring.len();
+        let mut head = (self.tail + self.len) % cap;
+        let count = data.len().min(cap - self.len);
+
+        for &src in &data[..count] {
+            self.ring[head] = src;
+            head = (head + 1) % cap;
+        }
+
+        self.len += count;
+        count
+
+    }
+
+}
+
+
+/// Internal function to get the number of frames in the TX FIFO of the given port.
+#[inline]
+fn tx_fifo_len<const PORT: u8>() -> usize {
+    let free = get_registers::<PORT>().fifo_config_1().get().tx_fifo_cnt().get() as usize;
+    FIFO_DEPTH.saturating_sub(free)
+}
+
+
+/// Internal DMA endpoint for a contiguous segment of a ring buffer. The ring buffer
+/// is cache aligned and owned by the slave while the transfer is running, the CPU
+/// doesn't access the segment until the transfer is stopped.
+struct RingSegment {
+    addr: usize,
+    len: usize,
+}
+
+impl RingSegment {
+    fn config(&self) -> DmaEndpointConfig {
+        DmaEndpointConfig {
+            peripheral: None,
+            data_width: DmaDataWidth::Byte,
+            burst_size: DmaBurstSize::Incr1,
+            increment: DmaIncrement::Incr(self.len),
+            addr: self.addr,
+        }
+    }
+}
+
+impl DmaSrcEndpoint for RingSegment {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        // Data has been written by the CPU and must be visible to the DMA.
+        unsafe { clean_data_range(self.addr, self.len) }
+        self.config()
+    }
+
+}
+
+impl DmaDstEndpoint for RingSegment {
+
+    unsafe fn configure(&mut self) -> DmaEndpointConfig {
+        // The RX ring is never written by the CPU, so lines shared with bytes not yet
+        // read are clean and can be safely invalidated.
+        unsafe { clean_invalidate_data_range(self.addr, self.len) }
+        self.config()
+    }
+
+    fn close(&mut self) {
+        // Drop lines that could have been speculatively loaded during the transfer.
+        unsafe { invalidate_data_range(self.addr, self.len) }
+    }
+
+}
@@ -1,18 +1,24 @@
 //! Main real time clock management, can be used for both synchronized and callback-based
-//! waiting. This module is 
+//! waiting. Times are expressed with [`Instant`] and [`Duration`], both counting
+//! microseconds of the core timer.
 
-use alloc::collections::VecDeque;
-use alloc::boxed::Box;
+use core::ops::{Add, AddAssign, Sub, SubAssign, Mul, Div};
 
 use critical_section::CriticalSection;
-use smallvec::SmallVec;
 
 use crate::interrupt::MACHINE_TIMER;
-use crate::hart::HartLocalCell;
 use crate::arch::bl808::addr;
 use crate::clock;
 
 
+pub mod sleep;
+pub mod queue;
+
+use queue::{CALLBACK_QUEUE, TimerCallback};
+
+pub use sleep::{sleep, sleep_until, with_timeout, interval};
+
+
 /// The tick frequency of the core timer.
 const FREQ: u32 = 1_000_000;
 
@@ -47,7 +53,7 @@ pub(crate) fn init() {
 }
 
 
-/// Get the current time in microseconds.
+/// Get the current time in microseconds, see [`Instant::now`].
 #[inline]
 pub fn get_time() -> u64 {
     #[cfg(feature = "bl808-d0")]
@@ -98,152 +104,467 @@ fn set_time_cmp(cmp: u64) {
 }
 
 
-/// State of the callback after being called or not.
-#[derive(Debug, Clone, Copy)]
-enum TimerCallbackState {
-    /// The callback has been consumed and should be removed from the queue.
-    Consumed,
-    /// The callback has been updated so it should be reordered in the queue.
-    Updated,
-    /// The callback is still pending for target time.
-    Waiting,
+/// Internal function to add a new callback to the queue of the current hart.
+fn schedule<F: TimerCallback>(target_time: u64, callback: F) -> Result<TimerHandle, TimerQueueFull> {
+    critical_section::with(|cs| {
+        CALLBACK_QUEUE.borrow_ref_mut(cs)
+            .insert(target_time, callback)
+            .map(|id| TimerHandle { id })
+            .ok_or(TimerQueueFull)
+    })
 }
 
-/// Timer callback abstraction.
-trait TimerCallback: Send {
-    
-    /// Return the target time.
-    fn target_time(&self) -> u64;
 
-    /// Try to call it, returning its (new) state.
-    fn call(&mut self, time: u64) -> TimerCallbackState;
+/// Synchronized wait, this function will block the current thread until the given 
+/// duration has been waited. Prefer [`wait_callback`] to use an interrupt-driven
+/// callback.
+#[inline]
+pub fn wait(duration: Duration) {
+    let start = get_time();
+    while get_time() - start < duration.0 { }
+}
 
+/// Wait for the given duration and then call the given callback. Note that it will be
+/// called in an interrupt-free context. The callback can return `None` to just be 
+/// consumed and removed from the queue, but it can also return `Some` new duration to
+/// be called again in the future (returning zero means that it will be called on next
+/// interrupt).
+/// 
+/// The returned handle can be used to cancel or reschedule the callback.
+/// 
+/// This function panics if the timer queue is full, this can only happen with the
+/// `static-timer-queue` feature, see [`try_wait_callback`].
+pub fn wait_callback<F>(duration: Duration, callback: F) -> TimerHandle
+where
+    F: FnMut() -> Option<Duration>,
+    F: Send + 'static
+{
+    try_wait_callback(duration, callback).expect("timer queue is full")
 }
 
-/// Descriptor for a callback and when to call it.
-struct TimerCallbackImpl<F> {
-    /// The target time for calling this callback.
-    target_time: u64,
-    /// The actual callback to call.
-    callback: F,
+/// Call the given callback when the given deadline is reached, the callback is called
+/// on next interrupt if the deadline is already passed. See [`wait_callback`] for the
+/// callback behavior.
+/// 
+/// This function panics if the timer queue is full, this can only happen with the
+/// `static-timer-queue` feature, see [`try_wait_callback_at`].
+pub fn wait_callback_at<F>(deadline: Instant, callback: F) -> TimerHandle
+where
+    F: FnMut() -> Option<Duration>,
+    F: Send + 'static
+{
+    try_wait_callback_at(deadline, callback).expect("timer queue is full")
 }
 
-impl<F> TimerCallback for TimerCallbackImpl<F> 
+/// Same as [`wait_callback`] but return an error if the timer queue is full.
+pub fn try_wait_callback<F>(duration: Duration, callback: F) -> Result<TimerHandle, TimerQueueFull>
 where
-    F: FnMut() -> Option<u64>,
+    F: FnMut() -> Option<Duration>,
     F: Send + 'static
 {
-    
+    schedule(get_time().saturating_add(duration.0), callback)
+}
+
+/// Same as [`wait_callback_at`] but return an error if the timer queue is full.
+pub fn try_wait_callback_at<F>(deadline: Instant, callback: F) -> Result<TimerHandle, TimerQueueFull>
+where
+    F: FnMut() -> Option<Duration>,
+    F: Send + 'static
+{
+    schedule(deadline.0, callback)
+}
+
+/// Get the deadline of the next callback registered on the current hart, this is
+/// typically used to know how long the hart can sleep while idle.
+pub fn next_deadline() -> Option<Instant> {
+    critical_section::with(|cs| {
+        CALLBACK_QUEUE.borrow_ref(cs).next_time().map(Instant)
+    })
+}
+
+/// Error returned when registering a callback while the statically allocated timer
+/// queue is full, see the [`queue`] module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerQueueFull;
+
+
+/// Handle to a callback registered with [`wait_callback`] or [`wait_callback_at`],
+/// it can be freely copied and dropped, this has no effect on the callback.
+/// 
+/// Callbacks are local to the hart they've been registered from, so the handle has
+/// no effect on other harts. While the callback is being called it's not pending, so
+/// it cannot be cancelled or rescheduled from itself, it should return `None` or its
+/// next duration instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle {
+    id: u64,
+}
+
+impl TimerHandle {
+
+    /// Cancel the callback so that it will never be called again, returning true if it
+    /// was pending.
+    pub fn cancel(&self) -> bool {
+        let callback = critical_section::with(|cs| {
+            CALLBACK_QUEUE.borrow_ref_mut(cs).remove(self.id)
+        });
+        // The callback is dropped while the queue is not borrowed, so that it can
+        // use the timer queue from its drop.
+        let pending = callback.is_some();
+        drop(callback);
+        pending
+    }
+
+    /// Change the deadline of the callback, returning true if it was pending, false if
+    /// it has been consumed or cancelled, in which case it is not rescheduled.
+    pub fn reschedule(&self, deadline: Instant) -> bool {
+        critical_section::with(|cs| {
+            CALLBACK_QUEUE.borrow_ref_mut(cs).reschedule(self.id, deadline.0)
+        })
+    }
+
+    /// Return true if the callback is waiting for its deadline.
+    pub fn is_pending(&self) -> bool {
+        critical_section::with(|cs| {
+            CALLBACK_QUEUE.borrow_ref(cs).contains(self.id)
+        })
+    }
+
+}
+
+
+/// This handler is called when the core time reaches the time cmp register.
+pub(crate) fn mtimer_handler(_code: usize, cs: CriticalSection) {
+
+    let time = get_time();
+
+    // Call every callback that reached its target time, the queue is not borrowed
+    // while calling so that callbacks can register or cancel other callbacks. Updated
+    // callbacks are not called again in this round, even if their new target time is
+    // already reached.
+    loop {
+
+        let Some((id, mut callback)) = CALLBACK_QUEUE.borrow_ref_mut(cs).pop_due(time) else {
+            break
+        };
+
+        if let Some(target_time) = callback.call(time) {
+            CALLBACK_QUEUE.borrow_ref_mut(cs).reinsert(id, target_time, callback, time);
+        } else {
+            CALLBACK_QUEUE.borrow_ref_mut(cs).release(id);
+            // The callback is dropped while the queue is not borrowed.
+            drop(callback);
+        }
+
+    }
+
+    let mut queue = CALLBACK_QUEUE.borrow_ref_mut(cs);
+    queue.end_round(time);
+    queue.update_time_cmp();
+
+}
+
+
+/// A span of time with microsecond resolution, the resolution of the core timer.
+/// 
+/// Arithmetic operators panic on overflow, like primitive integers in debug builds,
+/// use the `checked_` and `saturating_` variants to handle overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration(u64);
+
+impl Duration {
+
+    /// A duration of zero.
+    pub const ZERO: Self = Self(0);
+    /// The maximum duration, more than 584'000 years.
+    pub const MAX: Self = Self(u64::MAX);
+
+    /// Create a duration from microseconds.
     #[inline]
-    fn target_time(&self) -> u64 {
-        self.target_time
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
     }
 
+    /// Create a duration from milliseconds, saturating to [`MAX`](Self::MAX).
     #[inline]
-    fn call(&mut self, time: u64) -> TimerCallbackState {
-        if self.target_time <= time {
-            if let Some(duration) = (self.callback)() {
-                self.target_time = time + duration;
-                TimerCallbackState::Updated
-            } else {
-                TimerCallbackState::Consumed
-            }
-        } else {
-            TimerCallbackState::Waiting
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis.saturating_mul(1_000))
+    }
+
+    /// Create a duration from seconds, saturating to [`MAX`](Self::MAX).
+    #[inline]
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs.saturating_mul(1_000_000))
+    }
+
+    /// Get the total number of whole microseconds.
+    #[inline]
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    /// Get the total number of whole milliseconds.
+    #[inline]
+    pub const fn as_millis(self) -> u64 {
+        self.0 / 1_000
+    }
+
+    /// Get the total number of whole seconds.
+    #[inline]
+    pub const fn as_secs(self) -> u64 {
+        self.0 / 1_000_000
+    }
+
+    /// Return true if this duration is zero.
+    #[inline]
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Add two durations, returning `None` on overflow.
+    #[inline]
+    pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_add(rhs.0) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
+        }
+    }
+
+    /// Subtract two durations, returning `None` if the result would be negative.
+    #[inline]
+    pub const fn checked_sub(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_sub(rhs.0) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
+        }
+    }
+
+    /// Multiply a duration, returning `None` on overflow.
+    #[inline]
+    pub const fn checked_mul(self, rhs: u32) -> Option<Self> {
+        match self.0.checked_mul(rhs as u64) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
+        }
+    }
+
+    /// Divide a duration, returning `None` if the divider is zero.
+    #[inline]
+    pub const fn checked_div(self, rhs: u32) -> Option<Self> {
+        match self.0.checked_div(rhs as u64) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
         }
     }
 
+    /// Add two durations, saturating to [`MAX`](Self::MAX).
+    #[inline]
+    pub const fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    /// Subtract two durations, saturating to [`ZERO`](Self::ZERO).
+    #[inline]
+    pub const fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Multiply a duration, saturating to [`MAX`](Self::MAX).
+    #[inline]
+    pub const fn saturating_mul(self, rhs: u32) -> Self {
+        Self(self.0.saturating_mul(rhs as u64))
+    }
+
+    /// Get the number of ticks of a clock at the given frequency during this duration,
+    /// rounded down and saturating to `u64::MAX`.
+    #[inline]
+    pub const fn to_ticks(self, frequency: u32) -> u64 {
+        let ticks = self.0 as u128 * frequency as u128 / 1_000_000;
+        if ticks > u64::MAX as u128 { u64::MAX } else { ticks as u64 }
+    }
+
+    /// Create a duration from a number of ticks of a clock at the given frequency,
+    /// rounded down and saturating to [`MAX`](Self::MAX).
+    /// 
+    /// This function panics if the frequency is zero.
+    #[inline]
+    pub const fn from_ticks(ticks: u64, frequency: u32) -> Self {
+        let micros = ticks as u128 * 1_000_000 / frequency as u128;
+        Self(if micros > u64::MAX as u128 { u64::MAX } else { micros as u64 })
+    }
+
 }
 
-/// The ordered queue of callbacks. It's hart local because timer interrupts are.
-static CALLBACK_QUEUE: HartLocalCell<VecDeque<Box<dyn TimerCallback>>> = HartLocalCell::new_cell(VecDeque::new());
+impl Add for Duration {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("overflow when adding durations")
+    }
+}
 
-/// Internal function to insert the given callback into the queue. **This function 
-/// requires to be executed in an interrupt-free context to avoid deadlocking.**
-fn insert_callback(queue: &mut VecDeque<Box<dyn TimerCallback>>, callback: Box<dyn TimerCallback>) {
-    
-    let target_time = callback.target_time();
-    
-    // Search for the index to insert at.
-    let insert_idx = match queue.binary_search_by(|desc| {
-        desc.target_time().cmp(&target_time)
-    }) {
-        Ok(idx) => idx,
-        Err(idx) => idx,
-    };
+impl AddAssign for Duration {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
 
-    // The first queue element is the next one to be called.
-    if insert_idx == 0 {
-        set_time_cmp(target_time);
+impl Sub for Duration {
+    type Output = Self;
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs).expect("overflow when subtracting durations")
     }
+}
 
-    queue.insert(insert_idx, callback);
+impl SubAssign for Duration {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
 
+impl Mul<u32> for Duration {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: u32) -> Self::Output {
+        self.checked_mul(rhs).expect("overflow when multiplying duration")
+    }
 }
 
-/// Synchronized wait, this function will block the current thread until the given 
-/// duration (micros) has been waited. Prefer [`wait_callback`] or [`repeat_callback`] 
-/// to use an interrupt-driven callback.
-#[inline]
-pub fn wait(duration: u64) {
-    let start = get_time();
-    while get_time() - start < duration { }
+impl Div<u32> for Duration {
+    type Output = Self;
+    #[inline]
+    fn div(self, rhs: u32) -> Self::Output {
+        self.checked_div(rhs).expect("divide by zero error when dividing duration")
+    }
 }
 
-/// Wait for the given duration and then call the given callback. Note that it will be
-/// called in an interrupt-free context. The callback can return `None` to just be 
-/// consumed and removed from the queue, but it can also return `Some` new duration to
-/// be called again in the future (returning 0 means that it will be called on next int).
-pub fn wait_callback<F>(duration: u64, callback: F)
-where
-    F: FnMut() -> Option<u64>,
-    F: Send + 'static
-{
-    critical_section::with(|cs| {
-        let mut queue = CALLBACK_QUEUE.borrow_ref_mut(cs);
-        insert_callback(&mut queue, Box::new(TimerCallbackImpl {
-            target_time: get_time() + duration,
-            callback,
-        }))
-    });
+/// Conversion from core durations, saturating and truncated to microseconds.
+impl From<core::time::Duration> for Duration {
+    fn from(value: core::time::Duration) -> Self {
+        Self(value.as_micros().min(u64::MAX as u128) as u64)
+    }
+}
+
+impl From<Duration> for core::time::Duration {
+    fn from(value: Duration) -> Self {
+        core::time::Duration::from_micros(value.0)
+    }
 }
 
 
-/// This handler is called when the core time reaches the time cmp register.
-pub(crate) fn mtimer_handler(_code: usize, cs: CriticalSection) {
+/// A point in time of the core timer, counted in microseconds since its start. The
+/// core timer never wraps in practice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
 
-    let mut queue = CALLBACK_QUEUE.borrow_ref_mut(cs);
-    let time = get_time();
+impl Instant {
+
+    /// Get the current instant.
+    #[inline]
+    pub fn now() -> Self {
+        Self(get_time())
+    }
+
+    /// Create an instant from its raw time in microseconds, see [`get_time`].
+    #[inline]
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
+
+    /// Get the raw time of this instant in microseconds.
+    #[inline]
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    /// Get the duration elapsed since this instant, zero if it is in the future.
+    #[inline]
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+
+    /// Get the duration from the earlier instant to this one, zero if the earlier one
+    /// is in fact later.
+    #[inline]
+    pub const fn duration_since(self, earlier: Self) -> Duration {
+        Duration(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Get the duration from the earlier instant to this one, returning `None` if the
+    /// earlier one is in fact later.
+    #[inline]
+    pub const fn checked_duration_since(self, earlier: Self) -> Option<Duration> {
+        match self.0.checked_sub(earlier.0) {
+            Some(micros) => Some(Duration(micros)),
+            None => None,
+        }
+    }
 
-    // Temporary array of updated callbacks.
-    let mut updated_callbacks = SmallVec::<[_; 4]>::new();
-
-    // Call every callback that reached their target time and take the index of 
-    // the last consumed one.
-    while let Some(callback) = queue.front_mut() {
-        match callback.call(time) {
-            TimerCallbackState::Consumed => { queue.pop_front(); }
-            TimerCallbackState::Updated => {
-                updated_callbacks.push(queue.pop_front().unwrap());
-            }
-            TimerCallbackState::Waiting => {
-                // Callbacks are ordered in the queue so we can just break here.
-                break
-            }
+    /// Add a duration to this instant, returning `None` on overflow.
+    #[inline]
+    pub const fn checked_add(self, duration: Duration) -> Option<Self> {
+        match self.0.checked_add(duration.0) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
+        }
+    }
+
+    /// Subtract a duration to this instant, returning `None` on overflow.
+    #[inline]
+    pub const fn checked_sub(self, duration: Duration) -> Option<Self> {
+        match self.0.checked_sub(duration.0) {
+            Some(micros) => Some(Self(micros)),
+            None => None,
         }
     }
 
-    // Consume the update callbacks vector and insert them again.
-    for update_callback in updated_callbacks {
-        insert_callback(&mut queue, update_callback);
+    /// Add a duration to this instant, saturating to the maximum instant. This is
+    /// typically used to compute deadlines from possibly infinite timeouts.
+    #[inline]
+    pub const fn saturating_add(self, duration: Duration) -> Self {
+        Self(self.0.saturating_add(duration.0))
+    }
+
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs).expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    #[inline]
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+    #[inline]
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs).expect("overflow when subtracting duration from instant")
     }
+}
 
-    // Then we get the front to update time compare register to the next callback,
-    // or just disable it by setting it to the maximum value.
-    if let Some(front) = queue.front() {
-        set_time_cmp(front.target_time());
-    } else {
-        set_time_cmp(DISABLED_TIME_CMP);
+impl SubAssign<Duration> for Instant {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
     }
+}
 
+/// Subtracting instants gives the duration between them, saturating to zero.
+impl Sub for Instant {
+    type Output = Duration;
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.duration_since(rhs)
+    }
 }
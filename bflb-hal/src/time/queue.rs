@@ -0,0 +1,49 @@
+//! Queues of timer callbacks, ordered by target time.
+//!
+//! By default the queue is a heap-allocated deque of boxed callbacks. With the
+//! `static-timer-queue` feature, a statically allocated queue with a fixed capacity
+//! is used instead, callbacks are stored inline so no heap is needed. Both queues have
+//! the same interface.
+
+use crate::hart::HartLocalCell;
+
+use super::Duration;
+
+
+#[cfg(not(feature = "static-timer-queue"))]
+mod heap;
+#[cfg(not(feature = "static-timer-queue"))]
+pub(super) use heap::CallbackQueue;
+
+#[cfg(feature = "static-timer-queue")]
+mod fixed;
+#[cfg(feature = "static-timer-queue")]
+pub(super) use fixed::CallbackQueue;
+#[cfg(feature = "static-timer-queue")]
+pub use fixed::{CAPACITY, INLINE_SIZE};
+
+
+/// The queue of callbacks. It's hart local because timer interrupts are.
+pub(super) static CALLBACK_QUEUE: HartLocalCell<CallbackQueue> = HartLocalCell::new_cell(CallbackQueue::new());
+
+
+/// Timer callback abstraction.
+pub(super) trait TimerCallback: Send + 'static {
+
+    /// Call it, returning the new target time if it should be called again.
+    fn call(&mut self, time: u64) -> Option<u64>;
+
+}
+
+impl<F> TimerCallback for F
+where
+    F: FnMut() -> Option<Duration>,
+    F: Send + 'static
+{
+
+    #[inline]
+    fn call(&mut self, time: u64) -> Option<u64> {
+        (self)().map(|duration| time.saturating_add(duration.as_micros()))
+    }
+
+}
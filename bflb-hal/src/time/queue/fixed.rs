@@ -0,0 +1,296 @@
+//! Statically allocated callback queue, with a fixed capacity and callbacks stored
+//! inline, for firmware with no heap.
+//!
+//! Slots are linked in a list ordered by target time using their indices, so that
+//! inserting a callback doesn't move the others.
+
+use core::mem::{MaybeUninit, size_of, align_of};
+
+use super::TimerCallback;
+use super::super::{set_time_cmp, DISABLED_TIME_CMP};
+
+
+/// Maximum number of callbacks registered at the same time on each hart.
+pub const CAPACITY: usize = 32;
+
+/// Maximum size of a callback in bytes, its alignment must not exceed the one of a
+/// pointer. This is enough to capture a few references or a task waker.
+pub const INLINE_SIZE: usize = INLINE_WORDS * size_of::<usize>();
+
+/// Number of words of the inline storage of callbacks.
+const INLINE_WORDS: usize = 4;
+
+/// Index used to terminate the list.
+const NIL: u8 = u8::MAX;
+
+
+/// A callback stored inline, without allocation.
+pub(in crate::time) struct InlineCallback {
+    /// The storage of the callback.
+    storage: MaybeUninit<[usize; INLINE_WORDS]>,
+    /// Function to call the callback stored.
+    call: unsafe fn(*mut (), u64) -> Option<u64>,
+    /// Function to drop the callback stored.
+    drop: unsafe fn(*mut ()),
+}
+
+// SAFETY: Only callbacks that are Send can be stored.
+unsafe impl Send for InlineCallback {}
+
+impl InlineCallback {
+
+    fn new<F: TimerCallback>(callback: F) -> Self {
+
+        const {
+            assert!(size_of::<F>() <= INLINE_SIZE, "timer callback is too large for the static timer queue");
+            assert!(align_of::<F>() <= align_of::<usize>(), "timer callback is over-aligned for the static timer queue");
+        }
+
+        unsafe fn call<F: TimerCallback>(ptr: *mut (), time: u64) -> Option<u64> {
+            unsafe { (*ptr.cast::<F>()).call(time) }
+        }
+
+        unsafe fn drop<F: TimerCallback>(ptr: *mut ()) {
+            unsafe { ptr.cast::<F>().drop_in_place() }
+        }
+
+        let mut storage = MaybeUninit::<[usize; INLINE_WORDS]>::uninit();
+        // SAFETY: Size and alignment are checked above.
+        unsafe { storage.as_mut_ptr().cast::<F>().write(callback) };
+
+        Self {
+            storage,
+            call: call::<F>,
+            drop: drop::<F>,
+        }
+
+    }
+
+    /// Call the stored callback.
+    #[inline]
+    pub fn call(&mut self, time: u64) -> Option<u64> {
+        // SAFETY: The storage contains the callback of this function.
+        unsafe { (self.call)(self.storage.as_mut_ptr().cast(), time) }
+    }
+
+}
+
+impl Drop for InlineCallback {
+    fn drop(&mut self) {
+        // SAFETY: The storage contains the callback of this function.
+        unsafe { (self.drop)(self.storage.as_mut_ptr().cast()) }
+    }
+}
+
+
+/// State of a slot of the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    /// The slot can be used by a new callback.
+    Free,
+    /// The slot is linked in the queue.
+    Queued,
+    /// The callback of the slot is being called, the slot is reserved.
+    Running,
+}
+
+/// A slot of the queue.
+struct Slot {
+    /// Unique identifier of the callback, the low byte is the slot index.
+    id: u64,
+    /// The target time for calling this callback.
+    target_time: u64,
+    /// Set when the callback has already been called in the current round.
+    deferred: bool,
+    /// Index of the next slot in the queue.
+    next: u8,
+    /// State of this slot.
+    state: SlotState,
+    /// The callback, if queued.
+    callback: Option<InlineCallback>,
+}
+
+impl Slot {
+    const FREE: Self = Self {
+        id: 0,
+        target_time: 0,
+        deferred: false,
+        next: NIL,
+        state: SlotState::Free,
+        callback: None,
+    };
+}
+
+/// The callbacks queue, ordered by target time, and the next unique identifier.
+pub(in crate::time) struct CallbackQueue {
+    next_id: u64,
+    head: u8,
+    slots: [Slot; CAPACITY],
+}
+
+impl CallbackQueue {
+
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            head: NIL,
+            slots: [Slot::FREE; CAPACITY],
+        }
+    }
+
+    /// Insert a new callback into the queue, returning its identifier or `None` if the
+    /// queue is full.
+    pub fn insert<F: TimerCallback>(&mut self, target_time: u64, callback: F) -> Option<u64> {
+
+        let idx = self.slots.iter().position(|slot| slot.state == SlotState::Free)?;
+        let id = (self.next_id << 8) | idx as u64;
+        self.next_id += 1;
+
+        let slot = &mut self.slots[idx];
+        slot.id = id;
+        slot.callback = Some(InlineCallback::new(callback));
+        self.link(idx as u8, target_time, false);
+        Some(id)
+
+    }
+
+    /// Remove the pending callback with the given identifier and return it, so that
+    /// it can be dropped once the queue is no longer borrowed.
+    pub fn remove(&mut self, id: u64) -> Option<InlineCallback> {
+        let idx = self.find_queued(id)?;
+        self.unlink(idx);
+        let slot = &mut self.slots[idx as usize];
+        slot.state = SlotState::Free;
+        slot.callback.take()
+    }
+
+    /// Change the target time of the pending callback with the given identifier.
+    pub fn reschedule(&mut self, id: u64, target_time: u64) -> bool {
+        if let Some(idx) = self.find_queued(id) {
+            self.unlink(idx);
+            self.link(idx, target_time, false);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return true if the callback with the given identifier is pending.
+    pub fn contains(&self, id: u64) -> bool {
+        self.find_queued(id).is_some()
+    }
+
+    /// Get the target time of the next callback.
+    pub fn next_time(&self) -> Option<u64> {
+        self.slots.get(self.head as usize).map(|slot| slot.target_time)
+    }
+
+    /// Take the first callback if it reached its target time and has not already been
+    /// called in this round, its slot is reserved until released or reinserted.
+    pub fn pop_due(&mut self, time: u64) -> Option<(u64, InlineCallback)> {
+
+        let idx = self.head;
+        let slot = self.slots.get_mut(idx as usize)?;
+        if slot.target_time > time || slot.deferred {
+            return None;
+        }
+
+        self.head = slot.next;
+        slot.state = SlotState::Running;
+        Some((slot.id, slot.callback.take().unwrap()))
+
+    }
+
+    /// Insert back a callback taken with [`pop_due`](Self::pop_due), it will not be
+    /// called again before the end of the round.
+    pub fn reinsert(&mut self, id: u64, target_time: u64, callback: InlineCallback, time: u64) {
+        let idx = (id & 0xFF) as u8;
+        self.slots[idx as usize].callback = Some(callback);
+        self.link(idx, target_time, target_time <= time);
+    }
+
+    /// Release the slot of a callback taken with [`pop_due`](Self::pop_due) that will
+    /// not be inserted back.
+    pub fn release(&mut self, id: u64) {
+        self.slots[(id & 0xFF) as usize].state = SlotState::Free;
+    }
+
+    /// End the current round of calls, deferred callbacks can be called again.
+    pub fn end_round(&mut self, time: u64) {
+        let mut idx = self.head;
+        while let Some(slot) = self.slots.get_mut(idx as usize) {
+            if slot.target_time > time {
+                break;
+            }
+            slot.deferred = false;
+            idx = slot.next;
+        }
+    }
+
+    /// Update the time compare register to the front callback, or just disable it by
+    /// setting it to the maximum value.
+    pub fn update_time_cmp(&self) {
+        if let Some(front) = self.slots.get(self.head as usize) {
+            set_time_cmp(front.target_time);
+        } else {
+            set_time_cmp(DISABLED_TIME_CMP);
+        }
+    }
+
+    /// Internal function to find the index of a queued callback.
+    fn find_queued(&self, id: u64) -> Option<u8> {
+        let idx = (id & 0xFF) as u8;
+        let slot = self.slots.get(idx as usize)?;
+        (slot.id == id && slot.state == SlotState::Queued).then_some(idx)
+    }
+
+    /// Internal function to link the given slot into the queue, after the callbacks
+    /// with the same target time.
+    fn link(&mut self, idx: u8, target_time: u64, deferred: bool) {
+
+        let mut prev = NIL;
+        let mut next = self.head;
+        while let Some(slot) = self.slots.get(next as usize) {
+            if slot.target_time > target_time {
+                break;
+            }
+            prev = next;
+            next = slot.next;
+        }
+
+        let slot = &mut self.slots[idx as usize];
+        slot.target_time = target_time;
+        slot.deferred = deferred;
+        slot.state = SlotState::Queued;
+        slot.next = next;
+
+        if prev == NIL {
+            // The first queue element is the next one to be called.
+            self.head = idx;
+            set_time_cmp(target_time);
+        } else {
+            self.slots[prev as usize].next = idx;
+        }
+
+    }
+
+    /// Internal function to unlink the given queued slot from the queue.
+    fn unlink(&mut self, idx: u8) {
+
+        let next = self.slots[idx as usize].next;
+
+        if self.head == idx {
+            self.head = next;
+            self.update_time_cmp();
+            return;
+        }
+
+        let mut prev = self.head;
+        while self.slots[prev as usize].next != idx {
+            prev = self.slots[prev as usize].next;
+        }
+        self.slots[prev as usize].next = next;
+
+    }
+
+}
@@ -0,0 +1,149 @@
+//! Heap-allocated callback queue, with no limit on the number of callbacks.
+
+use alloc::collections::VecDeque;
+use alloc::boxed::Box;
+
+use super::TimerCallback;
+use super::super::{set_time_cmp, DISABLED_TIME_CMP};
+
+
+/// Descriptor for a callback and when to call it.
+struct TimerCallbackDesc {
+    /// Unique identifier of the callback, used by its handle.
+    id: u64,
+    /// The target time for calling this callback.
+    target_time: u64,
+    /// Set when the callback has already been called in the current round.
+    deferred: bool,
+    /// The actual callback to call.
+    callback: Box<dyn TimerCallback>,
+}
+
+/// The callbacks queue, ordered by target time, and the next unique identifier.
+pub(in crate::time) struct CallbackQueue {
+    next_id: u64,
+    callbacks: VecDeque<TimerCallbackDesc>,
+}
+
+impl CallbackQueue {
+
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            callbacks: VecDeque::new(),
+        }
+    }
+
+    /// Insert a new callback into the queue, returning its identifier.
+    pub fn insert<F: TimerCallback>(&mut self, target_time: u64, callback: F) -> Option<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.insert_desc(TimerCallbackDesc {
+            id,
+            target_time,
+            deferred: false,
+            callback: Box::new(callback),
+        });
+        Some(id)
+    }
+
+    /// Remove the pending callback with the given identifier and return it, so that
+    /// it can be dropped once the queue is no longer borrowed.
+    pub fn remove(&mut self, id: u64) -> Option<Box<dyn TimerCallback>> {
+        self.remove_desc(id).map(|desc| desc.callback)
+    }
+
+    /// Change the target time of the pending callback with the given identifier.
+    pub fn reschedule(&mut self, id: u64, target_time: u64) -> bool {
+        if let Some(mut desc) = self.remove_desc(id) {
+            desc.target_time = target_time;
+            self.insert_desc(desc);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return true if the callback with the given identifier is pending.
+    pub fn contains(&self, id: u64) -> bool {
+        self.callbacks.iter().any(|desc| desc.id == id)
+    }
+
+    /// Get the target time of the next callback.
+    pub fn next_time(&self) -> Option<u64> {
+        self.callbacks.front().map(|desc| desc.target_time)
+    }
+
+    /// Take the first callback if it reached its target time and has not already been
+    /// called in this round.
+    pub fn pop_due(&mut self, time: u64) -> Option<(u64, Box<dyn TimerCallback>)> {
+        let front = self.callbacks.front()?;
+        if front.target_time <= time && !front.deferred {
+            let desc = self.callbacks.pop_front().unwrap();
+            Some((desc.id, desc.callback))
+        } else {
+            None
+        }
+    }
+
+    /// Insert back a callback taken with [`pop_due`](Self::pop_due), it will not be
+    /// called again before the end of the round.
+    pub fn reinsert(&mut self, id: u64, target_time: u64, callback: Box<dyn TimerCallback>, time: u64) {
+        self.insert_desc(TimerCallbackDesc {
+            id,
+            target_time,
+            deferred: target_time <= time,
+            callback,
+        });
+    }
+
+    /// Release a callback taken with [`pop_due`](Self::pop_due) that will not be
+    /// inserted back.
+    pub fn release(&mut self, id: u64) {
+        let _ = id;
+    }
+
+    /// End the current round of calls, deferred callbacks can be called again.
+    pub fn end_round(&mut self, time: u64) {
+        for desc in self.callbacks.iter_mut().take_while(|desc| desc.target_time <= time) {
+            desc.deferred = false;
+        }
+    }
+
+    /// Update the time compare register to the front callback, or just disable it by
+    /// setting it to the maximum value.
+    pub fn update_time_cmp(&self) {
+        if let Some(front) = self.callbacks.front() {
+            set_time_cmp(front.target_time);
+        } else {
+            set_time_cmp(DISABLED_TIME_CMP);
+        }
+    }
+
+    /// Internal function to insert the given callback, after the callbacks with the
+    /// same target time.
+    fn insert_desc(&mut self, desc: TimerCallbackDesc) {
+
+        let target_time = desc.target_time;
+        let insert_idx = self.callbacks.partition_point(|desc| desc.target_time <= target_time);
+
+        // The first queue element is the next one to be called.
+        if insert_idx == 0 {
+            set_time_cmp(target_time);
+        }
+
+        self.callbacks.insert(insert_idx, desc);
+
+    }
+
+    /// Internal function to remove the callback with the given identifier.
+    fn remove_desc(&mut self, id: u64) -> Option<TimerCallbackDesc> {
+        let idx = self.callbacks.iter().position(|desc| desc.id == id)?;
+        let desc = self.callbacks.remove(idx);
+        if idx == 0 {
+            self.update_time_cmp();
+        }
+        desc
+    }
+
+}
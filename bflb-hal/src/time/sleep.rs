@@ -0,0 +1,255 @@
+//! Asynchronous waiting on the core timer.
+//!
+//! These futures don't block the current thread, they register a callback in the
+//! timer queue (see [`try_wait_callback_at`]) that wakes the task when the deadline
+//! is reached, so other tasks can run in the meantime. If the queue is full, the
+//! task is woken immediately to poll again later.
+//!
+//! ```ignore
+//! let mut interval = time::interval(Duration::from_secs(1));
+//! loop {
+//!     interval.tick().await;
+//!     match time::with_timeout(Duration::from_millis(10), receive()).await {
+//!         Ok(data) => { /* ... */ }
+//!         Err(Elapsed) => { /* ... */ }
+//!     }
+//! }
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::{Duration, Instant, TimerHandle, TimerQueueFull, try_wait_callback_at};
+
+
+/// Return a future that completes after the given duration.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now().saturating_add(duration))
+}
+
+/// Return a future that completes when the given deadline is reached, immediately if
+/// it's already passed.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        registration: None,
+    }
+}
+
+/// Run the given future until it completes or until the given duration is elapsed,
+/// in which case the future is dropped and [`Elapsed`] is returned.
+pub fn with_timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+/// Create an interval that ticks every given period, the first tick completes after
+/// one period.
+///
+/// This function panics if the period is zero.
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must not be zero");
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+
+/// Future returned by [`sleep`] and [`sleep_until`]. Dropping it before its
+/// completion cancels its timer callback.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Sleep {
+    /// The instant when this future completes.
+    deadline: Instant,
+    /// The registered timer callback and the waker it wakes.
+    registration: Option<(TimerHandle, Waker)>,
+}
+
+impl Sleep {
+
+    /// Get the instant when this future completes.
+    #[inline]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Return true if the deadline has been reached.
+    #[inline]
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Change the deadline of this future, it can be polled again after completion.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        if let Some((handle, _)) = &self.registration {
+            if !handle.reschedule(deadline) {
+                self.registration = None;
+            }
+        }
+    }
+
+    /// Internal function to cancel the registered callback, if any.
+    fn cancel(&mut self) {
+        if let Some((handle, _)) = self.registration.take() {
+            handle.cancel();
+        }
+    }
+
+}
+
+impl Future for Sleep {
+
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+
+        if self.is_elapsed() {
+            self.cancel();
+            return Poll::Ready(());
+        }
+
+        // Keep the registered callback if it's still pending and wakes the same task.
+        if let Some((handle, waker)) = &self.registration {
+            if waker.will_wake(cx.waker()) && handle.is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        self.cancel();
+
+        // If the deadline is reached in the meantime, the callback is called on next
+        // interrupt, so we cannot miss the wake up.
+        let waker = cx.waker().clone();
+        let callback_waker = waker.clone();
+        let handle = try_wait_callback_at(self.deadline, move || {
+            callback_waker.wake_by_ref();
+            None
+        });
+
+        match handle {
+            Ok(handle) => self.registration = Some((handle, waker)),
+            // The timer queue is full, poll again later instead of panicking.
+            Err(TimerQueueFull) => cx.waker().wake_by_ref(),
+        }
+
+        Poll::Pending
+
+    }
+
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+
+/// Future returned by [`with_timeout`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+
+    /// Get the instant when this future times out.
+    #[inline]
+    pub fn deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+
+    /// Get back the inner future.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+}
+
+impl<F: Future> Future for Timeout<F> {
+
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+
+        // SAFETY: The inner future is never moved out of a pinned timeout, and the
+        // sleep future is not structurally pinned because it's Unpin.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+
+    }
+
+}
+
+/// Error returned by [`with_timeout`] when the duration is elapsed before the
+/// completion of the future.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+
+/// A periodic timer returned by [`interval`].
+///
+/// If a tick is awaited too late, so that the next deadline is already passed, the
+/// missed ticks are skipped and the next deadline is one period after the late tick.
+#[derive(Debug)]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+
+    /// Get the period of this interval.
+    #[inline]
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Poll the next tick, returning the instant it was scheduled for.
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let tick = self.sleep.deadline();
+        let mut next = tick.saturating_add(self.period);
+        let now = Instant::now();
+        if next <= now {
+            next = now.saturating_add(self.period);
+        }
+
+        self.sleep.reset(next);
+        Poll::Ready(tick)
+
+    }
+
+    /// Return a future that completes at the next tick, with the instant it was
+    /// scheduled for.
+    pub fn tick(&mut self) -> impl Future<Output = Instant> + '_ {
+        core::future::poll_fn(move |cx| self.poll_tick(cx))
+    }
+
+    /// Restart this interval so that the next tick completes after one period.
+    pub fn reset(&mut self) {
+        self.sleep.reset(Instant::now().saturating_add(self.period));
+    }
+
+}
@@ -0,0 +1,425 @@
+//! General purpose timers management on BL808.
+//!
+//! Each core has its own timer peripheral (TIMER0 for M0 and TIMER1 for D0) with two
+//! channels. Each channel has a 32-bit counter, a clock source with a prescaler and
+//! three match registers that can trigger interrupts. This is independent of the
+//! machine timer used by the [`time`](crate::time) module.
+
+use core::cell::RefCell;
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{Timer as TimerRegs, timer::TimerMatchBits};
+use crate::clock;
+use crate::time::Duration;
+
+
+/// Definition of an exclusive access to a timer channel. This channel need to be
+/// configured in order to obtain a [`Timer`] structure that is actually usable.
+///
+/// Available channels: 0, 1.
+pub struct TimerAccess<const CHANNEL: u8>(pub(crate) ());
+
+impl<const CHANNEL: u8> TimerAccess<CHANNEL> {
+
+    /// Initialize this timer channel with the given configuration. The channel is
+    /// stopped, see [`Timer::start`].
+    pub fn init(self, config: &TimerConfig) -> Timer<CHANNEL> {
+
+        let regs = get_registers();
+
+        set_enable::<CHANNEL>(false);
+
+        regs.tccr().modify(|reg| match CHANNEL {
+            0 => reg.cs_0().set(config.clock_source as _),
+            1 => reg.cs_1().set(config.clock_source as _),
+            _ => unreachable!()
+        });
+
+        let div = config.divider.clamp(1, 256) - 1;
+        regs.tcdr().modify(|reg| match CHANNEL {
+            0 => reg.tcdr0().set(div as _),
+            1 => reg.tcdr1().set(div as _),
+            _ => unreachable!()
+        });
+
+        let free_run = config.mode != TimerMode::Periodic;
+        regs.tcmr().modify(|reg| match CHANNEL {
+            0 => reg.timer0_mode().set(free_run as _),
+            1 => reg.timer1_mode().set(free_run as _),
+            _ => unreachable!()
+        });
+
+        // Periodic mode reloads the counter to zero on match 0.
+        let preload_trigger = if free_run { 0 } else { 1 };
+        match CHANNEL {
+            0 => {
+                regs.tplvr0().set(0);
+                regs.tplcr0().set_with(|reg| reg.tplcr().set(preload_trigger));
+            }
+            1 => {
+                regs.tplvr1().set(0);
+                regs.tplcr1().set_with(|reg| reg.tplcr().set(preload_trigger));
+            }
+            _ => unreachable!()
+        }
+
+        for n in 0..3 {
+            regs.tmr(CHANNEL as usize, n).set(u32::MAX);
+        }
+
+        regs.tier(CHANNEL as usize).set(TimerMatchBits(0));
+        regs.ticr(CHANNEL as usize).set(TimerMatchBits(0b111));
+
+        critical_section::with(|cs| {
+            STATES[CHANNEL as usize].borrow_ref_mut(cs).one_shot = config.mode == TimerMode::OneShot;
+        });
+
+        Timer {
+            mode: config.mode,
+        }
+
+    }
+
+}
+
+
+/// An initialized timer channel.
+pub struct Timer<const CHANNEL: u8> {
+    mode: TimerMode,
+}
+
+impl<const CHANNEL: u8> Timer<CHANNEL> {
+
+    /// Downgrade this timer channel and stop it.
+    pub fn downgrade(self) -> TimerAccess<CHANNEL> {
+        // Drop will be called at the end, effectively stopping the channel.
+        TimerAccess(())
+    }
+
+    /// Get the mode of this timer.
+    #[inline]
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    /// Get the frequency of the counter, after the prescaler.
+    pub fn frequency(&self) -> u32 {
+        let regs = get_registers();
+        let (sel, div) = match CHANNEL {
+            0 => (regs.tccr().get().cs_0().get(), regs.tcdr().get().tcdr0().get()),
+            1 => (regs.tccr().get().cs_1().get(), regs.tcdr().get().tcdr1().get()),
+            _ => unreachable!()
+        };
+        get_source_freq(sel) / (div + 1)
+    }
+
+    /// Get the current value of the counter.
+    #[inline]
+    pub fn counter(&self) -> u32 {
+        let regs = get_registers();
+        match CHANNEL {
+            0 => regs.tcr0().get(),
+            1 => regs.tcr1().get(),
+            _ => unreachable!()
+        }
+    }
+
+    /// Reset the counter to zero and start counting.
+    pub fn start(&mut self) {
+        let regs = get_registers();
+        regs.ticr(CHANNEL as usize).set(TimerMatchBits(0b111));
+        clear_counter::<CHANNEL>();
+        set_enable::<CHANNEL>(true);
+    }
+
+    /// Stop counting, the counter keeps its value.
+    pub fn stop(&mut self) {
+        set_enable::<CHANNEL>(false);
+    }
+
+    /// Return true if the counter is running.
+    pub fn running(&self) -> bool {
+        let mut tcer = get_registers().tcer().get();
+        match CHANNEL {
+            0 => tcer.timer0_en().get() != 0,
+            1 => tcer.timer1_en().get() != 0,
+            _ => unreachable!()
+        }
+    }
+
+    /// Set the value of a match register, in counter cycles.
+    ///
+    /// In periodic mode, match 0 defines the period: the counter is reset to zero
+    /// after reaching this value. In one-shot mode, the counter is stopped after
+    /// reaching match 0.
+    pub fn set_match(&mut self, m: TimerMatch, value: u32) {
+        get_registers().tmr(CHANNEL as usize, m as usize).set(value);
+    }
+
+    /// Get the value of a match register.
+    pub fn get_match(&self, m: TimerMatch) -> u32 {
+        get_registers().tmr(CHANNEL as usize, m as usize).get()
+    }
+
+    /// Set the period (periodic mode) or the delay (one-shot mode) of this timer,
+    /// in counter cycles, this is an alias for setting match 0.
+    #[inline]
+    pub fn set_period(&mut self, ticks: u32) {
+        self.set_match(TimerMatch::Match0, ticks.max(1));
+    }
+
+    /// Set the period (periodic mode) or the delay (one-shot mode) of this timer
+    /// as a duration, converted to counter cycles with the current frequency and
+    /// saturated to the maximum supported.
+    pub fn set_period_duration(&mut self, period: Duration) {
+        let ticks = period.to_ticks(self.frequency());
+        self.set_period(ticks.min(u32::MAX as u64) as u32);
+    }
+
+    /// Return true if the counter reached the given match register since the last
+    /// time it was cleared.
+    #[inline]
+    pub fn matched(&self, m: TimerMatch) -> bool {
+        get_registers().tmsr(CHANNEL as usize).get().get(m as u8)
+    }
+
+    /// Clear the matched status of the given match register.
+    #[inline]
+    pub fn clear_matched(&mut self, m: TimerMatch) {
+        get_registers().ticr(CHANNEL as usize).set_with(|reg| reg.set(m as u8, true));
+    }
+
+    /// Wait for the counter to reach the given match register and clear its status.
+    /// In one-shot mode, the counter is stopped if it's match 0.
+    pub fn wait(&mut self, m: TimerMatch) {
+        while !self.matched(m) {}
+        self.clear_matched(m);
+        if self.mode == TimerMode::OneShot && m == TimerMatch::Match0 {
+            self.stop();
+        }
+    }
+
+    /// Enable or disable the interrupt of the given match register, the interrupt
+    /// calls the callback set with [`set_callback`](Self::set_callback).
+    pub fn set_match_interrupt(&mut self, m: TimerMatch, enabled: bool) {
+        get_registers().tier(CHANNEL as usize).modify(|reg| reg.set(m as u8, enabled));
+    }
+
+    /// Set a callback to be called with the match register that triggered the
+    /// interrupt, match interrupts must be enabled with
+    /// [`set_match_interrupt`](Self::set_match_interrupt).
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(TimerMatch) + Send + 'static,
+    {
+        critical_section::with(|cs| {
+            STATES[CHANNEL as usize].borrow_ref_mut(cs).callback = Some(Box::new(callback));
+        });
+        unsafe { get_interrupt::<CHANNEL>().set_enabled(true) }
+    }
+
+    /// Remove the callback, if any.
+    pub fn clear_callback(&mut self) {
+        unsafe { get_interrupt::<CHANNEL>().set_enabled(false) }
+        critical_section::with(|cs| {
+            STATES[CHANNEL as usize].borrow_ref_mut(cs).callback.take();
+        });
+    }
+
+}
+
+impl<const CHANNEL: u8> Drop for Timer<CHANNEL> {
+    fn drop(&mut self) {
+        self.clear_callback();
+        let regs = get_registers();
+        set_enable::<CHANNEL>(false);
+        regs.tier(CHANNEL as usize).set(TimerMatchBits(0));
+        regs.ticr(CHANNEL as usize).set(TimerMatchBits(0b111));
+    }
+}
+
+
+/// Type alias for a boxed closure used as a timer callback.
+type TimerCallback = Box<dyn FnMut(TimerMatch) + Send>;
+
+/// Internal state of a channel, used by the interrupt handler.
+struct TimerState {
+    callback: Option<TimerCallback>,
+    /// Stop the counter when match 0 is reached.
+    one_shot: bool,
+}
+
+/// Default value: no callback.
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_STATE: Mutex<RefCell<TimerState>> = Mutex::new(RefCell::new(TimerState {
+    callback: None,
+    one_shot: false,
+}));
+/// States of each channel.
+static STATES: [Mutex<RefCell<TimerState>>; 2] = [NO_STATE; 2];
+
+/// Internal generic handler for a timer channel.
+fn timer_handler<const CHANNEL: u8>(cs: CriticalSection) {
+
+    let regs = get_registers();
+    let status = regs.tmsr(CHANNEL as usize).get();
+    regs.ticr(CHANNEL as usize).set(status);
+
+    let mut state = STATES[CHANNEL as usize].borrow_ref_mut(cs);
+
+    if state.one_shot && status.get(0) {
+        set_enable::<CHANNEL>(false);
+    }
+
+    if let Some(callback) = state.callback.as_mut() {
+        for m in [TimerMatch::Match0, TimerMatch::Match1, TimerMatch::Match2] {
+            if status.get(m as u8) {
+                callback(m);
+            }
+        }
+    }
+
+}
+
+/// Interrupt handler for channel 0.
+pub(crate) fn timer_ch0_handler(_code: usize, cs: CriticalSection) {
+    timer_handler::<0>(cs);
+}
+
+/// Interrupt handler for channel 1.
+pub(crate) fn timer_ch1_handler(_code: usize, cs: CriticalSection) {
+    timer_handler::<1>(cs);
+}
+
+
+/// Return the timer registers of the current core.
+#[inline]
+pub(crate) fn get_registers() -> TimerRegs {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    { crate::arch::bl808::TIMER0 }
+    #[cfg(feature = "bl808-d0")]
+    { crate::arch::bl808::TIMER1 }
+}
+
+/// Return the interrupt of the given channel on the current core.
+#[inline]
+fn get_interrupt<const CHANNEL: u8>() -> crate::interrupt::Interrupt {
+    use crate::interrupt;
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    match CHANNEL {
+        0 => interrupt::TIMER0_CH0,
+        1 => interrupt::TIMER0_CH1,
+        _ => unreachable!()
+    }
+    #[cfg(feature = "bl808-d0")]
+    match CHANNEL {
+        0 => interrupt::TIMER1_CH0,
+        1 => interrupt::TIMER1_CH1,
+        _ => unreachable!()
+    }
+}
+
+/// Return the frequency of the bus clock of the current core's timer.
+#[inline]
+fn get_bclk_freq() -> u32 {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    { clock::mcu::get_mcu_pbclk_freq() }
+    #[cfg(feature = "bl808-d0")]
+    { clock::mm::get_mm_bclk2_freq() }
+}
+
+/// Return the frequency of the given clock source selection, as written in the
+/// `tccr` register. Also used by the watchdog that shares the same sources.
+pub(crate) fn get_source_freq(sel: u32) -> u32 {
+    match sel {
+        0 => get_bclk_freq(),
+        1 => clock::get_f32k_freq(),
+        2 => 1_000,
+        3 => clock::get_xtal_freq(),
+        _ => 0,
+    }
+}
+
+/// Internal function to enable or disable the given channel.
+fn set_enable<const CHANNEL: u8>(enable: bool) {
+    get_registers().tcer().modify(|reg| match CHANNEL {
+        0 => reg.timer0_en().set(enable as _),
+        1 => reg.timer1_en().set(enable as _),
+        _ => unreachable!()
+    });
+}
+
+/// Internal function to reset the counter of the given channel to zero.
+fn clear_counter<const CHANNEL: u8>() {
+    let regs = get_registers();
+    regs.tcer().modify(|reg| match CHANNEL {
+        0 => reg.timer0_cnt_clr().fill(),
+        1 => reg.timer1_cnt_clr().fill(),
+        _ => unreachable!()
+    });
+    regs.tcer().modify(|reg| match CHANNEL {
+        0 => reg.timer0_cnt_clr().clear(),
+        1 => reg.timer1_cnt_clr().clear(),
+        _ => unreachable!()
+    });
+}
+
+
+/// Configuration structure for timer channel initialization.
+#[derive(Debug, Clone)]
+pub struct TimerConfig {
+    /// Counting mode.
+    pub mode: TimerMode,
+    /// Clock source of the counter.
+    pub clock_source: TimerClockSource,
+    /// Prescaler of the clock source, from 1 to 256.
+    pub divider: u16,
+}
+
+impl TimerConfig {
+
+    /// Create a new basic config with the given mode, counting the crystal clock
+    /// without prescaler.
+    pub const fn new(mode: TimerMode) -> Self {
+        Self {
+            mode,
+            clock_source: TimerClockSource::Xtal,
+            divider: 1,
+        }
+    }
+
+}
+
+/// Counting mode of a timer channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// The counter is reset to zero when reaching match 0.
+    Periodic,
+    /// The counter is stopped when reaching match 0, if the interrupt is enabled or
+    /// when waiting for it.
+    OneShot,
+    /// The counter is never reset and wraps around.
+    FreeRun,
+}
+
+/// Clock source of a timer channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClockSource {
+    /// Bus clock of the core.
+    Bclk = 0,
+    F32k = 1,
+    /// 1 kHz clock.
+    Khz1 = 2,
+    Xtal = 3,
+}
+
+/// Match registers of a timer channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMatch {
+    Match0 = 0,
+    Match1 = 1,
+    Match2 = 2,
+}
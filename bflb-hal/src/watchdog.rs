@@ -0,0 +1,350 @@
+//! Watchdog timer and reset reason on BL808.
+//!
+//! Each core has its own watchdog, located in the timer peripheral of the core
+//! (TIMER0 for M0 and TIMER1 for D0). The watchdog has a 16-bit counter that must be
+//! regularly reset (fed) before it reaches its match value, otherwise the chip is
+//! reset or an interrupt is triggered, depending on the configured mode.
+//!
+//! This module also provides [`reset_reason`] to know why the chip has been reset,
+//! this is typically read once at boot.
+
+use core::cell::RefCell;
+
+use alloc::boxed::Box;
+
+use critical_section::{Mutex, CriticalSection};
+
+use crate::arch::bl808::{GLB, HBN};
+use crate::timer::{self, TimerClockSource};
+use crate::time::Duration;
+
+
+/// Exclusive access to the watchdog of the current core. It need to be configured in
+/// order to obtain a [`Watchdog`] structure that is actually usable.
+pub struct WatchdogAccess(pub(crate) ());
+
+impl WatchdogAccess {
+
+    /// Initialize the watchdog with the given configuration. The watchdog is
+    /// stopped, see [`Watchdog::start`].
+    pub fn init(self, config: &WatchdogConfig) -> Watchdog {
+
+        let regs = timer::get_registers();
+
+        unlock();
+        regs.wmer().modify(|reg| reg.we().clear());
+
+        unlock();
+        regs.tccr().modify(|reg| reg.cs_wdt().set(config.clock_source as _));
+
+        let div = config.divider.clamp(1, 256) - 1;
+        unlock();
+        regs.tcdr().modify(|reg| reg.wcdr().set(div as _));
+
+        unlock();
+        regs.wicr().set_with(|reg| reg.wiclr().fill());
+
+        let mut watchdog = Watchdog {
+            mode: config.mode,
+        };
+
+        watchdog.set_mode(config.mode);
+        watchdog.set_timeout_duration(config.timeout);
+        watchdog
+
+    }
+
+}
+
+
+/// An initialized watchdog.
+pub struct Watchdog {
+    mode: WatchdogMode,
+}
+
+impl Watchdog {
+
+    /// Downgrade this watchdog and stop it.
+    pub fn downgrade(self) -> WatchdogAccess {
+        // Drop will be called at the end, effectively stopping the watchdog.
+        WatchdogAccess(())
+    }
+
+    /// Get the mode of this watchdog.
+    #[inline]
+    pub fn mode(&self) -> WatchdogMode {
+        self.mode
+    }
+
+    /// Get the frequency of the watchdog counter, after the prescaler.
+    pub fn frequency(&self) -> u32 {
+        let regs = timer::get_registers();
+        let sel = regs.tccr().get().cs_wdt().get();
+        let div = regs.tcdr().get().wcdr().get();
+        timer::get_source_freq(sel) / (div + 1)
+    }
+
+    /// Set the timeout of the watchdog, in counter cycles. The counter is reset.
+    pub fn set_timeout(&mut self, ticks: u16) {
+        let regs = timer::get_registers();
+        unlock();
+        regs.wmr().set_with(|reg| reg.wmr().set(ticks.max(1) as _));
+        self.feed();
+    }
+
+    /// Get the timeout of the watchdog, in counter cycles.
+    pub fn timeout(&self) -> u16 {
+        timer::get_registers().wmr().get().wmr().get() as u16
+    }
+
+    /// Set the timeout of the watchdog as a duration, the timeout is saturated to
+    /// the maximum supported by the current clock. The counter is reset.
+    pub fn set_timeout_duration(&mut self, timeout: Duration) {
+        let ticks = timeout.to_ticks(self.frequency());
+        self.set_timeout(ticks.min(u16::MAX as u64) as u16);
+    }
+
+    /// Get the timeout of the watchdog as a duration.
+    pub fn timeout_duration(&self) -> Duration {
+        Duration::from_ticks(self.timeout() as u64, self.frequency())
+    }
+
+    /// Get the current value of the counter.
+    #[inline]
+    pub fn counter(&self) -> u16 {
+        timer::get_registers().wvr().get().wvr().get() as u16
+    }
+
+    /// Reset the counter and start the watchdog.
+    pub fn start(&mut self) {
+        self.feed();
+        unlock();
+        timer::get_registers().wmer().modify(|reg| reg.we().fill());
+    }
+
+    /// Stop the watchdog.
+    pub fn stop(&mut self) {
+        unlock();
+        timer::get_registers().wmer().modify(|reg| reg.we().clear());
+    }
+
+    /// Return true if the watchdog is running.
+    pub fn running(&self) -> bool {
+        timer::get_registers().wmer().get().we().get() != 0
+    }
+
+    /// Feed the watchdog, resetting its counter to zero. This must be called more
+    /// often than the timeout when the watchdog is running.
+    ///
+    /// With [`WatchdogMode::InterruptThenReset`], this also cancels a pending
+    /// reset if the interrupt has already been triggered.
+    pub fn feed(&mut self) {
+        let regs = timer::get_registers();
+        critical_section::with(|_| {
+            unlock();
+            regs.wcr().set_with(|reg| reg.wcr().fill());
+            if self.mode == WatchdogMode::InterruptThenReset {
+                unlock();
+                regs.wmer().modify(|reg| reg.wrie().clear());
+            }
+        });
+    }
+
+    /// Set the expiration mode of this watchdog.
+    pub fn set_mode(&mut self, mode: WatchdogMode) {
+        self.mode = mode;
+        critical_section::with(|cs| {
+            *MODE.borrow_ref_mut(cs) = mode;
+        });
+        unlock();
+        timer::get_registers().wmer().modify(|reg| reg.wrie().set((mode == WatchdogMode::Reset) as _));
+        unsafe { get_interrupt().set_enabled(mode != WatchdogMode::Reset) }
+    }
+
+    /// Set a callback to be called when the watchdog expires, only used by modes
+    /// [`WatchdogMode::Interrupt`] and [`WatchdogMode::InterruptThenReset`].
+    pub fn set_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        critical_section::with(|cs| {
+            CALLBACK.borrow_ref_mut(cs).replace(Box::new(callback));
+        });
+    }
+
+    /// Remove the callback, if any.
+    pub fn clear_callback(&mut self) {
+        critical_section::with(|cs| {
+            CALLBACK.borrow_ref_mut(cs).take();
+        });
+    }
+
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+        unsafe { get_interrupt().set_enabled(false) }
+        self.clear_callback();
+        unlock();
+        timer::get_registers().wicr().set_with(|reg| reg.wiclr().fill());
+    }
+}
+
+
+/// Expiration mode of the watchdog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogMode {
+    /// The chip is reset when the watchdog expires.
+    Reset,
+    /// The callback is called each time the watchdog expires, the chip is never
+    /// reset.
+    Interrupt,
+    /// The callback is called when the watchdog expires the first time, the
+    /// counter is then restarted and the chip is reset if the watchdog expires
+    /// again without being fed. This gives a chance to save state before reset.
+    InterruptThenReset,
+}
+
+/// Configuration structure for watchdog initialization.
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// Expiration mode.
+    pub mode: WatchdogMode,
+    /// Timeout, saturated to the maximum supported by the clock.
+    pub timeout: Duration,
+    /// Clock source of the counter.
+    pub clock_source: TimerClockSource,
+    /// Prescaler of the clock source, from 1 to 256.
+    pub divider: u16,
+}
+
+impl WatchdogConfig {
+
+    /// Create a new basic config with the given timeout, resetting the chip on
+    /// expiration. The counter uses the 1 kHz clock, so the timeout can be up to
+    /// 65 seconds.
+    pub const fn new(timeout: Duration) -> Self {
+        Self {
+            mode: WatchdogMode::Reset,
+            timeout,
+            clock_source: TimerClockSource::Khz1,
+            divider: 1,
+        }
+    }
+
+}
+
+
+/// Type alias for a boxed closure used as a watchdog callback.
+type WatchdogCallback = Box<dyn FnMut() + Send>;
+
+/// Callback called when the watchdog expires.
+static CALLBACK: Mutex<RefCell<Option<WatchdogCallback>>> = Mutex::new(RefCell::new(None));
+/// Current mode of the watchdog, used by the interrupt handler.
+static MODE: Mutex<RefCell<WatchdogMode>> = Mutex::new(RefCell::new(WatchdogMode::Reset));
+
+/// Interrupt handler for the watchdog.
+pub(crate) fn watchdog_handler(_code: usize, cs: CriticalSection) {
+
+    let regs = timer::get_registers();
+    unlock();
+    regs.wicr().set_with(|reg| reg.wiclr().fill());
+
+    if *MODE.borrow_ref(cs) == WatchdogMode::InterruptThenReset {
+        // Next expiration will reset the chip, unless fed.
+        unlock();
+        regs.wmer().modify(|reg| reg.wrie().fill());
+    }
+
+    // Restart the counter for the next expiration.
+    unlock();
+    regs.wcr().set_with(|reg| reg.wcr().fill());
+
+    if let Some(callback) = CALLBACK.borrow_ref_mut(cs).as_mut() {
+        callback();
+    }
+
+}
+
+/// Write the access keys, this must be done before each write to a watchdog
+/// register, including the clock source and divider.
+#[inline]
+fn unlock() {
+    let regs = timer::get_registers();
+    regs.wfar().set(0xBABA);
+    regs.wsar().set(0xEB10);
+}
+
+/// Return the watchdog interrupt of the current core.
+#[inline]
+fn get_interrupt() -> crate::interrupt::Interrupt {
+    #[cfg(any(feature = "bl808-m0", feature = "bl808-lp"))]
+    { crate::interrupt::TIMER0_WDT }
+    #[cfg(feature = "bl808-d0")]
+    { crate::interrupt::TIMER1_WDT }
+}
+
+
+/// Get the reason of the last reset of the chip. If multiple sources are recorded,
+/// the most significant is returned (power failures first).
+///
+/// Only the watchdog of the current core is checked directly, because the timer of
+/// the other core may be in a domain that is not clocked. A reset by the watchdog of
+/// the other core is still reported through the chip reset recorder.
+pub fn reset_reason() -> ResetReason {
+
+    let mut hbn_glb = HBN.glb().get();
+    let event = hbn_glb.reset_event().get();
+    let mut reset_sts = GLB.reset_sts0().get();
+    let recorder = reset_sts.top_reset_recorder().get();
+    let watchdog = timer::get_registers().wsr().get().wts().get() != 0;
+
+    if event & 0b10 != 0 {
+        ResetReason::BrownOut
+    } else if event & 0b01 != 0 || recorder & (1 << 0) != 0 {
+        ResetReason::PowerOn
+    } else if watchdog || recorder & (1 << 3) != 0 {
+        ResetReason::Watchdog
+    } else if recorder & (1 << 1) != 0 {
+        ResetReason::External
+    } else if recorder & ((1 << 5) | (1 << 6)) != 0 {
+        ResetReason::Software
+    } else if recorder & (1 << 2) != 0 {
+        ResetReason::PowerDown
+    } else {
+        ResetReason::Unknown
+    }
+
+}
+
+/// Clear the recorded reset reasons, so that the next call to [`reset_reason`]
+/// only reports resets that happened after this call. Only the watchdog status of the
+/// current core is cleared, see [`reset_reason`].
+pub fn clear_reset_reason() {
+    HBN.glb().modify(|reg| reg.clr_reset_event().fill());
+    HBN.glb().modify(|reg| reg.clr_reset_event().clear());
+    GLB.reset_sts0().modify(|reg| reg.clr_top_reset_recorder().fill());
+    GLB.reset_sts0().modify(|reg| reg.clr_top_reset_recorder().clear());
+    unlock();
+    timer::get_registers().wsr().set_with(|reg| reg.wts().clear());
+}
+
+/// Reason of the last reset of the chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    /// Power has been applied to the chip.
+    PowerOn,
+    /// The supply voltage dropped under the brown-out threshold.
+    BrownOut,
+    /// A watchdog expired.
+    Watchdog,
+    /// The external reset pin has been asserted.
+    External,
+    /// Software requested a system or CPU reset.
+    Software,
+    /// Wake up from a power down (PDS) mode.
+    PowerDown,
+    /// No recorded reason, for example if it has been cleared.
+    Unknown,
+}
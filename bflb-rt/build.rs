@@ -4,6 +4,9 @@ use std::{env, fs};
 
 fn main() {
 
+    println!("cargo:rustc-check-cfg=cfg(rt_chip, values(\"bl808_m0\", \"bl808_d0\", \"bl808_lp\"))");
+    println!("cargo:rustc-check-cfg=cfg(rt_chip_ok)");
+
     let chips = [
         (env::var("CARGO_FEATURE_BL808_M0").is_ok(), "bl808_m0"),
         (env::var("CARGO_FEATURE_BL808_D0").is_ok(), "bl808_d0"),
@@ -0,0 +1,53 @@
+//! Idle hook of the runtime, called in loop once the main function returns.
+//!
+//! The hart sleeps until the next timer callback (see [`hal::time::next_deadline`]),
+//! in the deepest sleep mode allowed by [`set_idle_mode`], so there is no periodic
+//! tick waking it up.
+
+use crate::hal;
+
+#[cfg(rt_chip = "bl808_m0")]
+use core::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(rt_chip = "bl808_m0")]
+pub use hal::sleep::SleepMode;
+
+
+/// The deepest sleep mode allowed while idle.
+#[cfg(rt_chip = "bl808_m0")]
+static IDLE_MODE: AtomicU8 = AtomicU8::new(SleepMode::Wait as u8);
+
+/// Set the deepest sleep mode allowed while idle, see [`SleepMode`] for the wake-up
+/// sources of each mode. The default mode is [`SleepMode::Wait`].
+#[cfg(rt_chip = "bl808_m0")]
+pub fn set_idle_mode(mode: SleepMode) {
+    IDLE_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Get the deepest sleep mode allowed while idle.
+#[cfg(rt_chip = "bl808_m0")]
+pub fn idle_mode() -> SleepMode {
+    match IDLE_MODE.load(Ordering::Relaxed) {
+        0 => SleepMode::Wait,
+        1 => SleepMode::ClockGated,
+        _ => SleepMode::PowerDown,
+    }
+}
+
+/// Sleep until the next interrupt, the pending interrupt is handled before this
+/// function returns.
+#[cfg(rt_chip = "bl808_m0")]
+pub fn idle() {
+    // Interrupts are handled when leaving the critical section, so the deadline
+    // cannot be changed by an interrupt before sleeping.
+    critical_section::with(|_| {
+        hal::sleep::sleep_until(idle_mode(), hal::time::next_deadline());
+    });
+}
+
+/// Sleep until the next interrupt, the pending interrupt is handled before this
+/// function returns.
+#[cfg(not(rt_chip = "bl808_m0"))]
+pub fn idle() {
+    hal::hart::wait_for_interrupt();
+}
@@ -0,0 +1,243 @@
+//! Interrupt handlers registered by the application.
+//!
+//! The HAL handles the interrupts of its drivers through its own vector (see
+//! [`hal::interrupt::VECTOR`]), all other interrupts can be bound to a handler by the
+//! application, either at runtime with [`register`] and [`register_fn`], or statically
+//! with the [`interrupt_handler!`](crate::interrupt_handler) macro.
+//!
+//! Handlers are called with interrupts disabled, they can register or unregister
+//! handlers, including their own.
+
+use core::cell::RefCell;
+
+use alloc::boxed::Box;
+
+use critical_section::Mutex;
+
+use crate::{hal, sym};
+use hal::interrupt::{COUNT as INT_COUNT, VECTOR};
+
+pub use hal::interrupt::{Interrupt, InterruptHandler};
+pub use critical_section::CriticalSection;
+
+
+/// Type alias for interrupt handler closures.
+pub type InterruptClosure = Box<dyn FnMut(CriticalSection) + Send>;
+
+/// A handler bound to an interrupt.
+enum Handler {
+    /// A function pointer, also given the interrupt code.
+    Fn(InterruptHandler),
+    /// A closure.
+    Closure(InterruptClosure),
+}
+
+/// State of an interrupt slot.
+enum Slot {
+    /// No handler is bound to the interrupt.
+    Free,
+    /// The handler bound to the interrupt.
+    Bound(Handler),
+    /// The closure bound to the interrupt is being called, it has been taken out of
+    /// the slot and will be put back, unless it is unregistered meanwhile.
+    Running,
+}
+
+const FREE_SLOT: Slot = Slot::Free;
+
+/// All interrupt handlers registered by the application.
+static HANDLERS: Mutex<RefCell<[Slot; INT_COUNT]>> = Mutex::new(RefCell::new([FREE_SLOT; INT_COUNT]));
+
+
+/// Error returned when registering an interrupt handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// The interrupt code is out of range for this chip.
+    InvalidInterrupt,
+    /// The interrupt is already handled by a driver of the HAL.
+    Reserved,
+    /// A handler is already registered for this interrupt, it should be unregistered
+    /// first.
+    AlreadyRegistered,
+}
+
+
+/// Register a closure to be called on the given interrupt, the interrupt is then
+/// enabled.
+pub fn register<F>(interrupt: Interrupt, handler: F) -> Result<(), RegisterError>
+where
+    F: FnMut(CriticalSection) + Send + 'static
+{
+    bind(interrupt, Handler::Closure(Box::new(handler)))
+}
+
+/// Register a function to be called on the given interrupt, the interrupt is then
+/// enabled. Unlike [`register`], this doesn't allocate.
+pub fn register_fn(interrupt: Interrupt, handler: InterruptHandler) -> Result<(), RegisterError> {
+    bind(interrupt, Handler::Fn(handler))
+}
+
+/// Unregister the handler of the given interrupt and disable it, returning false if
+/// no handler was registered. Interrupts handled by the HAL are left untouched.
+pub fn unregister(interrupt: Interrupt) -> bool {
+    critical_section::with(|cs| {
+
+        let mut handlers = HANDLERS.borrow_ref_mut(cs);
+        let Some(slot) = handlers.get_mut(interrupt.code) else {
+            return false;
+        };
+
+        if let Slot::Free = slot {
+            return false;
+        }
+
+        // SAFETY: The handler is removed in the same critical section.
+        unsafe { interrupt.set_enabled(false) }
+        // If running, the closure will be dropped after its call returns.
+        *slot = Slot::Free;
+        true
+
+    })
+}
+
+/// Return true if a handler is registered for the given interrupt, this doesn't
+/// include the handlers of the HAL.
+pub fn is_registered(interrupt: Interrupt) -> bool {
+    critical_section::with(|cs| {
+        HANDLERS.borrow_ref(cs)
+            .get(interrupt.code)
+            .is_some_and(|slot| !matches!(slot, Slot::Free))
+    })
+}
+
+/// Internal function to bind a handler to a free interrupt and enable it.
+fn bind(interrupt: Interrupt, handler: Handler) -> Result<(), RegisterError> {
+    critical_section::with(|cs| {
+
+        let mut handlers = HANDLERS.borrow_ref_mut(cs);
+        let slot = handlers.get_mut(interrupt.code).ok_or(RegisterError::InvalidInterrupt)?;
+
+        if VECTOR[interrupt.code].is_some() {
+            return Err(RegisterError::Reserved);
+        } else if !matches!(slot, Slot::Free) {
+            return Err(RegisterError::AlreadyRegistered);
+        }
+
+        *slot = Slot::Bound(handler);
+        // SAFETY: The handler is registered in the same critical section.
+        unsafe { interrupt.set_enabled(true) }
+        Ok(())
+
+    })
+}
+
+
+/// Interrupt handler used for all interrupts not handled by the HAL, it calls the
+/// handler registered by the application, if any.
+pub(crate) fn dispatch_handler(code: usize, cs: CriticalSection) {
+
+    let mut closure = {
+        let mut handlers = HANDLERS.borrow_ref_mut(cs);
+        match &mut handlers[code] {
+            Slot::Bound(Handler::Fn(handler)) => {
+                let handler = *handler;
+                drop(handlers);
+                (handler)(code, cs);
+                return;
+            }
+            slot @ Slot::Bound(Handler::Closure(_)) => {
+                let Slot::Bound(Handler::Closure(closure)) = core::mem::replace(slot, Slot::Running) else {
+                    unreachable!()
+                };
+                closure
+            }
+            // Interrupts are not expected to be enabled without handler.
+            Slot::Free | Slot::Running => return,
+        }
+    };
+
+    // The handlers are not borrowed while calling the closure, so it can register or
+    // unregister handlers.
+    (closure)(cs);
+
+    let mut handlers = HANDLERS.borrow_ref_mut(cs);
+    let slot = &mut handlers[code];
+    if let Slot::Running = slot {
+        *slot = Slot::Bound(Handler::Closure(closure));
+    }
+
+}
+
+/// Internal function to get the handler registered for the given interrupt, used
+/// for debugging: `Some(None)` is returned for closures.
+#[cfg(feature = "panic-uart-14")]
+pub(crate) fn registered(code: usize, cs: CriticalSection) -> Option<Option<InterruptHandler>> {
+    match HANDLERS.borrow(cs).try_borrow().ok()?.get(code)? {
+        Slot::Free => None,
+        Slot::Bound(Handler::Fn(handler)) => Some(Some(*handler)),
+        Slot::Bound(Handler::Closure(_)) | Slot::Running => Some(None),
+    }
+}
+
+
+/// Descriptor of a handler bound statically with the
+/// [`interrupt_handler!`](crate::interrupt_handler) macro, placed in a dedicated linker
+/// section so that the runtime can register it before the main function.
+#[doc(hidden)]
+#[repr(C)]
+pub struct StaticHandler {
+    pub code: usize,
+    pub handler: InterruptHandler,
+}
+
+/// Internal function to register all handlers bound statically, called once on
+/// startup. Panics if one of these interrupts is already handled.
+pub(crate) fn register_static_handlers() {
+
+    // SAFETY: The linker script only places descriptors in this section.
+    let handlers = unsafe {
+        let start = (&raw const sym::_ld_interrupt_handlers_start).cast::<StaticHandler>();
+        let end = (&raw const sym::_ld_interrupt_handlers_end).cast::<StaticHandler>();
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    };
+
+    for desc in handlers {
+        if let Err(e) = register_fn(Interrupt::new(desc.code), desc.handler) {
+            panic!("failed to bind static handler to interrupt {}: {e:?}", desc.code);
+        }
+    }
+
+}
+
+
+/// Statically bind a function to an interrupt, it is registered by the runtime before
+/// the main function is called, the same way as [`register_fn`], and can later be
+/// unregistered. The function is given the interrupt code and a critical section
+/// token.
+///
+/// ```ignore
+/// use bflb_rt::hal::interrupt::UART0;
+/// use bflb_rt::interrupt::CriticalSection;
+///
+/// fn uart0_handler(code: usize, cs: CriticalSection) {
+///     // ...
+/// }
+///
+/// bflb_rt::interrupt_handler!(UART0, uart0_handler);
+/// ```
+///
+/// The runtime panics on startup if the interrupt is handled by the HAL or already
+/// bound, see [`RegisterError`].
+#[macro_export]
+macro_rules! interrupt_handler {
+    ($interrupt:expr, $handler:path) => {
+        const _: () = {
+            #[used]
+            #[link_section = ".interrupt_handlers"]
+            static STATIC_HANDLER: $crate::interrupt::StaticHandler = $crate::interrupt::StaticHandler {
+                code: $interrupt.code,
+                handler: $handler,
+            };
+        };
+    };
+}
@@ -22,10 +22,13 @@ mod bl808_d0;
 // Re-export HAL.
 pub use bflb_hal as hal;
 
+pub mod idle;
+pub mod interrupt;
+
 // These modules are intentionally internal.
 mod allocator;
 
-use hal::interrupt::{COUNT as INT_COUNT, VECTOR, InterruptHandler, noop_handler};
+use hal::interrupt::{COUNT as INT_COUNT, VECTOR, InterruptHandler};
 use critical_section::CriticalSection;
 use allocator::RuntimeAllocator;
 
@@ -48,6 +51,12 @@ pub mod sym {
         /// First word **after** the read-only section.
         pub static mut _ld_rodata_end: u32;
 
+        /// First word of the statically bound interrupt handlers, in the
+        /// read-only data section.
+        pub static mut _ld_interrupt_handlers_start: u32;
+        /// First word **after** the statically bound interrupt handlers.
+        pub static mut _ld_interrupt_handlers_end: u32;
+
         /// First word of the data section in Flash that
         /// should be copied to RAM.
         pub static mut _ld_data_load_start: u32;
@@ -90,8 +99,9 @@ pub mod sym {
 #[global_allocator]
 static ALLOCATOR: RuntimeAllocator = RuntimeAllocator::empty();
 
-/// All interrupt (asynchronous) handlers.
-static INTERRUPT_VECTOR: [InterruptHandler; INT_COUNT] = VECTOR;
+/// All interrupt (asynchronous) handlers of the HAL, other interrupts are dispatched
+/// to the handlers registered in the [`interrupt`] module.
+static INTERRUPT_VECTOR: [Option<InterruptHandler>; INT_COUNT] = VECTOR;
 
 
 /// This function is responsible for loading mutable static variables 
@@ -147,6 +157,10 @@ extern "C" fn _rust_init() {
     unsafe { 
         hal::init(); 
     }
+    // Handlers are global, they are only registered once.
+    if hal::hart::hart_zero() {
+        interrupt::register_static_handlers();
+    }
 }
 
 
@@ -162,9 +176,9 @@ extern "C" fn _rust_entry() -> ! {
 
     unsafe { main(); }
 
-    // This function should no return: spin loop.
+    // This function should no return: sleep until the next interrupt in loop.
     loop {
-        hal::hart::wait_for_interrupt();
+        idle::idle();
     }
 
 }
@@ -186,7 +200,7 @@ extern "C" fn _rust_mtrap_handler(cause: usize) {
     let interrupt = cause & INTERRUPT_MASK != 0;
 
     let handler = if interrupt {
-        INTERRUPT_VECTOR[code]
+        INTERRUPT_VECTOR[code].unwrap_or(interrupt::dispatch_handler)
     } else {
         panic!("hardware exception: {code}");
     };
@@ -256,17 +270,23 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
         let enabled = int.enabled();
         let pending = int.pending();
         let handler = INTERRUPT_VECTOR[code];
+        let registered = critical_section::with(|cs| interrupt::registered(code, cs));
 
         // Do not show uninteresting interrupts.
-        if !enabled && !pending && handler == noop_handler {
+        if !enabled && !pending && handler.is_none() && registered.is_none() {
             continue;
         }
         
         let _ = write!(uart, "        {code:>5}: ");
         if int.enabled() { let _ = write!(uart, "enabled "); }
         if int.pending() { let _ = write!(uart, "pending "); }
-        if handler != noop_handler {
+        if let Some(handler) = handler {
             let _ = write!(uart, "-> {:?}", handler);
+        } else if let Some(registered) = registered {
+            match registered {
+                Some(handler) => { let _ = write!(uart, "-> {:?} (registered)", handler); }
+                None => { let _ = write!(uart, "-> closure (registered)"); }
+            }
         }
         let _ = writeln!(uart);
 
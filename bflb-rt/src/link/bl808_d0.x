@@ -50,6 +50,14 @@ SECTIONS {
 
         *(.rodata .rodata.*)
 
+        /* Interrupt handlers bound statically with the
+         * `interrupt_handler!` macro, installed by the runtime.
+         */
+        . = ALIGN(8);
+        _ld_interrupt_handlers_start = .;
+        KEEP(*(.interrupt_handlers))
+        _ld_interrupt_handlers_end = .;
+
         . = ALIGN(4);
         _ld_rodata_end = .;
 
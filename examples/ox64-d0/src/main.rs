@@ -11,6 +11,7 @@ use hal::Peripherals;
 use hal::clock::{XtalType, UartSel, McuRootSel, XclkSel, MmXclkSel};
 use hal::uart::UartConfig;
 use hal::interrupt;
+use hal::time::{self, Duration};
 
 
 #[link_section = ".data"] // Loaded in RAM
@@ -25,7 +26,6 @@ fn main() {
     let mut clocks = peripherals.clocks;
     let mut cpu_control = peripherals.cpu_control;
     let mut interrupts = peripherals.interrupts;
-    let timer = peripherals.timer;
 
     clocks.set_d0_cpu_enable(false);
     cpu_control.reset_d0();
@@ -67,8 +67,6 @@ fn main() {
     
     clocks.set_adc_dac_enable(true);
     
-    timer.init(&mut clocks);
-    
     // INTERRUPT INIT
     
     interrupts.set_enabled(interrupt::MACHINE_TIMER, true);
@@ -97,8 +95,8 @@ fn main() {
         
         loop {
 
-            let _ = writeln!(uart_tx, "RTC time: {}", timer.get_time());
-            timer.wait(1_000_000).await;
+            let _ = writeln!(uart_tx, "RTC time: {}", time::get_time());
+            time::sleep(Duration::from_secs(1)).await;
 
         }
 
@@ -12,7 +12,7 @@ use bflb_rt::hal;
 use hal::cache::CacheAligned;
 use hal::uart::UartConfig;
 use hal::Peripherals;
-use hal::time;
+use hal::time::{self, Duration, Instant};
 
 
 #[link_section = ".data"] // Loaded in RAM
@@ -47,9 +47,9 @@ pub fn main() {
             let _ = writeln!(uart, "dst: {:?}", core::str::from_utf8(&**dst).unwrap());
 
             // LOOP
-            time::wait_callback(0, move || {
+            time::wait_callback(Duration::ZERO, move || {
 
-                let mut time_ms = time::get_time() / 1_000;
+                let mut time_ms = Instant::now().as_micros() / 1_000;
 
                 let minutes = time_ms / 60_000;
                 time_ms -= minutes * 60_000;
@@ -60,7 +60,7 @@ pub fn main() {
                 let _ = writeln!(uart, "[{:02}:{:02}.{:03}] DMA interrupted: {}", minutes, seconds, time_ms, INTERRUPTED.load(Ordering::Relaxed));
 
                 // Callback again in 1 second.
-                Some(1_000_000)
+                Some(Duration::from_secs(1))
 
             });
 